@@ -1,5 +1,7 @@
 use gwl::window::{DefWindowBuildAction, WindowBuilder, WindowEvent};
 
+// The key match is left for trying out key handling
+#[allow(clippy::match_single_binding)]
 fn main() {
     let mut action = DefWindowBuildAction;
     let window = WindowBuilder::new(Box::new(&mut action))
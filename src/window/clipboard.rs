@@ -0,0 +1,151 @@
+//! Copy and paste through the CLIPBOARD selection on X11 and wl_data_device on Wayland,
+//! and the primary selection (middle click paste) through PRIMARY on X11 and
+//! zwp_primary_selection_device_manager_v1 on Wayland.
+//! Contents are offered in one or more MIME types, e.g. text/html with a plain text fallback.
+
+use crate::window::{RawWindow, Window};
+use std::fmt;
+
+/// Names under which text is offered, in the order they are preferred when reading.
+/// X11 clients use the target atoms instead of MIME types, so both are offered.
+pub(crate) const TEXT_MIME_TYPES: [&str; 5] = [
+    "text/plain;charset=utf-8",
+    "UTF8_STRING",
+    "text/plain",
+    "TEXT",
+    // Latin-1
+    "STRING",
+];
+
+/// Which of the selections a ```Clipboard``` accesses.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Selection {
+    /// Explicit copy and paste.
+    Clipboard,
+    /// The most recently selected text, pasted with the middle mouse button.
+    Primary,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClipboardError {
+    /// The backend or the display server has no clipboard.
+    NotSupported(String),
+    /// The clipboard is empty or its owner does not offer the requested type.
+    NoContent,
+    /// The display server did not let the window take the clipboard,
+    /// e.g. on Wayland when there was no input event yet.
+    Denied,
+    /// The owner of the clipboard did not answer in time.
+    Timeout,
+    /// The clipboard text is not valid UTF-8.
+    InvalidText,
+    /// Transferring the contents failed.
+    Io(String),
+}
+
+impl fmt::Display for ClipboardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClipboardError::NotSupported(what) => write!(f, "{} is not available", what),
+            ClipboardError::NoContent => write!(f, "the clipboard has no content of this type"),
+            ClipboardError::Denied => write!(f, "the display server refused the clipboard"),
+            ClipboardError::Timeout => write!(f, "the clipboard owner did not answer"),
+            ClipboardError::InvalidText => write!(f, "the clipboard text is not valid UTF-8"),
+            ClipboardError::Io(error) => write!(f, "clipboard transfer failed: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for ClipboardError {}
+
+/// The clipboard as seen by a window, returned by ```Window::clipboard```
+/// and ```Window::primary_selection```.
+/// Contents set by the window are served by its run loop and stay available until another
+/// client takes the clipboard or the window is dropped.
+pub struct Clipboard<'a> {
+    window: &'a RawWindow,
+    selection: Selection,
+}
+
+impl Clipboard<'_> {
+    /// Reads the clipboard as text, from the first text type the owner offers.
+    pub fn get_text(&self) -> Result<String, ClipboardError> {
+        let mime_types = self.mime_types()?;
+        let mime_type = TEXT_MIME_TYPES
+            .iter()
+            .find(|text| mime_types.iter().any(|mime_type| mime_type == *text))
+            .ok_or(ClipboardError::NoContent)?;
+
+        let data = self.get(mime_type)?;
+        match *mime_type {
+            "STRING" => Ok(data.iter().map(|b| *b as char).collect()),
+            _ => String::from_utf8(data).map_err(|_| ClipboardError::InvalidText),
+        }
+    }
+
+    /// Replaces the clipboard with text, offered under all text types.
+    pub fn set_text(&self, text: &str) -> Result<(), ClipboardError> {
+        let latin1 = text
+            .chars()
+            .map(|c| u8::try_from(c).unwrap_or(b'?'))
+            .collect::<Vec<u8>>();
+
+        self.set_all(
+            TEXT_MIME_TYPES
+                .iter()
+                .map(|mime_type| match *mime_type {
+                    "STRING" => (mime_type.to_string(), latin1.clone()),
+                    _ => (mime_type.to_string(), text.as_bytes().to_vec()),
+                })
+                .collect(),
+        )
+    }
+
+    /// Reads the clipboard contents of the given type, e.g. image/png.
+    pub fn get(&self, mime_type: &str) -> Result<Vec<u8>, ClipboardError> {
+        self.window.clipboard_get(self.selection, mime_type)
+    }
+
+    /// Replaces the clipboard with contents of a single type.
+    pub fn set(&self, mime_type: &str, data: Vec<u8>) -> Result<(), ClipboardError> {
+        self.set_all(vec![(mime_type.to_owned(), data)])
+    }
+
+    /// Replaces the clipboard with the same contents in several types,
+    /// e.g. text/html together with text/plain for applications without HTML support.
+    pub fn set_all(&self, contents: Vec<(String, Vec<u8>)>) -> Result<(), ClipboardError> {
+        self.window.clipboard_set(self.selection, contents)
+    }
+
+    /// Returns the types the current clipboard contents are offered in.
+    pub fn mime_types(&self) -> Result<Vec<String>, ClipboardError> {
+        self.window.clipboard_mime_types(self.selection)
+    }
+}
+
+impl Window {
+    pub fn clipboard(&self) -> Clipboard<'_> {
+        Clipboard {
+            window: &self.inner,
+            selection: Selection::Clipboard,
+        }
+    }
+
+    /// The primary selection, which holds the most recently selected text of any application.
+    pub fn primary_selection(&self) -> Clipboard<'_> {
+        Clipboard {
+            window: &self.inner,
+            selection: Selection::Primary,
+        }
+    }
+
+    /// Reports the text the user selected in the window, which becomes the primary selection.
+    /// Empty text is ignored, so that clearing the selection keeps the previous contents
+    /// available for pasting, as other applications do.
+    pub fn report_text_selection(&self, text: &str) -> Result<(), ClipboardError> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.primary_selection().set_text(text)
+    }
+}
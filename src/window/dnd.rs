@@ -0,0 +1,195 @@
+//! Files dragged from other applications and dropped onto a window, through XDND on X11 and
+//! wl_data_device on Wayland. The window receives the DragEntered, DragMoved, DragDropped and
+//! DragLeft events and decides with ```Window::accept_drag``` whether a drop is accepted.
+
+use crate::window::Window;
+#[cfg(all(target_os = "linux", not(feature = "headless")))]
+use std::ffi::OsString;
+#[cfg(all(target_os = "linux", not(feature = "headless")))]
+use std::os::unix::ffi::OsStringExt;
+#[cfg(all(target_os = "linux", not(feature = "headless")))]
+use std::path::PathBuf;
+
+/// What happens to the dragged files when they are dropped.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DropAction {
+    Copy,
+    /// The source deletes the files after the drop.
+    Move,
+    /// Not available on Wayland, where accepting a drag with it rejects the drop.
+    Link,
+}
+
+impl Window {
+    /// Accepts the drop of the files dragged over the window with the given action.
+    /// Drags are rejected until this is called, usually on DragEntered, and the answer
+    /// applies to all positions until it is changed on DragMoved.
+    pub fn accept_drag(&self, action: DropAction) {
+        self.inner.set_drag_action(Some(action));
+    }
+
+    /// Rejects the drop of the files dragged over the window, e.g. when they are outside of
+    /// the area that takes files.
+    pub fn reject_drag(&self) {
+        self.inner.set_drag_action(None);
+    }
+
+    /// Returns the actions the source of the current drag allows, so that the callback can
+    /// choose one of them. Empty when nothing is dragged over the window.
+    pub fn drag_source_actions(&self) -> Vec<DropAction> {
+        self.inner.drag_source_actions()
+    }
+}
+
+/// Parses the file URIs of a text/uri-list (RFC 2483) into paths.
+/// Comments, URIs of other schemes and files on other hosts are skipped.
+#[cfg(all(target_os = "linux", not(feature = "headless")))]
+pub(crate) fn parse_uri_list(data: &[u8]) -> Vec<PathBuf> {
+    parse_uri_list_on_host(data, &hostname())
+}
+
+#[cfg(all(target_os = "linux", not(feature = "headless")))]
+fn parse_uri_list_on_host(data: &[u8], hostname: &[u8]) -> Vec<PathBuf> {
+    data.split(|b| *b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| !line.is_empty() && !line.starts_with(b"#"))
+        .filter_map(|uri| {
+            let path = uri.strip_prefix(b"file:")?;
+            // file:///path, file://localhost/path, file://<hostname>/path (RFC 8089)
+            // and the file:/path of some file managers
+            let path = match path.strip_prefix(b"//") {
+                Some(authority) => {
+                    let start = authority.iter().position(|b| *b == b'/')?;
+                    match &authority[..start] {
+                        b"" | b"localhost" => &authority[start..],
+                        host if !hostname.is_empty() && host.eq_ignore_ascii_case(hostname) => {
+                            &authority[start..]
+                        }
+                        _ => return None,
+                    }
+                }
+                None => path,
+            };
+            Some(PathBuf::from(OsString::from_vec(percent_decode(path))))
+        })
+        .collect()
+}
+
+/// Returns the name of this host, empty when it is not known.
+#[cfg(all(target_os = "linux", not(feature = "headless")))]
+fn hostname() -> Vec<u8> {
+    // HOST_NAME_MAX is 64 on Linux
+    let mut name = [0u8; 256];
+    if unsafe { libc::gethostname(name.as_mut_ptr() as *mut libc::c_char, name.len()) } != 0 {
+        return Vec::new();
+    }
+    let len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+    name[..len].to_vec()
+}
+
+/// Replaces %XX escapes by the bytes they stand for, invalid escapes are kept.
+#[cfg(all(target_os = "linux", not(feature = "headless")))]
+fn percent_decode(data: &[u8]) -> Vec<u8> {
+    let hex = |b: u8| (b as char).to_digit(16).map(|digit| digit as u8);
+
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        match (
+            data[i],
+            data.get(i + 1).and_then(|b| hex(*b)),
+            data.get(i + 2).and_then(|b| hex(*b)),
+        ) {
+            (b'%', Some(high), Some(low)) => {
+                decoded.push(high << 4 | low);
+                i += 3;
+            }
+            (b, _, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+#[cfg(all(test, target_os = "linux", not(feature = "headless")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_uri_list_skips_comments_and_empty_lines() {
+        let uri_list = b"# dragged from a file manager\n\nfile:///tmp/a.txt\n#file:///tmp/b.txt\n";
+        assert_eq!(parse_uri_list(uri_list), [PathBuf::from("/tmp/a.txt")]);
+    }
+
+    #[test]
+    fn parse_uri_list_accepts_crlf_and_lf() {
+        let uri_list = b"file:///tmp/a.txt\r\nfile:///tmp/b.txt\nfile:///tmp/c.txt";
+        assert_eq!(
+            parse_uri_list(uri_list),
+            [
+                PathBuf::from("/tmp/a.txt"),
+                PathBuf::from("/tmp/b.txt"),
+                PathBuf::from("/tmp/c.txt"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_uri_list_accepts_local_hosts_only() {
+        let uri_list = b"file://localhost/tmp/a.txt\r\nfile:/tmp/b.txt\r\n\
+            file://other-host/tmp/c.txt\r\nfile://localhost\r\n";
+        assert_eq!(
+            parse_uri_list(uri_list),
+            [PathBuf::from("/tmp/a.txt"), PathBuf::from("/tmp/b.txt")]
+        );
+    }
+
+    #[test]
+    fn parse_uri_list_accepts_the_hostname() {
+        let uri_list = b"file://workstation/tmp/a.txt\r\nfile://WORKSTATION/tmp/b.txt\r\n\
+            file://workstation.example/tmp/c.txt\r\n";
+        assert_eq!(
+            parse_uri_list_on_host(uri_list, b"workstation"),
+            [PathBuf::from("/tmp/a.txt"), PathBuf::from("/tmp/b.txt")]
+        );
+        // An unknown hostname matches no host
+        assert!(parse_uri_list_on_host(b"file://workstation/tmp/a.txt", b"").is_empty());
+
+        let hostname = hostname();
+        if !hostname.is_empty() {
+            let uri_list = [b"file://", &hostname[..], b"/tmp/a.txt"].concat();
+            assert_eq!(parse_uri_list(&uri_list), [PathBuf::from("/tmp/a.txt")]);
+        }
+    }
+
+    #[test]
+    fn parse_uri_list_skips_other_schemes() {
+        let uri_list = b"https://example.com/a.txt\r\nsmb://server/share/b.txt\r\n\
+            trash:///c.txt\r\n/tmp/d.txt\r\nfile:///tmp/e.txt\r\n";
+        assert_eq!(parse_uri_list(uri_list), [PathBuf::from("/tmp/e.txt")]);
+    }
+
+    #[test]
+    fn parse_uri_list_decodes_escapes() {
+        let uri_list = b"file:///tmp/a%20b%2Fc%e6%97%a5.txt\r\nfile:///tmp/%FF\r\n";
+        assert_eq!(
+            parse_uri_list(uri_list),
+            [
+                PathBuf::from("/tmp/a b/c日.txt"),
+                PathBuf::from(OsString::from_vec(b"/tmp/\xFF".to_vec())),
+            ]
+        );
+    }
+
+    #[test]
+    fn percent_decode_keeps_malformed_escapes() {
+        assert_eq!(percent_decode(b"100%"), b"100%");
+        assert_eq!(percent_decode(b"%4"), b"%4");
+        assert_eq!(percent_decode(b"%zz%4g"), b"%zz%4g");
+        assert_eq!(percent_decode(b"%%41"), b"%A");
+        assert_eq!(percent_decode(b"%41%"), b"A%");
+    }
+}
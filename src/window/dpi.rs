@@ -0,0 +1,378 @@
+//! Sizes and positions in physical pixels and in logical (scale independent) units.
+//! Logical units are converted to physical pixels by multiplying them by the scale factor.
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PhysicalSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PhysicalSize {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+
+    pub fn to_logical(&self, scale_factor: f64) -> LogicalSize {
+        LogicalSize {
+            width: self.width as f64 / scale_factor,
+            height: self.height as f64 / scale_factor,
+        }
+    }
+}
+
+impl From<(u32, u32)> for PhysicalSize {
+    fn from((width, height): (u32, u32)) -> Self {
+        Self::new(width, height)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct LogicalSize {
+    pub width: f64,
+    pub height: f64,
+}
+
+impl LogicalSize {
+    pub fn new(width: f64, height: f64) -> Self {
+        Self { width, height }
+    }
+
+    pub fn to_physical(&self, scale_factor: f64) -> PhysicalSize {
+        PhysicalSize {
+            width: (self.width * scale_factor).round() as u32,
+            height: (self.height * scale_factor).round() as u32,
+        }
+    }
+}
+
+impl From<(f64, f64)> for LogicalSize {
+    fn from((width, height): (f64, f64)) -> Self {
+        Self::new(width, height)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PhysicalPosition {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl PhysicalPosition {
+    pub fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    pub fn to_logical(&self, scale_factor: f64) -> LogicalPosition {
+        LogicalPosition {
+            x: self.x as f64 / scale_factor,
+            y: self.y as f64 / scale_factor,
+        }
+    }
+}
+
+impl From<(i32, i32)> for PhysicalPosition {
+    fn from((x, y): (i32, i32)) -> Self {
+        Self::new(x, y)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct LogicalPosition {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl LogicalPosition {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+
+    pub fn to_physical(&self, scale_factor: f64) -> PhysicalPosition {
+        PhysicalPosition {
+            x: (self.x * scale_factor).round() as i32,
+            y: (self.y * scale_factor).round() as i32,
+        }
+    }
+}
+
+impl From<(f64, f64)> for LogicalPosition {
+    fn from((x, y): (f64, f64)) -> Self {
+        Self::new(x, y)
+    }
+}
+
+/// A size in either physical pixels or logical units.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Size {
+    Physical(PhysicalSize),
+    Logical(LogicalSize),
+}
+
+impl Size {
+    pub fn to_physical(&self, scale_factor: f64) -> PhysicalSize {
+        match self {
+            Size::Physical(size) => *size,
+            Size::Logical(size) => size.to_physical(scale_factor),
+        }
+    }
+
+    pub fn to_logical(&self, scale_factor: f64) -> LogicalSize {
+        match self {
+            Size::Physical(size) => size.to_logical(scale_factor),
+            Size::Logical(size) => *size,
+        }
+    }
+}
+
+impl From<PhysicalSize> for Size {
+    fn from(size: PhysicalSize) -> Self {
+        Size::Physical(size)
+    }
+}
+
+impl From<LogicalSize> for Size {
+    fn from(size: LogicalSize) -> Self {
+        Size::Logical(size)
+    }
+}
+
+/// A position in either physical pixels or logical units.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Position {
+    Physical(PhysicalPosition),
+    Logical(LogicalPosition),
+}
+
+impl Position {
+    pub fn to_physical(&self, scale_factor: f64) -> PhysicalPosition {
+        match self {
+            Position::Physical(position) => *position,
+            Position::Logical(position) => position.to_physical(scale_factor),
+        }
+    }
+
+    pub fn to_logical(&self, scale_factor: f64) -> LogicalPosition {
+        match self {
+            Position::Physical(position) => position.to_logical(scale_factor),
+            Position::Logical(position) => *position,
+        }
+    }
+}
+
+impl From<PhysicalPosition> for Position {
+    fn from(position: PhysicalPosition) -> Self {
+        Position::Physical(position)
+    }
+}
+
+impl From<LogicalPosition> for Position {
+    fn from(position: LogicalPosition) -> Self {
+        Position::Logical(position)
+    }
+}
+
+/// A rectangle in physical pixels.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Returns the overlapping part of both rectangles, or None when they do not overlap.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let left = self.x.max(other.x);
+        let top = self.y.max(other.y);
+        let right = (self.x + self.width as i32).min(other.x + other.width as i32);
+        let bottom = (self.y + self.height as i32).min(other.y + other.height as i32);
+
+        if left < right && top < bottom {
+            Some(Rect::new(
+                left,
+                top,
+                (right - left) as u32,
+                (bottom - top) as u32,
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Finds Xft/DPI (stored as DPI * 1024) in the XSETTINGS wire format.
+#[cfg(all(
+    target_os = "linux",
+    not(any(feature = "wayland", feature = "headless"))
+))]
+pub(crate) fn parse_xsettings_dpi(data: &[u8]) -> Option<f64> {
+    let big_endian = *data.first()? == 1;
+    let read_u16 = |at: usize| {
+        let bytes = [*data.get(at)?, *data.get(at + 1)?];
+        Some(match big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    };
+    let read_u32 = |at: usize| {
+        let bytes = [
+            *data.get(at)?,
+            *data.get(at + 1)?,
+            *data.get(at + 2)?,
+            *data.get(at + 3)?,
+        ];
+        Some(match big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    };
+    let pad = |len: usize| (len + 3) & !3;
+
+    // byte order, padding, serial, number of settings
+    let count = read_u32(8)?;
+    let mut at = 12;
+    for _ in 0..count {
+        let kind = *data.get(at)?;
+        let name_len = read_u16(at + 2)? as usize;
+        let name = data.get(at + 4..at + 4 + name_len)?;
+        // name and last change serial
+        at += 4 + pad(name_len) + 4;
+
+        match kind {
+            // integer
+            0 => {
+                if name == b"Xft/DPI" {
+                    return Some(read_u32(at)? as i32 as f64 / 1024.0);
+                }
+                at += 4;
+            }
+            // string
+            1 => at += 4 + pad(read_u32(at)? as usize),
+            // color
+            2 => at += 8,
+            _ => return None,
+        }
+    }
+
+    None
+}
+
+/// Guesses the scale factor from the pixel density of the monitor.
+#[cfg(all(
+    target_os = "linux",
+    not(any(feature = "wayland", feature = "headless"))
+))]
+pub(crate) fn dpi_scale_factor(
+    (width, height): (u32, u32),
+    (width_mm, height_mm): (u32, u32),
+) -> f64 {
+    if width_mm == 0 || height_mm == 0 {
+        return 1.0;
+    }
+    let pixels_per_mm =
+        ((width as f64 * height as f64) / (width_mm as f64 * height_mm as f64)).sqrt();
+    // Rounded to twelfths, 96 DPI is 1.0
+    ((pixels_per_mm * (12.0 * 25.4 / 96.0)).round() / 12.0).max(1.0)
+}
+
+#[cfg(all(
+    test,
+    target_os = "linux",
+    not(any(feature = "wayland", feature = "headless"))
+))]
+mod tests {
+    use super::*;
+
+    /// XSETTINGS data with a string, a color and the Xft/DPI integer setting.
+    fn xsettings(big_endian: bool, dpi: i32) -> Vec<u8> {
+        let u16_bytes = |value: u16| match big_endian {
+            true => value.to_be_bytes(),
+            false => value.to_le_bytes(),
+        };
+        let u32_bytes = |value: u32| match big_endian {
+            true => value.to_be_bytes(),
+            false => value.to_le_bytes(),
+        };
+        let setting = |data: &mut Vec<u8>, kind: u8, name: &[u8]| {
+            data.extend([kind, 0]);
+            data.extend(u16_bytes(name.len() as u16));
+            data.extend(name);
+            data.resize((data.len() + 3) & !3, 0);
+            data.extend(u32_bytes(0));
+        };
+
+        let mut data = vec![big_endian as u8, 0, 0, 0];
+        data.extend(u32_bytes(7));
+        data.extend(u32_bytes(3));
+
+        setting(&mut data, 1, b"Net/ThemeName");
+        data.extend(u32_bytes(7));
+        data.extend(b"Adwaita\0");
+        setting(&mut data, 2, b"Gtk/Color");
+        data.extend([0; 8]);
+        setting(&mut data, 0, b"Xft/DPI");
+        data.extend(u32_bytes(dpi as u32));
+        data
+    }
+
+    #[test]
+    fn parse_xsettings_dpi_in_both_byte_orders() {
+        for big_endian in [false, true] {
+            let data = xsettings(big_endian, 144 * 1024);
+            assert_eq!(parse_xsettings_dpi(&data), Some(144.0));
+        }
+    }
+
+    #[test]
+    fn parse_xsettings_dpi_keeps_invalid_values() {
+        // Rejected by the caller, which falls back to Xft.dpi
+        let data = xsettings(false, -1024);
+        assert_eq!(parse_xsettings_dpi(&data), Some(-1.0));
+    }
+
+    #[test]
+    fn parse_xsettings_dpi_rejects_truncated_data() {
+        let data = xsettings(false, 96 * 1024);
+        for len in 0..data.len() {
+            assert_eq!(parse_xsettings_dpi(&data[..len]), None);
+        }
+    }
+
+    #[test]
+    fn parse_xsettings_dpi_without_dpi() {
+        let mut data = xsettings(true, 96 * 1024);
+        // Only the first two settings
+        data[11] = 2;
+        assert_eq!(parse_xsettings_dpi(&data), None);
+
+        let mut data = xsettings(true, 96 * 1024);
+        // Unknown setting type
+        data[12] = 3;
+        assert_eq!(parse_xsettings_dpi(&data), None);
+    }
+
+    #[test]
+    fn dpi_scale_factor_from_monitor_size() {
+        // 24" 1080p, 92 DPI
+        assert_eq!(dpi_scale_factor((1920, 1080), (531, 299)), 1.0);
+        // 27" 4K, 163 DPI
+        assert_eq!(dpi_scale_factor((3840, 2160), (597, 336)), 20.0 / 12.0);
+        // 13.3" 2560x1600, 227 DPI
+        assert_eq!(dpi_scale_factor((2560, 1600), (286, 179)), 28.0 / 12.0);
+        // Projectors and TVs report no or a small physical size
+        assert_eq!(dpi_scale_factor((1920, 1080), (0, 0)), 1.0);
+        assert_eq!(dpi_scale_factor((1920, 1080), (1600, 900)), 1.0);
+    }
+}
@@ -0,0 +1,313 @@
+//! Minimal EGL bindings and the context shared by the Wayland, the XCB and the headless GL
+//! contexts.
+
+#![allow(
+    non_camel_case_types,
+    non_upper_case_globals,
+    clippy::upper_case_acronyms
+)]
+
+use crate::window::{GlAttributes, GlError, GlProfile, PhysicalSize};
+use libloading::Library;
+use once_cell::sync::Lazy;
+use std::ffi::{c_char, c_void, CString};
+use std::ptr::{null, null_mut};
+
+pub(crate) type EGLDisplay = *mut c_void;
+pub(crate) type EGLConfig = *mut c_void;
+pub(crate) type EGLContext = *mut c_void;
+pub(crate) type EGLSurface = *mut c_void;
+pub(crate) type EGLint = i32;
+pub(crate) type EGLenum = u32;
+pub(crate) type EGLBoolean = u32;
+pub(crate) type EGLAttrib = isize;
+
+pub(crate) const EGL_NONE: EGLint = 0x3038;
+pub(crate) const EGL_TRUE: EGLint = 1;
+pub(crate) const EGL_BLUE_SIZE: EGLint = 0x3022;
+pub(crate) const EGL_GREEN_SIZE: EGLint = 0x3023;
+pub(crate) const EGL_RED_SIZE: EGLint = 0x3024;
+pub(crate) const EGL_DEPTH_SIZE: EGLint = 0x3025;
+pub(crate) const EGL_STENCIL_SIZE: EGLint = 0x3026;
+pub(crate) const EGL_SAMPLES: EGLint = 0x3031;
+pub(crate) const EGL_SAMPLE_BUFFERS: EGLint = 0x3032;
+pub(crate) const EGL_SURFACE_TYPE: EGLint = 0x3033;
+pub(crate) const EGL_RENDERABLE_TYPE: EGLint = 0x3040;
+pub(crate) const EGL_HEIGHT: EGLint = 0x3056;
+pub(crate) const EGL_WIDTH: EGLint = 0x3057;
+pub(crate) const EGL_PBUFFER_BIT: EGLint = 0x0001;
+#[cfg(all(any(feature = "xcb", feature = "wayland"), not(feature = "headless")))]
+pub(crate) const EGL_WINDOW_BIT: EGLint = 0x0004;
+pub(crate) const EGL_OPENGL_BIT: EGLint = 0x0008;
+pub(crate) const EGL_OPENGL_API: EGLenum = 0x30A2;
+pub(crate) const EGL_GL_COLORSPACE: EGLint = 0x309D;
+pub(crate) const EGL_GL_COLORSPACE_SRGB: EGLint = 0x3089;
+pub(crate) const EGL_CONTEXT_MAJOR_VERSION: EGLint = 0x3098;
+pub(crate) const EGL_CONTEXT_MINOR_VERSION: EGLint = 0x30FB;
+pub(crate) const EGL_CONTEXT_OPENGL_PROFILE_MASK: EGLint = 0x30FD;
+pub(crate) const EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT: EGLint = 0x0001;
+pub(crate) const EGL_CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT: EGLint = 0x0002;
+pub(crate) const EGL_CONTEXT_OPENGL_DEBUG: EGLint = 0x31B0;
+#[cfg(all(feature = "wayland", not(feature = "headless")))]
+pub(crate) const EGL_PLATFORM_WAYLAND_KHR: EGLenum = 0x31D8;
+#[cfg(all(feature = "xcb", not(any(feature = "wayland", feature = "headless"))))]
+pub(crate) const EGL_PLATFORM_XCB_EXT: EGLenum = 0x31DC;
+pub(crate) const EGL_PLATFORM_SURFACELESS_MESA: EGLenum = 0x31DD;
+
+/// The EGL functions used by the contexts, loaded from libEGL at runtime so that applications
+/// start without it and only context creation fails.
+pub(crate) struct Egl {
+    pub get_platform_display:
+        unsafe extern "C" fn(EGLenum, *mut c_void, *const EGLAttrib) -> EGLDisplay,
+    pub initialize: unsafe extern "C" fn(EGLDisplay, *mut EGLint, *mut EGLint) -> EGLBoolean,
+    pub bind_api: unsafe extern "C" fn(EGLenum) -> EGLBoolean,
+    pub choose_config: unsafe extern "C" fn(
+        EGLDisplay,
+        *const EGLint,
+        *mut EGLConfig,
+        EGLint,
+        *mut EGLint,
+    ) -> EGLBoolean,
+    pub create_context:
+        unsafe extern "C" fn(EGLDisplay, EGLConfig, EGLContext, *const EGLint) -> EGLContext,
+    pub destroy_context: unsafe extern "C" fn(EGLDisplay, EGLContext) -> EGLBoolean,
+    #[cfg(all(any(feature = "xcb", feature = "wayland"), not(feature = "headless")))]
+    pub create_window_surface:
+        unsafe extern "C" fn(EGLDisplay, EGLConfig, *mut c_void, *const EGLint) -> EGLSurface,
+    pub create_pbuffer_surface:
+        unsafe extern "C" fn(EGLDisplay, EGLConfig, *const EGLint) -> EGLSurface,
+    pub destroy_surface: unsafe extern "C" fn(EGLDisplay, EGLSurface) -> EGLBoolean,
+    pub query_surface:
+        unsafe extern "C" fn(EGLDisplay, EGLSurface, EGLint, *mut EGLint) -> EGLBoolean,
+    pub make_current:
+        unsafe extern "C" fn(EGLDisplay, EGLSurface, EGLSurface, EGLContext) -> EGLBoolean,
+    pub get_current_context: unsafe extern "C" fn() -> EGLContext,
+    pub swap_buffers: unsafe extern "C" fn(EGLDisplay, EGLSurface) -> EGLBoolean,
+    pub swap_interval: unsafe extern "C" fn(EGLDisplay, EGLint) -> EGLBoolean,
+    pub get_proc_address: unsafe extern "C" fn(*const c_char) -> *const c_void,
+    pub get_error: unsafe extern "C" fn() -> EGLint,
+    _library: Library,
+}
+
+/// libEGL, None when it is not installed.
+pub(crate) static EGL: Lazy<Option<Egl>> = Lazy::new(|| unsafe {
+    let library = ["libEGL.so.1", "libEGL.so"]
+        .iter()
+        .find_map(|name| Library::new(name).ok())?;
+    Some(Egl {
+        get_platform_display: *library.get(b"eglGetPlatformDisplay\0").ok()?,
+        initialize: *library.get(b"eglInitialize\0").ok()?,
+        bind_api: *library.get(b"eglBindAPI\0").ok()?,
+        choose_config: *library.get(b"eglChooseConfig\0").ok()?,
+        create_context: *library.get(b"eglCreateContext\0").ok()?,
+        destroy_context: *library.get(b"eglDestroyContext\0").ok()?,
+        #[cfg(all(any(feature = "xcb", feature = "wayland"), not(feature = "headless")))]
+        create_window_surface: *library.get(b"eglCreateWindowSurface\0").ok()?,
+        create_pbuffer_surface: *library.get(b"eglCreatePbufferSurface\0").ok()?,
+        destroy_surface: *library.get(b"eglDestroySurface\0").ok()?,
+        query_surface: *library.get(b"eglQuerySurface\0").ok()?,
+        make_current: *library.get(b"eglMakeCurrent\0").ok()?,
+        get_current_context: *library.get(b"eglGetCurrentContext\0").ok()?,
+        swap_buffers: *library.get(b"eglSwapBuffers\0").ok()?,
+        swap_interval: *library.get(b"eglSwapInterval\0").ok()?,
+        get_proc_address: *library.get(b"eglGetProcAddress\0").ok()?,
+        get_error: *library.get(b"eglGetError\0").ok()?,
+        _library: library,
+    })
+});
+
+/// An EGL context together with the surface it renders into.
+pub(crate) struct EglContext {
+    egl: &'static Egl,
+    display: EGLDisplay,
+    context: EGLContext,
+    surface: EGLSurface,
+}
+
+impl EglContext {
+    /// Creates a context on the display of the given platform.
+    /// ```create_surface``` receives libEGL, the chosen config and the surface attributes
+    /// (terminated with EGL_NONE) and returns the surface, or null when it failed.
+    pub(crate) unsafe fn new(
+        platform: EGLenum,
+        native_display: *mut c_void,
+        attributes: &GlAttributes,
+        surface_type: EGLint,
+        create_surface: impl FnOnce(&Egl, EGLDisplay, EGLConfig, &[EGLint]) -> EGLSurface,
+    ) -> Result<Self, GlError> {
+        let egl = EGL
+            .as_ref()
+            .ok_or_else(|| GlError::NotSupported("EGL (libEGL is not installed)".to_owned()))?;
+
+        let display = (egl.get_platform_display)(platform, native_display, null());
+        if display.is_null() || (egl.initialize)(display, null_mut(), null_mut()) == 0 {
+            return Err(GlError::NotSupported(format!(
+                "EGL platform 0x{:x}",
+                platform
+            )));
+        }
+        if (egl.bind_api)(EGL_OPENGL_API) == 0 {
+            return Err(GlError::NotSupported("OpenGL on EGL".to_owned()));
+        }
+
+        let mut config_attributes = vec![
+            EGL_SURFACE_TYPE,
+            surface_type,
+            EGL_RENDERABLE_TYPE,
+            EGL_OPENGL_BIT,
+            EGL_RED_SIZE,
+            8,
+            EGL_GREEN_SIZE,
+            8,
+            EGL_BLUE_SIZE,
+            8,
+            EGL_DEPTH_SIZE,
+            attributes.depth_bits as EGLint,
+            EGL_STENCIL_SIZE,
+            attributes.stencil_bits as EGLint,
+        ];
+        if attributes.samples > 0 {
+            let samples = attributes.samples as EGLint;
+            config_attributes.extend([EGL_SAMPLE_BUFFERS, 1, EGL_SAMPLES, samples]);
+        }
+        config_attributes.push(EGL_NONE);
+
+        let mut config = null_mut();
+        let mut count = 0;
+        (egl.choose_config)(
+            display,
+            config_attributes.as_ptr(),
+            &mut config,
+            1,
+            &mut count,
+        );
+        if count == 0 {
+            return Err(GlError::NoMatchingConfig);
+        }
+
+        let profile = match attributes.profile {
+            GlProfile::Core => EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT,
+            GlProfile::Compatibility => EGL_CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT,
+        };
+        let mut context_attributes = vec![
+            EGL_CONTEXT_MAJOR_VERSION,
+            attributes.version.0 as EGLint,
+            EGL_CONTEXT_MINOR_VERSION,
+            attributes.version.1 as EGLint,
+            EGL_CONTEXT_OPENGL_PROFILE_MASK,
+            profile,
+        ];
+        if attributes.debug {
+            context_attributes.extend([EGL_CONTEXT_OPENGL_DEBUG, EGL_TRUE]);
+        }
+        context_attributes.push(EGL_NONE);
+
+        let context =
+            (egl.create_context)(display, config, null_mut(), context_attributes.as_ptr());
+        if context.is_null() {
+            return Err(GlError::CreationFailed(format!(
+                "OpenGL {}.{} (EGL error 0x{:x})",
+                attributes.version.0,
+                attributes.version.1,
+                (egl.get_error)()
+            )));
+        }
+
+        let surface_attributes = match attributes.srgb {
+            true => vec![EGL_GL_COLORSPACE, EGL_GL_COLORSPACE_SRGB, EGL_NONE],
+            false => vec![EGL_NONE],
+        };
+        let surface = create_surface(egl, display, config, &surface_attributes);
+        if surface.is_null() {
+            let error = (egl.get_error)();
+            (egl.destroy_context)(display, context);
+            return Err(GlError::CreationFailed(format!(
+                "surface (EGL error 0x{:x})",
+                error
+            )));
+        }
+
+        Ok(Self {
+            egl,
+            display,
+            context,
+            surface,
+        })
+    }
+
+    /// Creates a context rendering into a pbuffer on the surfaceless platform of Mesa,
+    /// which needs neither a display server nor a GPU.
+    pub(crate) fn new_headless(
+        size: PhysicalSize,
+        attributes: &GlAttributes,
+    ) -> Result<Self, GlError> {
+        unsafe {
+            Self::new(
+                EGL_PLATFORM_SURFACELESS_MESA,
+                null_mut(),
+                attributes,
+                EGL_PBUFFER_BIT,
+                |egl, display, config, surface_attributes| {
+                    let mut pbuffer_attributes = vec![
+                        EGL_WIDTH,
+                        size.width as EGLint,
+                        EGL_HEIGHT,
+                        size.height as EGLint,
+                    ];
+                    pbuffer_attributes.extend_from_slice(surface_attributes);
+                    (egl.create_pbuffer_surface)(display, config, pbuffer_attributes.as_ptr())
+                },
+            )
+        }
+    }
+
+    pub(crate) fn make_current(&self) -> Result<(), GlError> {
+        unsafe {
+            match (self.egl.make_current)(self.display, self.surface, self.surface, self.context) {
+                0 => Err(GlError::MakeCurrentFailed),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    pub(crate) fn swap_buffers(&self) {
+        unsafe {
+            (self.egl.swap_buffers)(self.display, self.surface);
+        }
+    }
+
+    pub(crate) fn set_swap_interval(&self, interval: i32) {
+        unsafe {
+            (self.egl.swap_interval)(self.display, interval);
+        }
+    }
+
+    pub(crate) fn size(&self) -> PhysicalSize {
+        let (mut width, mut height) = (0, 0);
+        unsafe {
+            (self.egl.query_surface)(self.display, self.surface, EGL_WIDTH, &mut width);
+            (self.egl.query_surface)(self.display, self.surface, EGL_HEIGHT, &mut height);
+        }
+        PhysicalSize::new(width as u32, height as u32)
+    }
+
+    pub(crate) fn get_proc_address(&self, name: &str) -> *const c_void {
+        match CString::new(name) {
+            Ok(name) => unsafe { (self.egl.get_proc_address)(name.as_ptr()) },
+            Err(_) => null(),
+        }
+    }
+}
+
+impl Drop for EglContext {
+    fn drop(&mut self) {
+        unsafe {
+            if (self.egl.get_current_context)() == self.context {
+                (self.egl.make_current)(self.display, null_mut(), null_mut(), null_mut());
+            }
+            (self.egl.destroy_surface)(self.display, self.surface);
+            (self.egl.destroy_context)(self.display, self.context);
+            // The display is not terminated, it is shared with the other contexts of the process
+        }
+    }
+}
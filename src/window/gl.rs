@@ -0,0 +1,331 @@
+//! OpenGL contexts rendering into a window, created with GLX on X11 and EGL on Wayland,
+//! and headless contexts rendering into an offscreen buffer.
+
+#[cfg(target_os = "linux")]
+use crate::window::egl::EglContext;
+use crate::window::{PhysicalSize, RawGlContext, RawWindow, Window};
+use std::ffi::c_void;
+use std::fmt;
+use std::marker::PhantomData;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GlError {
+    /// The platform or the display server does not provide the required API or extension.
+    NotSupported(String),
+    /// No framebuffer configuration matches the requested attributes.
+    NoMatchingConfig,
+    /// The driver refused to create the context, e.g. because the version is not supported.
+    CreationFailed(String),
+    /// The context could not be made current.
+    MakeCurrentFailed,
+}
+
+impl fmt::Display for GlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GlError::NotSupported(what) => write!(f, "{} is not supported", what),
+            GlError::NoMatchingConfig => {
+                write!(f, "no framebuffer configuration matches the attributes")
+            }
+            GlError::CreationFailed(message) => {
+                write!(f, "failed to create the context: {}", message)
+            }
+            GlError::MakeCurrentFailed => write!(f, "failed to make the context current"),
+        }
+    }
+}
+
+impl std::error::Error for GlError {}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum GlProfile {
+    #[default]
+    Core,
+    /// Also provides the functions removed from the core profile.
+    Compatibility,
+}
+
+/// Attributes of a context and of the framebuffer it renders into.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlAttributes {
+    pub(crate) version: (u8, u8),
+    pub(crate) profile: GlProfile,
+    pub(crate) debug: bool,
+    pub(crate) srgb: bool,
+    pub(crate) samples: u8,
+    pub(crate) depth_bits: u8,
+    pub(crate) stencil_bits: u8,
+}
+
+pub trait IGlContext: Sized {
+    fn new(window: &RawWindow, attributes: &GlAttributes) -> Result<Self, GlError>;
+
+    fn make_current(&self) -> Result<(), GlError>;
+
+    fn swap_buffers(&self);
+
+    fn set_swap_interval(&self, interval: i32);
+
+    fn get_proc_address(&self, name: &str) -> *const c_void;
+
+    fn resize(&self, size: PhysicalSize);
+
+    fn size(&self) -> PhysicalSize;
+}
+
+const GL_RGBA: u32 = 0x1908;
+const GL_UNSIGNED_BYTE: u32 = 0x1401;
+
+type GlReadPixels = unsafe extern "system" fn(i32, i32, i32, i32, u32, u32, *mut c_void);
+
+/// An OpenGL context with a double-buffered framebuffer in a window,
+/// or with an offscreen framebuffer when it is headless.
+/// A window context borrows the window, whose display connection it uses.
+pub struct GlContext<'a> {
+    inner: GlContextInner,
+    _window: PhantomData<&'a Window>,
+}
+
+enum GlContextInner {
+    Window(RawGlContext),
+    #[cfg(target_os = "linux")]
+    Headless(EglContext),
+}
+
+impl GlContext<'_> {
+    /// Makes the context current on the calling thread, so that GL calls render into its framebuffer.
+    pub fn make_current(&self) -> Result<(), GlError> {
+        match &self.inner {
+            GlContextInner::Window(context) => context.make_current(),
+            #[cfg(target_os = "linux")]
+            GlContextInner::Headless(context) => context.make_current(),
+        }
+    }
+
+    /// Shows the back buffer in the window. Does nothing for headless contexts.
+    pub fn swap_buffers(&self) {
+        match &self.inner {
+            GlContextInner::Window(context) => context.swap_buffers(),
+            #[cfg(target_os = "linux")]
+            GlContextInner::Headless(context) => context.swap_buffers(),
+        }
+    }
+
+    /// Sets the number of vertical blanks swap_buffers waits for, 0 disables vsync.
+    /// The context has to be current.
+    pub fn set_swap_interval(&self, interval: i32) {
+        match &self.inner {
+            GlContextInner::Window(context) => context.set_swap_interval(interval),
+            #[cfg(target_os = "linux")]
+            GlContextInner::Headless(context) => context.set_swap_interval(interval),
+        }
+    }
+
+    /// Returns the address of a GL function (e.g. for ```gl::load_with```), or null when it is unknown.
+    pub fn get_proc_address(&self, name: &str) -> *const c_void {
+        match &self.inner {
+            GlContextInner::Window(context) => context.get_proc_address(name),
+            #[cfg(target_os = "linux")]
+            GlContextInner::Headless(context) => context.get_proc_address(name),
+        }
+    }
+
+    /// Resizes the framebuffer. Has to be called when the window was resized on Wayland,
+    /// on X11 the framebuffer follows the window. The size of headless contexts is fixed.
+    pub fn resize(&self, size: PhysicalSize) {
+        match &self.inner {
+            GlContextInner::Window(context) => context.resize(size),
+            #[cfg(target_os = "linux")]
+            GlContextInner::Headless(_) => {}
+        }
+    }
+
+    /// Size of the framebuffer in pixels.
+    pub fn size(&self) -> PhysicalSize {
+        match &self.inner {
+            GlContextInner::Window(context) => context.size(),
+            #[cfg(target_os = "linux")]
+            GlContextInner::Headless(context) => context.size(),
+        }
+    }
+
+    /// Reads the framebuffer as non-premultiplied RGBA, row by row from the top-left corner.
+    /// The context has to be current.
+    pub fn read_pixels(&self) -> Vec<u8> {
+        let size = self.size();
+        let read_pixels = self.get_proc_address("glReadPixels");
+        if read_pixels.is_null() {
+            return Vec::new();
+        }
+
+        let stride = size.width as usize * 4;
+        let mut pixels = vec![0; stride * size.height as usize];
+        unsafe {
+            let read_pixels: GlReadPixels = std::mem::transmute(read_pixels);
+            read_pixels(
+                0,
+                0,
+                size.width as i32,
+                size.height as i32,
+                GL_RGBA,
+                GL_UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut c_void,
+            );
+        }
+
+        // OpenGL returns the bottom row first
+        pixels
+            .chunks_exact(stride)
+            .rev()
+            .flatten()
+            .copied()
+            .collect()
+    }
+}
+
+pub struct GlContextBuilder {
+    attributes: GlAttributes,
+}
+
+impl Default for GlContextBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GlContextBuilder {
+    /// Starts with an OpenGL 3.3 core context, a 24 bit depth buffer and an 8 bit stencil buffer.
+    pub fn new() -> Self {
+        Self {
+            attributes: GlAttributes {
+                version: (3, 3),
+                profile: GlProfile::Core,
+                debug: false,
+                srgb: false,
+                samples: 0,
+                depth_bits: 24,
+                stencil_bits: 8,
+            },
+        }
+    }
+
+    pub fn version(mut self, major: u8, minor: u8) -> Self {
+        self.attributes.version = (major, minor);
+        self
+    }
+
+    /// Only used for OpenGL 3.2 and later.
+    pub fn profile(mut self, profile: GlProfile) -> Self {
+        self.attributes.profile = profile;
+        self
+    }
+
+    /// Creates a debug context, which reports errors and performance hints through KHR_debug.
+    pub fn debug(mut self, b: bool) -> Self {
+        self.attributes.debug = b;
+        self
+    }
+
+    /// Requests a framebuffer that converts linear colors to sRGB (GL_FRAMEBUFFER_SRGB).
+    pub fn srgb(mut self, b: bool) -> Self {
+        self.attributes.srgb = b;
+        self
+    }
+
+    /// Sets the number of samples per pixel for multisampling, 0 disables it.
+    pub fn samples(mut self, samples: u8) -> Self {
+        self.attributes.samples = samples;
+        self
+    }
+
+    pub fn depth_bits(mut self, bits: u8) -> Self {
+        self.attributes.depth_bits = bits;
+        self
+    }
+
+    pub fn stencil_bits(mut self, bits: u8) -> Self {
+        self.attributes.stencil_bits = bits;
+        self
+    }
+
+    pub fn build<'a>(&self, window: &'a Window) -> Result<GlContext<'a>, GlError> {
+        Ok(GlContext {
+            inner: GlContextInner::Window(RawGlContext::new(&window.inner, &self.attributes)?),
+            _window: PhantomData,
+        })
+    }
+
+    /// Creates a context rendering into an offscreen framebuffer of the given size,
+    /// e.g. for render tests. It needs neither a display server nor a GPU, but Mesa's
+    /// surfaceless EGL platform, so it is only available on Linux.
+    pub fn build_headless(&self, size: PhysicalSize) -> Result<GlContext<'static>, GlError> {
+        #[cfg(target_os = "linux")]
+        {
+            Ok(GlContext {
+                inner: GlContextInner::Headless(EglContext::new_headless(size, &self.attributes)?),
+                _window: PhantomData,
+            })
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = size;
+            Err(GlError::NotSupported("headless contexts".to_owned()))
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    const GL_COLOR_BUFFER_BIT: u32 = 0x4000;
+    const GL_SCISSOR_TEST: u32 = 0x0C11;
+
+    type GlClearColor = unsafe extern "system" fn(f32, f32, f32, f32);
+    type GlClear = unsafe extern "system" fn(u32);
+    type GlEnable = unsafe extern "system" fn(u32);
+    type GlScissor = unsafe extern "system" fn(i32, i32, i32, i32);
+    type GlFinish = unsafe extern "system" fn();
+
+    #[test]
+    fn read_pixels_of_headless_context() {
+        let context = match GlContextBuilder::new().build_headless(PhysicalSize::new(3, 2)) {
+            Ok(context) => context,
+            // Without libEGL or Mesa's surfaceless platform there is nothing to test
+            Err(GlError::NotSupported(what)) => {
+                eprintln!("skipped, {} is not supported", what);
+                return;
+            }
+            Err(error) => panic!("{}", error),
+        };
+        context.make_current().unwrap();
+        assert_eq!(context.size(), PhysicalSize::new(3, 2));
+
+        unsafe {
+            let function = |name| {
+                let address = context.get_proc_address(name);
+                assert!(!address.is_null(), "{} is missing", name);
+                address
+            };
+            let clear_color: GlClearColor = std::mem::transmute(function("glClearColor"));
+            let clear: GlClear = std::mem::transmute(function("glClear"));
+            let enable: GlEnable = std::mem::transmute(function("glEnable"));
+            let scissor: GlScissor = std::mem::transmute(function("glScissor"));
+            let finish: GlFinish = std::mem::transmute(function("glFinish"));
+
+            clear_color(1.0, 0.0, 0.0, 1.0);
+            clear(GL_COLOR_BUFFER_BIT);
+            // Rows of OpenGL start at the bottom, so this is the top row
+            enable(GL_SCISSOR_TEST);
+            scissor(0, 1, 3, 1);
+            clear_color(0.0, 0.0, 1.0, 1.0);
+            clear(GL_COLOR_BUFFER_BIT);
+            finish();
+        }
+
+        let blue = [0, 0, 255, 255];
+        let red = [255, 0, 0, 255];
+        let expected: Vec<u8> = [blue; 3].into_iter().chain([red; 3]).flatten().collect();
+        assert_eq!(context.read_pixels(), expected);
+    }
+}
@@ -0,0 +1,85 @@
+//! HasWindowHandle and HasDisplayHandle of raw-window-handle 0.6,
+//! converted from the 0.5 handles the backends provide.
+
+use crate::window::Window;
+#[cfg(not(feature = "headless"))]
+use raw_window_handle::{
+    HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
+};
+use rwh_06::{DisplayHandle, HandleError, WindowHandle};
+#[cfg(not(feature = "headless"))]
+use std::num::{NonZeroIsize, NonZeroU32};
+#[cfg(not(feature = "headless"))]
+use std::ptr::NonNull;
+
+#[cfg(not(feature = "headless"))]
+impl rwh_06::HasWindowHandle for Window {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        let raw = match self.raw_window_handle() {
+            RawWindowHandle::Xlib(handle) => {
+                let mut raw = rwh_06::XlibWindowHandle::new(handle.window);
+                raw.visual_id = handle.visual_id;
+                rwh_06::RawWindowHandle::Xlib(raw)
+            }
+            RawWindowHandle::Xcb(handle) => {
+                let window = NonZeroU32::new(handle.window).ok_or(HandleError::Unavailable)?;
+                let mut raw = rwh_06::XcbWindowHandle::new(window);
+                raw.visual_id = NonZeroU32::new(handle.visual_id);
+                rwh_06::RawWindowHandle::Xcb(raw)
+            }
+            RawWindowHandle::Wayland(handle) => {
+                // The surface is missing until the window is created
+                let surface = NonNull::new(handle.surface).ok_or(HandleError::Unavailable)?;
+                rwh_06::RawWindowHandle::Wayland(rwh_06::WaylandWindowHandle::new(surface))
+            }
+            RawWindowHandle::Win32(handle) => {
+                let hwnd =
+                    NonZeroIsize::new(handle.hwnd as isize).ok_or(HandleError::Unavailable)?;
+                let mut raw = rwh_06::Win32WindowHandle::new(hwnd);
+                raw.hinstance = NonZeroIsize::new(handle.hinstance as isize);
+                rwh_06::RawWindowHandle::Win32(raw)
+            }
+            _ => return Err(HandleError::NotSupported),
+        };
+        // The handle stays valid as long as the window is borrowed
+        Ok(unsafe { WindowHandle::borrow_raw(raw) })
+    }
+}
+
+#[cfg(not(feature = "headless"))]
+impl rwh_06::HasDisplayHandle for Window {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        let raw = match self.raw_display_handle() {
+            RawDisplayHandle::Xlib(handle) => rwh_06::RawDisplayHandle::Xlib(
+                rwh_06::XlibDisplayHandle::new(NonNull::new(handle.display), handle.screen),
+            ),
+            RawDisplayHandle::Xcb(handle) => rwh_06::RawDisplayHandle::Xcb(
+                rwh_06::XcbDisplayHandle::new(NonNull::new(handle.connection), handle.screen),
+            ),
+            RawDisplayHandle::Wayland(handle) => {
+                let display = NonNull::new(handle.display).ok_or(HandleError::Unavailable)?;
+                rwh_06::RawDisplayHandle::Wayland(rwh_06::WaylandDisplayHandle::new(display))
+            }
+            RawDisplayHandle::Windows(_) => {
+                rwh_06::RawDisplayHandle::Windows(rwh_06::WindowsDisplayHandle::new())
+            }
+            _ => return Err(HandleError::NotSupported),
+        };
+        Ok(unsafe { DisplayHandle::borrow_raw(raw) })
+    }
+}
+
+// Headless windows are not backed by any platform
+#[cfg(feature = "headless")]
+impl rwh_06::HasWindowHandle for Window {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        Err(HandleError::NotSupported)
+    }
+}
+
+#[cfg(feature = "headless")]
+impl rwh_06::HasDisplayHandle for Window {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        Err(HandleError::NotSupported)
+    }
+}
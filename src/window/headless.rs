@@ -0,0 +1,834 @@
+//! In-memory backend without a display server, selected with the headless feature,
+//! so that code written against Window and WindowEvent can be unit tested.
+//! The window only records what is requested, and run delivers the injected events.
+//! There are no platform handles: Window does not implement the raw-window-handle 0.5 traits,
+//! and HasWindowHandle and HasDisplayHandle of 0.6 return NotSupported.
+
+use crate::window::{
+    place_popup, ClipboardError, ConstraintAdjustment, ControlFlow, DropAction, Fullscreen,
+    Gravity, IWindow, Icon, ModeInfo, MonitorHandle, PhysicalPosition, PhysicalSize, PixelFormat,
+    PopupError, Position, Rect, Selection, Size, Window, WindowBuildAction, WindowEvent,
+    WindowLevel, WindowType,
+};
+#[cfg(feature = "gl")]
+use crate::window::{GlAttributes, GlError, IGlContext};
+#[cfg(feature = "vulkan")]
+use crate::window::{VkInstance, VkSurfaceKHR, VulkanError};
+use raw_window_handle::RawWindowHandle;
+use std::cell::{RefCell, RefMut};
+use std::collections::VecDeque;
+#[cfg(feature = "gl")]
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Identifies a headless window, e.g. to replace the window created by WindowBuilder.
+pub struct WindowHandle {
+    pub id: u64,
+}
+
+pub struct WindowInstance<'a> {
+    pub id: u64,
+    marker: PhantomData<&'a ()>,
+}
+
+pub struct RawWindow {
+    id: u64,
+    state: RefCell<State>,
+    /// Events delivered by the next run, in order.
+    events: RefCell<VecDeque<WindowEvent>>,
+    framebuffer: RefCell<Vec<u32>>,
+    framebuffer_size: RefCell<PhysicalSize>,
+    /// Copy of the framebuffer made by the last present.
+    presented: RefCell<Vec<u32>>,
+    /// Regions passed to present since they were last taken.
+    presented_damage: RefCell<Vec<Rect>>,
+}
+
+struct State {
+    title: String,
+    app_id: Option<(String, String)>,
+    window_type: WindowType,
+    transient_for: Option<u64>,
+    modal: bool,
+    border_width: u32,
+    decorated: bool,
+    minimized: bool,
+    maximized: bool,
+    visible: bool,
+    level: WindowLevel,
+    all_workspaces: bool,
+    workspace: u32,
+    position: PhysicalPosition,
+    size: PhysicalSize,
+    fullscreen: Option<Fullscreen>,
+    /// Position and size to restore when leaving fullscreen.
+    windowed: Option<(PhysicalPosition, PhysicalSize)>,
+    format: PixelFormat,
+    popup: bool,
+    /// Set from an injected DragEntered until the DragDropped or DragLeft that ends the drag.
+    dragging: bool,
+    drag_action: Option<DropAction>,
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Clipboard contents shared by all headless windows, by MIME type.
+static CLIPBOARD: Mutex<Vec<(String, Vec<u8>)>> = Mutex::new(Vec::new());
+static PRIMARY_SELECTION: Mutex<Vec<(String, Vec<u8>)>> = Mutex::new(Vec::new());
+
+impl IWindow<'_> for RawWindow {
+    fn new(
+        title: String,
+        size: Size,
+        position: Position,
+        border_width: u32,
+        _parent: Option<RawWindowHandle>,
+        build_action: Box<&mut dyn WindowBuildAction>,
+    ) -> Self {
+        build_action.pre_init();
+
+        let id = match build_action.override_window_handle() {
+            Some(handle) => handle.id,
+            None => {
+                let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+                build_action.window_created(&WindowInstance {
+                    id,
+                    marker: PhantomData,
+                });
+                id
+            }
+        };
+
+        let scale_factor = headless_monitor().scale_factor;
+        Self::with_state(
+            id,
+            State {
+                title,
+                app_id: None,
+                window_type: WindowType::Normal,
+                transient_for: None,
+                modal: false,
+                border_width,
+                decorated: true,
+                minimized: false,
+                maximized: false,
+                visible: false,
+                level: WindowLevel::Normal,
+                all_workspaces: false,
+                workspace: 0,
+                position: position.to_physical(scale_factor),
+                size: size.to_physical(scale_factor),
+                fullscreen: None,
+                windowed: None,
+                format: PixelFormat::default(),
+                popup: false,
+                dragging: false,
+                drag_action: None,
+            },
+        )
+    }
+
+    fn run<F>(&self, mut callback: F)
+    where
+        F: FnMut(WindowEvent, &mut ControlFlow),
+    {
+        let mut control_flow = ControlFlow::Listen;
+
+        self.show();
+
+        // The queue is not borrowed while the callback runs, so it can inject further events
+        loop {
+            let event = self.events.borrow_mut().pop_front();
+            let Some(event) = event else {
+                break;
+            };
+            let ends_drag = matches!(
+                event,
+                WindowEvent::DragDropped { .. } | WindowEvent::DragLeft
+            );
+            match event {
+                WindowEvent::PopupDone => self.state.borrow_mut().visible = false,
+                WindowEvent::DragEntered { .. } => {
+                    let mut state = self.state.borrow_mut();
+                    state.dragging = true;
+                    state.drag_action = None;
+                }
+                _ => {}
+            }
+            callback(event, &mut control_flow);
+            if ends_drag {
+                self.state.borrow_mut().dragging = false;
+            }
+
+            // Unlike the other backends, the process keeps running so that the test can continue
+            if let ControlFlow::Exit(_) = control_flow {
+                break;
+            }
+        }
+    }
+
+    fn get_instance(&self) -> WindowInstance<'_> {
+        WindowInstance {
+            id: self.id,
+            marker: PhantomData,
+        }
+    }
+
+    fn set_window_title(&self, title: &str) {
+        self.state.borrow_mut().title = title.to_owned();
+    }
+
+    fn title(&self) -> String {
+        self.state.borrow().title.clone()
+    }
+
+    fn set_window_border_width(&self, border_width: u32) {
+        self.state.borrow_mut().border_width = border_width;
+    }
+
+    fn set_app_id(&self, app_id: &str, instance_name: &str) {
+        self.state.borrow_mut().app_id = Some((app_id.to_owned(), instance_name.to_owned()));
+    }
+
+    fn set_window_type(&self, window_type: WindowType) {
+        self.state.borrow_mut().window_type = window_type;
+    }
+
+    fn set_transient_for(&self, parent: Option<&RawWindow>) {
+        self.state.borrow_mut().transient_for = parent.map(|parent| parent.id);
+    }
+
+    fn set_modal(&self, b: bool) {
+        self.state.borrow_mut().modal = b;
+    }
+
+    fn set_undecorated(&self, b: bool) {
+        self.state.borrow_mut().decorated = !b;
+    }
+
+    // There is nothing to show the icon in
+    fn set_window_icon(&self, _icon: Option<Icon>) {}
+
+    fn set_minimized(&self, b: bool) {
+        self.state.borrow_mut().minimized = b;
+    }
+
+    fn set_maximized(&self, b: bool) {
+        self.state.borrow_mut().maximized = b;
+    }
+
+    fn set_window_level(&self, level: WindowLevel) {
+        self.state.borrow_mut().level = level;
+    }
+
+    fn set_visible_on_all_workspaces(&self, b: bool) {
+        self.state.borrow_mut().all_workspaces = b;
+    }
+
+    fn set_workspace(&self, workspace: u32) {
+        self.state.borrow_mut().workspace = workspace;
+    }
+
+    fn show(&self) {
+        let mut state = self.state.borrow_mut();
+        if !state.visible {
+            // Like a display server, the whole window has to be drawn once it is shown,
+            // before the events injected so far
+            state.visible = true;
+            let size = state.size;
+            let rect = Rect::new(0, 0, size.width, size.height);
+            self.events
+                .borrow_mut()
+                .push_front(WindowEvent::Expose(rect));
+        }
+    }
+
+    fn hide(&self) {
+        self.state.borrow_mut().visible = false;
+    }
+
+    fn get_window_pos(&self) -> PhysicalPosition {
+        self.state.borrow().position
+    }
+
+    fn set_window_pos(&self, position: Position) {
+        let position = position.to_physical(self.scale_factor());
+        self.state.borrow_mut().position = position;
+    }
+
+    // There are no other windows to stack against
+    fn raise(&self) {}
+
+    fn lower(&self) {}
+
+    fn set_synchronized(&self, _b: bool) {}
+
+    fn get_window_size(&self) -> PhysicalSize {
+        self.state.borrow().size
+    }
+
+    fn scale_factor(&self) -> f64 {
+        headless_monitor().scale_factor
+    }
+
+    fn set_fullscreen(&self, fullscreen: Option<Fullscreen>) {
+        let mut state = self.state.borrow_mut();
+
+        match &fullscreen {
+            None => {
+                if let Some((position, size)) = state.windowed.take() {
+                    state.position = position;
+                    state.size = size;
+                }
+            }
+            Some(fullscreen) => {
+                let monitor = match fullscreen {
+                    Fullscreen::Borderless(Some(monitor)) => monitor.clone(),
+                    Fullscreen::Borderless(None) => headless_monitor(),
+                    Fullscreen::Exclusive(mode) => mode.monitor.clone(),
+                };
+                if state.windowed.is_none() {
+                    state.windowed = Some((state.position, state.size));
+                }
+                state.position = monitor.position.into();
+                state.size = match fullscreen {
+                    Fullscreen::Exclusive(mode) => mode.info.size.into(),
+                    Fullscreen::Borderless(_) => monitor.size.into(),
+                };
+            }
+        }
+
+        state.fullscreen = fullscreen;
+    }
+
+    fn get_fullscreen(&self) -> Option<Fullscreen> {
+        self.state.borrow().fullscreen.clone()
+    }
+
+    fn available_monitors(&self) -> Vec<MonitorHandle> {
+        vec![headless_monitor()]
+    }
+
+    fn primary_monitor(&self) -> Option<MonitorHandle> {
+        Some(headless_monitor())
+    }
+
+    fn current_monitor(&self) -> Option<MonitorHandle> {
+        Some(headless_monitor())
+    }
+
+    fn create_popup(
+        &self,
+        size: Size,
+        anchor_rect: Rect,
+        gravity: Gravity,
+        constraint_adjustment: ConstraintAdjustment,
+    ) -> Result<Self, PopupError> {
+        let parent = self.state.borrow();
+        let monitor = headless_monitor();
+
+        // Popups are placed in desktop coordinates, like override-redirect windows on X11
+        let anchor_rect = Rect::new(
+            anchor_rect.x + parent.position.x,
+            anchor_rect.y + parent.position.y,
+            anchor_rect.width,
+            anchor_rect.height,
+        );
+        let bounds = Rect::new(
+            monitor.position.0,
+            monitor.position.1,
+            monitor.size.0,
+            monitor.size.1,
+        );
+        let rect = place_popup(
+            anchor_rect,
+            gravity,
+            size.to_physical(monitor.scale_factor),
+            constraint_adjustment,
+            bounds,
+        );
+
+        Ok(Self::with_state(
+            NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            State {
+                title: String::new(),
+                app_id: None,
+                window_type: WindowType::Menu,
+                transient_for: Some(self.id),
+                modal: false,
+                border_width: 0,
+                decorated: false,
+                minimized: false,
+                maximized: false,
+                visible: false,
+                level: WindowLevel::Normal,
+                all_workspaces: false,
+                workspace: parent.workspace,
+                position: PhysicalPosition::new(rect.x, rect.y),
+                size: PhysicalSize::new(rect.width, rect.height),
+                fullscreen: None,
+                windowed: None,
+                format: parent.format,
+                popup: true,
+                dragging: false,
+                drag_action: None,
+            },
+        ))
+    }
+
+    fn framebuffer(&self) -> RefMut<'_, [u32]> {
+        let size = self.get_window_size();
+        let mut framebuffer = self.framebuffer.borrow_mut();
+        let mut framebuffer_size = self.framebuffer_size.borrow_mut();
+
+        if *framebuffer_size != size {
+            *framebuffer = vec![0; (size.width * size.height) as usize];
+            *framebuffer_size = size;
+        }
+
+        RefMut::map(framebuffer, |framebuffer| framebuffer.as_mut_slice())
+    }
+
+    fn present(&self, damage: &[Rect]) {
+        let framebuffer = self.framebuffer.borrow();
+        let size = *self.framebuffer_size.borrow();
+        let mut presented = self.presented.borrow_mut();
+
+        let bounds = Rect::new(0, 0, size.width, size.height);
+        let damage = match damage.is_empty() || presented.len() != framebuffer.len() {
+            true => vec![bounds],
+            false => damage
+                .iter()
+                .filter_map(|rect| rect.intersection(&bounds))
+                .collect(),
+        };
+
+        // Only the damaged regions are copied, like a compositor would
+        presented.resize(framebuffer.len(), 0);
+        for rect in &damage {
+            for y in rect.y..rect.y + rect.height as i32 {
+                let start = y as usize * size.width as usize + rect.x as usize;
+                let end = start + rect.width as usize;
+                presented[start..end].copy_from_slice(&framebuffer[start..end]);
+            }
+        }
+        self.presented_damage.borrow_mut().extend(damage);
+    }
+
+    fn framebuffer_formats(&self) -> Vec<PixelFormat> {
+        vec![
+            PixelFormat::Argb8888,
+            PixelFormat::Xrgb8888,
+            PixelFormat::Argb2101010,
+            PixelFormat::Xrgb2101010,
+        ]
+    }
+
+    fn set_framebuffer_format(&self, format: PixelFormat) {
+        self.state.borrow_mut().format = format;
+    }
+}
+
+impl RawWindow {
+    fn with_state(id: u64, state: State) -> Self {
+        Self {
+            id,
+            state: RefCell::new(state),
+            events: RefCell::new(VecDeque::new()),
+            framebuffer: RefCell::new(Vec::new()),
+            framebuffer_size: RefCell::new(PhysicalSize::new(0, 0)),
+            presented: RefCell::new(Vec::new()),
+            presented_damage: RefCell::new(Vec::new()),
+        }
+    }
+
+    // There is no embedder without a display server
+    #[cfg(target_os = "linux")]
+    pub(crate) fn xembed(&self, _socket: u64) {}
+}
+
+impl RawWindow {
+    pub(crate) fn clipboard_get(
+        &self,
+        selection: Selection,
+        mime_type: &str,
+    ) -> Result<Vec<u8>, ClipboardError> {
+        selection_store(selection)
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(m, _)| m == mime_type)
+            .map(|(_, data)| data.clone())
+            .ok_or(ClipboardError::NoContent)
+    }
+
+    pub(crate) fn clipboard_set(
+        &self,
+        selection: Selection,
+        contents: Vec<(String, Vec<u8>)>,
+    ) -> Result<(), ClipboardError> {
+        *selection_store(selection).lock().unwrap() = contents;
+        Ok(())
+    }
+
+    pub(crate) fn clipboard_mime_types(
+        &self,
+        selection: Selection,
+    ) -> Result<Vec<String>, ClipboardError> {
+        let clipboard = selection_store(selection).lock().unwrap();
+        Ok(clipboard
+            .iter()
+            .map(|(mime_type, _)| mime_type.clone())
+            .collect())
+    }
+}
+
+/// Injected drags allow every action.
+impl RawWindow {
+    pub(crate) fn set_drag_action(&self, action: Option<DropAction>) {
+        let mut state = self.state.borrow_mut();
+        if state.dragging {
+            state.drag_action = action;
+        }
+    }
+
+    pub(crate) fn drag_source_actions(&self) -> Vec<DropAction> {
+        match self.state.borrow().dragging {
+            true => vec![DropAction::Copy, DropAction::Move, DropAction::Link],
+            false => Vec::new(),
+        }
+    }
+}
+
+fn selection_store(selection: Selection) -> &'static Mutex<Vec<(String, Vec<u8>)>> {
+    match selection {
+        Selection::Clipboard => &CLIPBOARD,
+        Selection::Primary => &PRIMARY_SELECTION,
+    }
+}
+
+/// The only monitor of the headless backend, 1920x1080 at 96 DPI and 60 Hz.
+fn headless_monitor() -> MonitorHandle {
+    let mode = ModeInfo {
+        id: 0,
+        size: (1920, 1080),
+        bit_depth: 24,
+        refresh_rate_millihertz: 60000,
+    };
+    MonitorHandle {
+        id: 0,
+        name: Some("headless".to_owned()),
+        position: (0, 0),
+        size: mode.size,
+        size_mm: (508, 286),
+        scale_factor: 1.0,
+        primary: true,
+        current_mode: Some(mode),
+        modes: vec![mode],
+    }
+}
+
+/// Inspection and event injection for tests.
+impl Window {
+    /// Queues an event that ```run``` delivers to its callback, as if the display server sent it.
+    /// ```run``` returns once all queued events were delivered.
+    pub fn inject_event(&self, event: WindowEvent) {
+        self.inner.events.borrow_mut().push_back(event);
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.inner.state.borrow().visible
+    }
+
+    pub fn is_decorated(&self) -> bool {
+        self.inner.state.borrow().decorated
+    }
+
+    pub fn is_minimized(&self) -> bool {
+        self.inner.state.borrow().minimized
+    }
+
+    pub fn is_maximized(&self) -> bool {
+        self.inner.state.borrow().maximized
+    }
+
+    pub fn is_modal(&self) -> bool {
+        self.inner.state.borrow().modal
+    }
+
+    pub fn is_popup(&self) -> bool {
+        self.inner.state.borrow().popup
+    }
+
+    pub fn window_level(&self) -> WindowLevel {
+        self.inner.state.borrow().level
+    }
+
+    pub fn window_type(&self) -> WindowType {
+        self.inner.state.borrow().window_type
+    }
+
+    /// App id and instance name set with ```WindowBuilder::app_id```.
+    pub fn app_id(&self) -> Option<(String, String)> {
+        self.inner.state.borrow().app_id.clone()
+    }
+
+    pub fn border_width(&self) -> u32 {
+        self.inner.state.borrow().border_width
+    }
+
+    /// Workspace index and whether the window is visible on all workspaces.
+    pub fn workspace(&self) -> (u32, bool) {
+        let state = self.inner.state.borrow();
+        (state.workspace, state.all_workspaces)
+    }
+
+    /// Identifier of the window this window is transient for (see ```WindowInstance::id```).
+    pub fn transient_for(&self) -> Option<u64> {
+        self.inner.state.borrow().transient_for
+    }
+
+    pub fn framebuffer_format(&self) -> PixelFormat {
+        self.inner.state.borrow().format
+    }
+
+    /// Action accepted by the callback for the current or the last injected drag,
+    /// None if it was rejected.
+    pub fn drag_action(&self) -> Option<DropAction> {
+        self.inner.state.borrow().drag_action
+    }
+
+    /// Returns the pixels shown by ```present```, row by row.
+    /// Regions that were not damaged keep the pixels of earlier calls.
+    pub fn presented_pixels(&self) -> Vec<u32> {
+        self.inner.presented.borrow().clone()
+    }
+
+    /// Returns and clears the regions passed to ```present```,
+    /// clipped to the framebuffer (the whole buffer for an empty slice).
+    pub fn take_presented_damage(&self) -> Vec<Rect> {
+        std::mem::take(&mut *self.inner.presented_damage.borrow_mut())
+    }
+}
+
+/// Headless windows have no surface to render into, headless contexts are created
+/// with ```GlContextBuilder::build_headless``` instead.
+#[cfg(feature = "gl")]
+pub enum RawGlContext {}
+
+#[cfg(feature = "gl")]
+impl IGlContext for RawGlContext {
+    fn new(_window: &RawWindow, _attributes: &GlAttributes) -> Result<Self, GlError> {
+        Err(GlError::NotSupported(
+            "window contexts of headless windows".to_owned(),
+        ))
+    }
+
+    fn make_current(&self) -> Result<(), GlError> {
+        match *self {}
+    }
+
+    fn swap_buffers(&self) {
+        match *self {}
+    }
+
+    fn set_swap_interval(&self, _interval: i32) {
+        match *self {}
+    }
+
+    fn get_proc_address(&self, _name: &str) -> *const c_void {
+        match *self {}
+    }
+
+    fn resize(&self, _size: PhysicalSize) {
+        match *self {}
+    }
+
+    fn size(&self) -> PhysicalSize {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "vulkan")]
+impl RawWindow {
+    pub(crate) fn required_vulkan_extensions() -> &'static [&'static str] {
+        &[]
+    }
+
+    pub(crate) unsafe fn create_vulkan_surface(
+        &self,
+        _instance: VkInstance,
+    ) -> Result<VkSurfaceKHR, VulkanError> {
+        Err(VulkanError::NotSupported(
+            "surfaces of headless windows".to_owned(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::window::{DefWindowBuildAction, WindowBuilder};
+
+    fn window(width: u32, height: u32) -> Window {
+        let mut action = DefWindowBuildAction;
+        WindowBuilder::new(Box::new(&mut action))
+            .title("headless")
+            .width(width)
+            .height(height)
+            .build()
+    }
+
+    fn delivered_events(window: &Window) -> Vec<WindowEvent> {
+        let mut events = Vec::new();
+        window.run(|event, _| events.push(event));
+        events
+    }
+
+    #[test]
+    fn run_delivers_expose_before_injected_events() {
+        let window = window(20, 10);
+        window.inject_event(WindowEvent::KeyDown(0x61));
+        window.inject_event(WindowEvent::KeyUp(0x61));
+        window.inject_event(WindowEvent::CloseRequested);
+
+        assert_eq!(
+            delivered_events(&window),
+            [
+                WindowEvent::Expose(Rect::new(0, 0, 20, 10)),
+                WindowEvent::KeyDown(0x61),
+                WindowEvent::KeyUp(0x61),
+                WindowEvent::CloseRequested,
+            ]
+        );
+        assert!(window.is_visible());
+
+        // The window stays exposed, later runs only deliver new events
+        window.inject_event(WindowEvent::Focused(true));
+        assert_eq!(delivered_events(&window), [WindowEvent::Focused(true)]);
+    }
+
+    #[test]
+    fn run_stops_at_exit() {
+        let window = window(20, 10);
+        window.inject_event(WindowEvent::KeyDown(0x61));
+        window.inject_event(WindowEvent::KeyDown(0x62));
+
+        let mut events = Vec::new();
+        window.run(|event, control_flow| {
+            if let WindowEvent::KeyDown(_) = event {
+                *control_flow = ControlFlow::Exit(0);
+            }
+            events.push(event);
+        });
+        assert_eq!(events.len(), 2);
+
+        assert_eq!(delivered_events(&window), [WindowEvent::KeyDown(0x62)]);
+    }
+
+    #[test]
+    fn run_delivers_events_injected_by_the_callback() {
+        let window = window(20, 10);
+        window.inject_event(WindowEvent::KeyDown(0x61));
+
+        let mut events = Vec::new();
+        window.run(|event, _| {
+            if let WindowEvent::KeyDown(key) = event {
+                window.inject_event(WindowEvent::KeyUp(key));
+                window.show();
+            }
+            events.push(event);
+        });
+        assert_eq!(
+            events,
+            [
+                WindowEvent::Expose(Rect::new(0, 0, 20, 10)),
+                WindowEvent::KeyDown(0x61),
+                WindowEvent::KeyUp(0x61),
+            ]
+        );
+    }
+
+    #[test]
+    fn title() {
+        let window = window(20, 10);
+        assert_eq!(window.title(), "headless");
+
+        window.set_window_title("English 日本語");
+        assert_eq!(window.title(), "English 日本語");
+    }
+
+    #[test]
+    fn present_copies_damaged_regions() {
+        let window = window(4, 2);
+        window.framebuffer().fill(1);
+        window.present(&[]);
+        assert_eq!(window.presented_pixels(), [1; 8]);
+        assert_eq!(window.take_presented_damage(), [Rect::new(0, 0, 4, 2)]);
+
+        window.framebuffer().fill(2);
+        window.present(&[Rect::new(1, 1, 2, 1), Rect::new(3, 1, 5, 5)]);
+        assert_eq!(window.presented_pixels(), [1, 1, 1, 1, 1, 2, 2, 2]);
+        assert_eq!(
+            window.take_presented_damage(),
+            [Rect::new(1, 1, 2, 1), Rect::new(3, 1, 1, 1)]
+        );
+        assert!(window.take_presented_damage().is_empty());
+    }
+
+    // The selections are shared by all windows, so they are tested together
+    #[test]
+    fn clipboard_and_primary_selection() {
+        let window = window(20, 10);
+        let other = self::window(20, 10);
+
+        window.clipboard().set_text("copied").unwrap();
+        window.report_text_selection("selected").unwrap();
+        assert_eq!(other.clipboard().get_text().unwrap(), "copied");
+        assert_eq!(other.primary_selection().get_text().unwrap(), "selected");
+
+        other
+            .clipboard()
+            .set("image/png", vec![0x89, b'P', b'N', b'G'])
+            .unwrap();
+        assert_eq!(window.clipboard().mime_types().unwrap(), ["image/png"]);
+        assert_eq!(
+            window.clipboard().get("image/png").unwrap(),
+            [0x89, b'P', b'N', b'G']
+        );
+        assert_eq!(
+            window.clipboard().get_text(),
+            Err(ClipboardError::NoContent)
+        );
+
+        // Empty selections leave the primary selection as it is
+        window.report_text_selection("").unwrap();
+        assert_eq!(other.primary_selection().get_text().unwrap(), "selected");
+    }
+
+    #[test]
+    fn drags_are_rejected_until_accepted() {
+        let window = window(20, 10);
+        let position = PhysicalPosition::new(5, 5);
+        window.inject_event(WindowEvent::DragEntered {
+            paths: vec!["/tmp/file".into()],
+            position,
+        });
+        window.inject_event(WindowEvent::DragDropped {
+            paths: vec!["/tmp/file".into()],
+            position,
+        });
+
+        window.run(|event, _| {
+            if let WindowEvent::DragEntered { .. } = event {
+                assert_eq!(window.drag_action(), None);
+                assert_eq!(window.drag_source_actions().len(), 3);
+                window.accept_drag(DropAction::Copy);
+            }
+        });
+        assert_eq!(window.drag_action(), Some(DropAction::Copy));
+        assert!(window.drag_source_actions().is_empty());
+    }
+}
@@ -0,0 +1,251 @@
+use std::fmt;
+
+/// Sizes offered to the window manager in addition to the original image, so that it can pick the closest one.
+#[cfg_attr(any(feature = "headless", windows), allow(dead_code))]
+const ICON_SIZES: [u32; 5] = [16, 32, 48, 64, 128];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BadIcon {
+    /// The length of the RGBA data is not a multiple of 4.
+    ByteCountNotDivisibleBy4 { byte_count: usize },
+    /// width * height does not match the number of pixels.
+    DimensionsVsPixelCount {
+        width: u32,
+        height: u32,
+        pixel_count: usize,
+    },
+    /// The image could not be decoded.
+    Decode(String),
+}
+
+impl fmt::Display for BadIcon {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BadIcon::ByteCountNotDivisibleBy4 { byte_count } => write!(
+                f,
+                "the length of the RGBA data ({}) is not divisible by 4",
+                byte_count
+            ),
+            BadIcon::DimensionsVsPixelCount {
+                width,
+                height,
+                pixel_count,
+            } => write!(
+                f,
+                "{}x{} does not match the number of pixels ({})",
+                width, height, pixel_count
+            ),
+            BadIcon::Decode(message) => write!(f, "failed to decode the icon: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for BadIcon {}
+
+/// An icon shown in the title bar and the taskbar.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Icon {
+    pub(crate) rgba: Vec<u8>,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+impl Icon {
+    /// Creates an icon from non-premultiplied RGBA pixels, row by row from the top-left corner.
+    pub fn from_rgba(rgba: Vec<u8>, width: u32, height: u32) -> Result<Self, BadIcon> {
+        if !rgba.len().is_multiple_of(4) {
+            return Err(BadIcon::ByteCountNotDivisibleBy4 {
+                byte_count: rgba.len(),
+            });
+        }
+        let pixel_count = rgba.len() / 4;
+        if pixel_count != width as usize * height as usize {
+            return Err(BadIcon::DimensionsVsPixelCount {
+                width,
+                height,
+                pixel_count,
+            });
+        }
+
+        Ok(Self {
+            rgba,
+            width,
+            height,
+        })
+    }
+
+    /// Decodes a PNG image into an icon.
+    #[cfg(feature = "png")]
+    pub fn from_png(bytes: &[u8]) -> Result<Self, BadIcon> {
+        let decode_error = |e: png::DecodingError| BadIcon::Decode(e.to_string());
+
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(decode_error)?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(decode_error)?;
+        buffer.truncate(info.buffer_size());
+
+        let rgba = match info.color_type {
+            png::ColorType::Rgba => buffer,
+            png::ColorType::Rgb => buffer
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 0xFF])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => buffer
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            png::ColorType::Grayscale => buffer.iter().flat_map(|g| [*g, *g, *g, 0xFF]).collect(),
+            png::ColorType::Indexed => {
+                return Err(BadIcon::Decode(
+                    "indexed colors were not expanded".to_owned(),
+                ))
+            }
+        };
+
+        Self::from_rgba(rgba, info.width, info.height)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the icon followed by downscaled copies for the standard icon sizes smaller than it.
+    #[cfg_attr(any(feature = "headless", windows), allow(dead_code))]
+    pub(crate) fn sizes(&self) -> Vec<Icon> {
+        let largest = self.width.max(self.height);
+        let mut icons = vec![self.clone()];
+        icons.extend(
+            ICON_SIZES
+                .iter()
+                .rev()
+                .filter(|size| **size < largest)
+                .map(|size| self.resized(*size)),
+        );
+        icons
+    }
+
+    /// Downscales the icon so that its longest side is ```size``` by averaging the covered pixels.
+    fn resized(&self, size: u32) -> Icon {
+        let largest = self.width.max(self.height) as u64;
+        let width = ((self.width as u64 * size as u64 / largest) as u32).max(1);
+        let height = ((self.height as u64 * size as u64 / largest) as u32).max(1);
+
+        // Range of source pixels covered by the destination pixel i
+        let span = |i: u32, source: u32, destination: u32| {
+            let start = i * source / destination;
+            (start, ((i + 1) * source / destination).max(start + 1))
+        };
+
+        let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            let (top, bottom) = span(y, self.height, height);
+            for x in 0..width {
+                let (left, right) = span(x, self.width, width);
+
+                let mut sum = [0u32; 4];
+                for sy in top..bottom {
+                    for sx in left..right {
+                        let offset = ((sy * self.width + sx) * 4) as usize;
+                        for (channel, value) in sum.iter_mut().zip(&self.rgba[offset..offset + 4]) {
+                            *channel += *value as u32;
+                        }
+                    }
+                }
+                let count = (bottom - top) * (right - left);
+                rgba.extend(sum.iter().map(|channel| (channel / count) as u8));
+            }
+        }
+
+        Icon {
+            rgba,
+            width,
+            height,
+        }
+    }
+
+    /// Returns the pixels as 0xAARRGGBB values.
+    #[cfg_attr(any(feature = "headless", windows), allow(dead_code))]
+    pub(crate) fn to_argb(&self) -> Vec<u32> {
+        self.rgba
+            .chunks_exact(4)
+            .map(|p| u32::from_be_bytes([p[3], p[0], p[1], p[2]]))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_rgba_rejects_invalid_lengths() {
+        assert_eq!(
+            Icon::from_rgba(vec![0; 7], 1, 2),
+            Err(BadIcon::ByteCountNotDivisibleBy4 { byte_count: 7 })
+        );
+        assert_eq!(
+            Icon::from_rgba(vec![0; 8], 2, 2),
+            Err(BadIcon::DimensionsVsPixelCount {
+                width: 2,
+                height: 2,
+                pixel_count: 2
+            })
+        );
+    }
+
+    #[test]
+    fn resized_averages_covered_pixels() {
+        #[rustfmt::skip]
+        let icon = Icon::from_rgba(
+            vec![
+                0, 0, 0, 0,      100, 100, 100, 100,
+                200, 200, 200, 200, 40, 60, 80, 255,
+            ],
+            2,
+            2,
+        )
+        .unwrap();
+
+        let resized = icon.resized(1);
+        assert_eq!((resized.width, resized.height), (1, 1));
+        assert_eq!(resized.rgba, [85, 90, 95, 138]);
+    }
+
+    #[test]
+    fn resized_keeps_aspect_ratio() {
+        let icon = Icon::from_rgba(vec![255; 64 * 16 * 4], 64, 16).unwrap();
+
+        let resized = icon.resized(16);
+        assert_eq!((resized.width, resized.height), (16, 4));
+        assert!(resized.rgba.iter().all(|value| *value == 255));
+
+        // The short side never drops to zero pixels
+        let resized = icon.resized(2);
+        assert_eq!((resized.width, resized.height), (2, 1));
+    }
+
+    #[test]
+    fn sizes_skips_larger_sizes() {
+        let icon = Icon::from_rgba(vec![0; 40 * 40 * 4], 40, 40).unwrap();
+
+        let sizes = icon
+            .sizes()
+            .iter()
+            .map(|icon| icon.width)
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, [40, 32, 16]);
+    }
+
+    #[test]
+    fn to_argb() {
+        let icon = Icon::from_rgba(vec![0x11, 0x22, 0x33, 0x44, 0xFF, 0, 0, 0x80], 2, 1).unwrap();
+
+        assert_eq!(icon.to_argb(), [0x44112233, 0x80FF0000]);
+    }
+}
@@ -1,6 +1,33 @@
-use crate::window::{ControlFlow, IWindow, WindowBuildAction, WindowEvent};
-use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use crate::window::xlib_ext::{XRandr, XShm, XINERAMA, XRANDR, XSHM};
+use crate::window::{
+    dpi_scale_factor, parse_uri_list, parse_xsettings_dpi, place_popup, ClipboardError,
+    ConstraintAdjustment, ControlFlow, DropAction, Fullscreen, Gravity, IWindow, Icon, ModeInfo,
+    MonitorHandle, PhysicalPosition, PhysicalSize, PixelFormat, PopupError, Position, Rect,
+    Selection, Size, WindowBuildAction, WindowEvent, WindowLevel, WindowType,
+};
+#[cfg(feature = "vulkan")]
+use crate::window::{
+    instance_proc_address, VkInstance, VkResult, VkSurfaceKHR, VulkanError, VK_SUCCESS,
+};
+#[cfg(feature = "gl")]
+use crate::window::{GlAttributes, GlError, GlProfile, IGlContext};
+use raw_window_handle::{
+    HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
+};
 use safex::xlib::*;
+use std::cell::{Cell, RefCell, RefMut};
+#[cfg(any(feature = "gl", feature = "vulkan"))]
+use std::ffi::c_void;
+use std::ffi::{c_char, c_int, c_long, c_uchar, c_uint, c_ulong, CStr, CString};
+use std::mem::MaybeUninit;
+use std::path::PathBuf;
+use std::ptr::{null, null_mut};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+#[cfg(feature = "gl")]
+use x11::glx;
+use x11::{keysym, xlib, xrandr, xshm};
 
 pub struct WindowHandle {
     pub window: Window,
@@ -14,19 +41,40 @@ pub struct WindowInstance<'a> {
 
 pub struct RawWindow {
     window: Window,
-    display: Display,
-    cmap: ColorMap,
+    /// Shared with the popups of the window, which are created on the same connection.
+    display: Rc<Display>,
+    /// Popups open on the connection. Each reads only its own events, the other windows leave
+    /// them in the queue.
+    popups: Rc<RefCell<Vec<xlib::Window>>>,
+    /// Looked up once, some renderers ask for the raw window handle every frame.
+    visual_id: xlib::VisualID,
+
+    fullscreen: RefCell<Option<Fullscreen>>,
+    /// CRTC and mode to restore after leaving exclusive fullscreen.
+    saved_mode: RefCell<Option<(xrandr::RRCrtc, xrandr::RRMode)>>,
+    /// Popups grab the pointer and the keyboard and are dismissed by clicks outside of them.
+    popup: bool,
+    running: RefCell<bool>,
+    /// Embedder (socket) window when the window is embedded with XEmbed.
+    embedder: RefCell<Option<xlib::Window>>,
+    frame: RefCell<Option<FrameImage>>,
+    selections: RefCell<Vec<OwnedSelection>>,
+    incr_transfers: RefCell<Vec<IncrTransfer>>,
+    /// Drag of another client over the window.
+    drag: RefCell<Option<XdndDrag>>,
+    /// Child window a GL context renders into when its visual differs from the window's,
+    /// 0 when there is none. The context clears it before destroying the child.
+    gl_child: Rc<Cell<xlib::Window>>,
 }
 
-impl IWindow for RawWindow {
+impl IWindow<'_> for RawWindow {
     fn new(
         title: String,
-        width: u32,
-        height: u32,
-        x: i32,
-        y: i32,
+        size: Size,
+        position: Position,
         border_width: u32,
-        mut build_action: Box<dyn WindowBuildAction>,
+        parent: Option<RawWindowHandle>,
+        build_action: Box<&mut dyn WindowBuildAction>,
     ) -> Self {
         build_action.pre_init();
 
@@ -40,21 +88,39 @@ impl IWindow for RawWindow {
 
                 let white = Color::from_rgb(&display, &cmap, 65535, 65535, 65535).get_pixel();
 
+                // The monitor is not known before the window exists, so the desktop wide factor is used
+                let scale_factor = desktop_scale_factor(display.as_raw()).unwrap_or(1.0);
+                let size = size.to_physical(scale_factor);
+                let position = position.to_physical(scale_factor);
+
                 let window = Window::create_simple(
                     &display,
                     &screen,
                     Some(()),
                     Some(root),
-                    y,
-                    x,
-                    width,
-                    height,
+                    position.x,
+                    position.y,
+                    size.width,
+                    size.height,
                     border_width,
                     0,
                     white,
                 );
+                select_input(&display, &window);
 
-                window.set_window_title(&title);
+                // safex can only create windows below windows it created itself,
+                // so children of foreign windows are moved there before they are mapped
+                if let Some(RawWindowHandle::Xlib(parent)) = parent {
+                    unsafe {
+                        xlib::XReparentWindow(
+                            display.as_raw(),
+                            window.as_raw(),
+                            parent.window,
+                            position.x,
+                            position.y,
+                        );
+                    }
+                }
 
                 let handle = WindowInstance {
                     window: &window,
@@ -63,20 +129,47 @@ impl IWindow for RawWindow {
 
                 build_action.window_created(&handle);
 
-                Self {
+                let raw = Self {
+                    visual_id: unsafe { window_visual_id(display.as_raw(), window.as_raw()) },
                     window,
-                    display,
-                    cmap,
-                }
+                    display: Rc::new(display),
+                    popups: Rc::new(RefCell::new(Vec::new())),
+                    fullscreen: RefCell::new(None),
+                    saved_mode: RefCell::new(None),
+                    popup: false,
+                    running: RefCell::new(true),
+                    embedder: RefCell::new(None),
+                    frame: RefCell::new(None),
+                    selections: RefCell::new(Vec::new()),
+                    incr_transfers: RefCell::new(Vec::new()),
+                    drag: RefCell::new(None),
+                    gl_child: Rc::new(Cell::new(0)),
+                };
+                raw.set_window_title(&title);
+                raw.set_client_properties();
+                raw.change_atom_property("XdndAware", xlib::XA_ATOM, &[XDND_VERSION]);
+                raw
             }
 
             Some(handle) => {
-                let screen = Screen::default(&handle.display);
-                let cmap = ColorMap::default(&handle.display, &screen);
+                select_input(&handle.display, &handle.window);
                 Self {
+                    visual_id: unsafe {
+                        window_visual_id(handle.display.as_raw(), handle.window.as_raw())
+                    },
                     window: handle.window,
-                    display: handle.display,
-                    cmap,
+                    display: Rc::new(handle.display),
+                    popups: Rc::new(RefCell::new(Vec::new())),
+                    fullscreen: RefCell::new(None),
+                    saved_mode: RefCell::new(None),
+                    popup: false,
+                    running: RefCell::new(true),
+                    embedder: RefCell::new(None),
+                    frame: RefCell::new(None),
+                    selections: RefCell::new(Vec::new()),
+                    incr_transfers: RefCell::new(Vec::new()),
+                    drag: RefCell::new(None),
+                    gl_child: Rc::new(Cell::new(0)),
                 }
             }
         }
@@ -87,22 +180,139 @@ impl IWindow for RawWindow {
         F: FnMut(WindowEvent, &mut ControlFlow),
     {
         let mut control_flow = ControlFlow::Listen;
+        let mut scale_factor = self.scale_factor();
+        // Position of the last ConfigureNotify
+        let mut position = None;
 
         self.window.map();
 
-        self.window.run(|event, flow| match control_flow {
-            ControlFlow::Listen => match event {
-                safex::xlib::WindowEvent::Expose => {
-                    callback(WindowEvent::Expose, &mut control_flow);
+        while *self.running.borrow() {
+            let event = unsafe {
+                let mut event = MaybeUninit::uninit();
+                xlib::XIfEvent(
+                    self.display.as_raw(),
+                    event.as_mut_ptr(),
+                    Some(is_run_loop_event),
+                    self as *const RawWindow as xlib::XPointer,
+                );
+                event.assume_init()
+            };
+
+            match control_flow {
+                ControlFlow::Listen => match event.get_type() {
+                    xlib::Expose => {
+                        // Each exposed rectangle arrives separately, count tells how many follow
+                        let expose = xlib::XExposeEvent::from(event);
+                        let rect = Rect::new(
+                            expose.x,
+                            expose.y,
+                            expose.width as u32,
+                            expose.height as u32,
+                        );
+                        callback(WindowEvent::Expose(rect), &mut control_flow);
+                    }
+                    xlib::ClientMessage => {
+                        let message = xlib::XClientMessageEvent::from(event);
+                        if message.message_type == self.atom("_XEMBED") {
+                            if let Some(event) = self.handle_xembed_message(&message) {
+                                callback(event, &mut control_flow);
+                            }
+                        } else {
+                            self.handle_xdnd_message(&message, &mut callback, &mut control_flow);
+                        }
+                    }
+                    xlib::SelectionRequest => {
+                        let request = xlib::XSelectionRequestEvent::from(event);
+                        self.handle_selection_request(&request);
+                    }
+                    xlib::SelectionClear => {
+                        // Another client took the selection
+                        let clear = xlib::XSelectionClearEvent::from(event);
+                        self.selections
+                            .borrow_mut()
+                            .retain(|owned| owned.selection != clear.selection);
+                    }
+                    xlib::SelectionNotify => {
+                        // Answer to the request for the files of a drag
+                        let notify = xlib::XSelectionEvent::from(event);
+                        if notify.selection == self.atom("XdndSelection") {
+                            let event = self.receive_xdnd_selection(&notify);
+                            self.deliver_xdnd_entered(event, &mut callback, &mut control_flow);
+                        }
+                    }
+                    xlib::PropertyNotify => {
+                        let property = xlib::XPropertyEvent::from(event);
+                        if property.state == xlib::PropertyDelete {
+                            self.continue_incr_transfer(property.window, property.atom);
+                        } else if property.window == self.window.as_raw()
+                            && property.atom == self.atom("GWL_XDND")
+                        {
+                            let event = self.receive_xdnd_chunk();
+                            self.deliver_xdnd_entered(event, &mut callback, &mut control_flow);
+                        }
+                    }
+                    xlib::ConfigureNotify => {
+                        let configure = xlib::XConfigureEvent::from(event);
+                        let gl_child = self.gl_child.get();
+                        if gl_child != 0 {
+                            unsafe {
+                                xlib::XResizeWindow(
+                                    self.display.as_raw(),
+                                    gl_child,
+                                    configure.width.max(1) as c_uint,
+                                    configure.height.max(1) as c_uint,
+                                );
+                            }
+                        }
+
+                        // Moving the window may have moved it onto a monitor with another scale
+                        // factor, looking that up takes round trips, so only after it moved
+                        let new_position = (configure.x, configure.y);
+                        if position == Some(new_position) {
+                            continue;
+                        }
+                        position = Some(new_position);
+
+                        let new_scale_factor = self.scale_factor();
+                        if new_scale_factor != scale_factor {
+                            scale_factor = new_scale_factor;
+                            callback(
+                                WindowEvent::ScaleFactorChanged(scale_factor),
+                                &mut control_flow,
+                            );
+                        }
+                    }
+                    xlib::ButtonPress if self.popup => {
+                        // The pointer is grabbed, so clicks outside of the popup are reported as well
+                        let button = xlib::XButtonEvent::from(event);
+                        let size = self.get_window_size();
+                        if button.x < 0
+                            || button.y < 0
+                            || button.x >= size.width as i32
+                            || button.y >= size.height as i32
+                        {
+                            self.dismiss_popup();
+                            callback(WindowEvent::PopupDone, &mut control_flow);
+                        }
+                    }
+                    xlib::KeyPress if self.popup => {
+                        let mut key = xlib::XKeyEvent::from(event);
+                        let symbol = unsafe { xlib::XLookupKeysym(&mut key, 0) };
+                        if symbol == keysym::XK_Escape as xlib::KeySym {
+                            self.dismiss_popup();
+                            callback(WindowEvent::PopupDone, &mut control_flow);
+                        }
+                    }
+                    _ => {}
+                },
+                ControlFlow::Exit(code) => {
+                    std::process::exit(code as i32);
                 }
-            },
-            ControlFlow::Exit(code) => {
-                std::process::exit(code as i32);
             }
-        })
+        }
     }
 
-    fn get_instance(&self) -> WindowInstance {
+    fn get_instance(&self) -> WindowInstance<'_> {
         WindowInstance {
             window: &self.window,
             display: &self.display,
@@ -110,16 +320,126 @@ impl IWindow for RawWindow {
     }
 
     fn set_window_title(&self, title: &str) {
+        // WM_NAME is Latin-1, so the UTF-8 EWMH properties are set as well
         self.window.set_window_title(title);
+        let utf8_string = self.atom("UTF8_STRING");
+        self.change_string_property("_NET_WM_NAME", utf8_string, title);
+        self.change_string_property("_NET_WM_ICON_NAME", utf8_string, title);
+        self.display.flush();
+    }
+
+    fn title(&self) -> String {
+        let title = self.get_string_property("_NET_WM_NAME", self.atom("UTF8_STRING"));
+        String::from_utf8_lossy(&title).into_owned()
+    }
+
+    fn set_window_border_width(&self, border_width: u32) {
+        unsafe {
+            xlib::XSetWindowBorderWidth(self.display.as_raw(), self.window.as_raw(), border_width);
+        }
+        self.display.flush();
+    }
+
+    fn set_app_id(&self, app_id: &str, instance_name: &str) {
+        // The strings end at the first null byte, as WM_CLASS is made of null-terminated strings
+        let truncate = |value: &str| {
+            let end = value.find('\0').unwrap_or(value.len());
+            CString::new(&value[..end]).unwrap()
+        };
+        let res_name = truncate(instance_name);
+        let res_class = truncate(app_id);
+
+        unsafe {
+            let mut hint = xlib::XClassHint {
+                res_name: res_name.as_ptr() as *mut _,
+                res_class: res_class.as_ptr() as *mut _,
+            };
+            xlib::XSetClassHint(self.display.as_raw(), self.window.as_raw(), &mut hint);
+        }
+        self.display.flush();
+    }
+
+    fn set_window_type(&self, window_type: WindowType) {
+        let name = match window_type {
+            WindowType::Normal => "_NET_WM_WINDOW_TYPE_NORMAL",
+            WindowType::Dialog => "_NET_WM_WINDOW_TYPE_DIALOG",
+            WindowType::Utility => "_NET_WM_WINDOW_TYPE_UTILITY",
+            WindowType::Toolbar => "_NET_WM_WINDOW_TYPE_TOOLBAR",
+            WindowType::Splash => "_NET_WM_WINDOW_TYPE_SPLASH",
+            WindowType::Tooltip => "_NET_WM_WINDOW_TYPE_TOOLTIP",
+            WindowType::Menu => "_NET_WM_WINDOW_TYPE_MENU",
+            WindowType::Notification => "_NET_WM_WINDOW_TYPE_NOTIFICATION",
+            WindowType::Dock => "_NET_WM_WINDOW_TYPE_DOCK",
+        };
+        self.change_atom_property(
+            "_NET_WM_WINDOW_TYPE",
+            xlib::XA_ATOM,
+            &[self.atom(name) as c_long],
+        );
+        self.display.flush();
+    }
+
+    fn set_transient_for(&self, parent: Option<&RawWindow>) {
+        unsafe {
+            match parent {
+                Some(parent) => {
+                    xlib::XSetTransientForHint(
+                        self.display.as_raw(),
+                        self.window.as_raw(),
+                        parent.window.as_raw(),
+                    );
+                }
+                None => {
+                    xlib::XDeleteProperty(
+                        self.display.as_raw(),
+                        self.window.as_raw(),
+                        xlib::XA_WM_TRANSIENT_FOR,
+                    );
+                }
+            }
+        }
+        self.display.flush();
     }
 
-    fn set_window_border_width(&self, border_width: u32) {}
+    fn set_modal(&self, b: bool) {
+        self.set_net_wm_state("_NET_WM_STATE_MODAL", b);
+    }
 
     fn set_undecorated(&self, b: bool) {
-        match b {
-            true => {}
-            false => {}
+        // flags (MWM_HINTS_DECORATIONS), functions, decorations, input mode, status
+        let motif_wm_hints = self.atom("_MOTIF_WM_HINTS");
+        self.change_atom_property(
+            "_MOTIF_WM_HINTS",
+            motif_wm_hints,
+            &[1 << 1, 0, !b as c_long, 0, 0],
+        );
+        self.display.flush();
+    }
+
+    fn set_window_icon(&self, icon: Option<Icon>) {
+        match icon {
+            None => unsafe {
+                xlib::XDeleteProperty(
+                    self.display.as_raw(),
+                    self.window.as_raw(),
+                    self.atom("_NET_WM_ICON"),
+                );
+            },
+            Some(icon) => {
+                // width, height and the ARGB pixels of every size one after another
+                let data = icon
+                    .sizes()
+                    .iter()
+                    .flat_map(|icon| {
+                        [icon.width as c_long, icon.height as c_long]
+                            .into_iter()
+                            .chain(icon.to_argb().into_iter().map(|pixel| pixel as c_long))
+                    })
+                    .collect::<Vec<c_long>>();
+                self.change_atom_property("_NET_WM_ICON", xlib::XA_CARDINAL, &data);
+            }
         }
+        self.display.flush();
     }
 
     fn set_minimized(&self, b: bool) {
@@ -144,6 +464,25 @@ impl IWindow for RawWindow {
         }
     }
 
+    fn set_window_level(&self, level: WindowLevel) {
+        self.set_net_wm_state("_NET_WM_STATE_ABOVE", level == WindowLevel::AlwaysOnTop);
+        self.set_net_wm_state("_NET_WM_STATE_BELOW", level == WindowLevel::AlwaysOnBottom);
+    }
+
+    fn set_visible_on_all_workspaces(&self, b: bool) {
+        self.set_net_wm_state("_NET_WM_STATE_STICKY", b);
+    }
+
+    fn set_workspace(&self, workspace: u32) {
+        let workspace = workspace as c_long;
+        if self.is_mapped() {
+            // desktop, source indication
+            self.send_client_message("_NET_WM_DESKTOP", [workspace, 1, 0, 0, 0]);
+        } else {
+            self.change_atom_property("_NET_WM_DESKTOP", xlib::XA_CARDINAL, &[workspace]);
+        }
+    }
+
     fn show(&self) {
         self.window.map();
     }
@@ -152,21 +491,2453 @@ impl IWindow for RawWindow {
         self.window.unmap();
     }
 
-    fn get_window_pos(&self) -> (u32, u32) {
+    fn get_window_pos(&self) -> PhysicalPosition {
         let geometry = self.window.get_geometry();
-        (geometry.x, geometry.y)
+        PhysicalPosition::new(geometry.x, geometry.y)
+    }
+
+    fn set_window_pos(&self, position: Position) {
+        let position = position.to_physical(self.scale_factor());
+        unsafe {
+            xlib::XMoveWindow(
+                self.display.as_raw(),
+                self.window.as_raw(),
+                position.x,
+                position.y,
+            );
+        }
+        self.display.flush();
+    }
+
+    fn raise(&self) {
+        unsafe {
+            xlib::XRaiseWindow(self.display.as_raw(), self.window.as_raw());
+        }
+        self.display.flush();
+    }
+
+    fn lower(&self) {
+        unsafe {
+            xlib::XLowerWindow(self.display.as_raw(), self.window.as_raw());
+        }
+        self.display.flush();
     }
 
-    fn get_window_size(&self) -> (u32, u32) {
+    // X11 child windows are always drawn independently of their parent
+    fn set_synchronized(&self, _b: bool) {}
+
+    fn get_window_size(&self) -> PhysicalSize {
         let geometry = self.window.get_geometry();
-        (geometry.width, geometry.height)
+        PhysicalSize::new(geometry.width, geometry.height)
+    }
+
+    fn scale_factor(&self) -> f64 {
+        match self.current_monitor() {
+            Some(monitor) => monitor.scale_factor,
+            None => desktop_scale_factor(self.display.as_raw()).unwrap_or(1.0),
+        }
+    }
+
+    fn set_fullscreen(&self, fullscreen: Option<Fullscreen>) {
+        self.restore_video_mode();
+
+        match &fullscreen {
+            None => {
+                self.set_net_wm_state("_NET_WM_STATE_FULLSCREEN", false);
+            }
+            Some(fullscreen) => {
+                let monitor = match fullscreen {
+                    Fullscreen::Borderless(Some(monitor)) => Some(monitor.clone()),
+                    Fullscreen::Borderless(None) => self.current_monitor(),
+                    Fullscreen::Exclusive(mode) => {
+                        self.switch_video_mode(&mode.monitor, mode.info.id);
+                        Some(mode.monitor.clone())
+                    }
+                };
+
+                // _NET_WM_FULLSCREEN_MONITORS takes Xinerama indices, which do not necessarily
+                // follow the order of the RandR monitors
+                let index =
+                    monitor.and_then(|monitor| xinerama_index(self.display.as_raw(), &monitor));
+                if let Some(index) = index {
+                    // top, bottom, left, right, source indication
+                    let monitors = [index, index, index, index, 1];
+                    if self.is_mapped() {
+                        self.send_client_message("_NET_WM_FULLSCREEN_MONITORS", monitors);
+                    } else {
+                        self.change_atom_property(
+                            "_NET_WM_FULLSCREEN_MONITORS",
+                            xlib::XA_CARDINAL,
+                            &monitors[..4],
+                        );
+                    }
+                }
+
+                self.set_net_wm_state("_NET_WM_STATE_FULLSCREEN", true);
+            }
+        }
+
+        *self.fullscreen.borrow_mut() = fullscreen;
+    }
+
+    fn get_fullscreen(&self) -> Option<Fullscreen> {
+        self.fullscreen.borrow().clone()
+    }
+
+    fn available_monitors(&self) -> Vec<MonitorHandle> {
+        let display = self.display.as_raw();
+        let Some(xrandr) = XRANDR.as_ref() else {
+            return Vec::new();
+        };
+
+        unsafe {
+            // Monitors were added in XRandR 1.5
+            let (mut event_base, mut error_base) = (0, 0);
+            let (mut major, mut minor) = (0, 0);
+            if (xrandr.query_extension)(display, &mut event_base, &mut error_base) == 0
+                || (xrandr.query_version)(display, &mut major, &mut minor) == 0
+                || (major, minor) < (1, 5)
+            {
+                return Vec::new();
+            }
+
+            let root = xlib::XDefaultRootWindow(display);
+            let mut count = 0;
+            let monitors = (xrandr.get_monitors)(display, root, xlib::True, &mut count);
+            if monitors.is_null() {
+                return Vec::new();
+            }
+            let resources = (xrandr.get_screen_resources_current)(display, root);
+            let depth = xlib::XDefaultDepth(display, xlib::XDefaultScreen(display)) as u16;
+            let desktop_scale_factor = desktop_scale_factor(display);
+
+            let handles = std::slice::from_raw_parts(monitors, count as usize)
+                .iter()
+                .map(|info| {
+                    let output = match info.noutput {
+                        0 => 0,
+                        _ => *info.outputs,
+                    };
+                    let (modes, current_mode) =
+                        output_modes(xrandr, display, resources, output, depth);
+                    let size = (info.width as u32, info.height as u32);
+                    let size_mm = (info.mwidth as u32, info.mheight as u32);
+                    MonitorHandle {
+                        id: output,
+                        name: atom_name(display, info.name),
+                        position: (info.x, info.y),
+                        size,
+                        size_mm,
+                        scale_factor: desktop_scale_factor
+                            .unwrap_or_else(|| dpi_scale_factor(size, size_mm)),
+                        primary: info.primary != 0,
+                        current_mode,
+                        modes,
+                    }
+                })
+                .collect();
+
+            (xrandr.free_screen_resources)(resources);
+            (xrandr.free_monitors)(monitors);
+
+            handles
+        }
+    }
+
+    fn primary_monitor(&self) -> Option<MonitorHandle> {
+        let monitors = self.available_monitors();
+        monitors
+            .iter()
+            .find(|monitor| monitor.primary)
+            .or(monitors.first())
+            .cloned()
+    }
+
+    fn current_monitor(&self) -> Option<MonitorHandle> {
+        let size = self.get_window_size();
+        let (x, y) = self.root_position();
+        let (x, y) = (x + size.width as i32 / 2, y + size.height as i32 / 2);
+
+        let monitors = self.available_monitors();
+        monitors
+            .iter()
+            .find(|monitor| monitor.contains(x, y))
+            .or(monitors.first())
+            .cloned()
+    }
+
+    fn create_popup(
+        &self,
+        size: Size,
+        anchor_rect: Rect,
+        gravity: Gravity,
+        constraint_adjustment: ConstraintAdjustment,
+    ) -> Result<Self, PopupError> {
+        // Grabs and override-redirect windows work on the connection of the parent
+        let display = self.display.clone();
+        let screen = Screen::default(&display);
+        let root = Window::root_window(&display, &screen);
+        let cmap = ColorMap::default(&display, &screen);
+        let white = Color::from_rgb(&display, &cmap, 65535, 65535, 65535).get_pixel();
+
+        // Override-redirect windows are placed by the client, in root window coordinates
+        let (x, y) = self.root_position();
+        let anchor_rect = Rect::new(
+            anchor_rect.x + x,
+            anchor_rect.y + y,
+            anchor_rect.width,
+            anchor_rect.height,
+        );
+        let bounds = match self.current_monitor() {
+            Some(monitor) => Rect::new(
+                monitor.position.0,
+                monitor.position.1,
+                monitor.size.0,
+                monitor.size.1,
+            ),
+            None => unsafe {
+                let raw = display.as_raw();
+                let screen = xlib::XDefaultScreen(raw);
+                Rect::new(
+                    0,
+                    0,
+                    xlib::XDisplayWidth(raw, screen) as u32,
+                    xlib::XDisplayHeight(raw, screen) as u32,
+                )
+            },
+        };
+        let rect = place_popup(
+            anchor_rect,
+            gravity,
+            size.to_physical(self.scale_factor()),
+            constraint_adjustment,
+            bounds,
+        );
+
+        let window = Window::create_simple(
+            &display,
+            &screen,
+            Some(()),
+            Some(root),
+            rect.x,
+            rect.y,
+            rect.width,
+            rect.height,
+            0,
+            0,
+            white,
+        );
+
+        unsafe {
+            let mut attributes: xlib::XSetWindowAttributes = std::mem::zeroed();
+            attributes.override_redirect = xlib::True;
+            xlib::XChangeWindowAttributes(
+                display.as_raw(),
+                window.as_raw(),
+                xlib::CWOverrideRedirect,
+                &mut attributes,
+            );
+            xlib::XSelectInput(
+                display.as_raw(),
+                window.as_raw(),
+                ExposureMask as c_long
+                    | StructureNotifyMask as c_long
+                    | ButtonPressMask as c_long
+                    | KeyPressMask as c_long,
+            );
+        }
+
+        self.popups.borrow_mut().push(window.as_raw());
+        let popup = Self {
+            visual_id: unsafe { window_visual_id(display.as_raw(), window.as_raw()) },
+            window,
+            display,
+            popups: self.popups.clone(),
+            fullscreen: RefCell::new(None),
+            saved_mode: RefCell::new(None),
+            popup: true,
+            running: RefCell::new(true),
+            embedder: RefCell::new(None),
+            frame: RefCell::new(None),
+            selections: RefCell::new(Vec::new()),
+            incr_transfers: RefCell::new(Vec::new()),
+            drag: RefCell::new(None),
+            gl_child: Rc::new(Cell::new(0)),
+        };
+        popup.change_atom_property(
+            "_NET_WM_WINDOW_TYPE",
+            xlib::XA_ATOM,
+            &[popup.atom("_NET_WM_WINDOW_TYPE_POPUP_MENU") as c_long],
+        );
+        popup.set_transient_for(Some(self));
+        popup.set_client_properties();
+
+        // Grabs fail on windows that are not viewable yet, override-redirect windows are viewable
+        // as soon as the server processed the map request
+        popup.window.map();
+        unsafe {
+            let display = popup.display.as_raw();
+            let window = popup.window.as_raw();
+            xlib::XSync(display, xlib::False);
+            xlib::XGrabPointer(
+                display,
+                window,
+                xlib::False,
+                (ButtonPressMask | ButtonReleaseMask | PointerMotionMask) as u32,
+                xlib::GrabModeAsync,
+                xlib::GrabModeAsync,
+                0,
+                0,
+                xlib::CurrentTime,
+            );
+            xlib::XGrabKeyboard(
+                display,
+                window,
+                xlib::False,
+                xlib::GrabModeAsync,
+                xlib::GrabModeAsync,
+                xlib::CurrentTime,
+            );
+        }
+
+        Ok(popup)
+    }
+
+    fn framebuffer(&self) -> RefMut<'_, [u32]> {
+        let size = self.get_window_size();
+        let mut frame = self.frame.borrow_mut();
+
+        let resized = match frame.as_ref() {
+            Some(frame) => (frame.width, frame.height) != (size.width, size.height),
+            None => true,
+        };
+        if resized {
+            // The old image is released before the new one is created
+            *frame = None;
+            let display = self.display.as_raw();
+            *frame = Some(unsafe { FrameImage::new(display, size.width, size.height) });
+        }
+
+        RefMut::map(frame, |frame| frame.as_mut().unwrap().pixels())
+    }
+
+    fn present(&self, damage: &[Rect]) {
+        let mut frame = self.frame.borrow_mut();
+        let frame = match frame.as_mut() {
+            Some(frame) => frame,
+            None => return,
+        };
+
+        let bounds = Rect::new(0, 0, frame.width, frame.height);
+        let damage = match damage.is_empty() {
+            true => vec![bounds],
+            false => damage
+                .iter()
+                .filter_map(|rect| rect.intersection(&bounds))
+                .collect(),
+        };
+
+        unsafe {
+            for rect in damage {
+                frame.put(self.window.as_raw(), rect);
+            }
+            // The server has to read the pixels before the application changes them again
+            xlib::XSync(self.display.as_raw(), xlib::False);
+        }
+    }
+
+    fn framebuffer_formats(&self) -> Vec<PixelFormat> {
+        vec![PixelFormat::Argb8888, PixelFormat::Xrgb8888]
+    }
+
+    fn set_framebuffer_format(&self, _format: PixelFormat) {
+        // The window has no alpha channel, so both supported formats share the same layout
     }
 }
 
-unsafe impl HasRawWindowHandle for RawWindow {
-    fn raw_window_handle(&self) -> RawWindowHandle {
-        let mut handle = raw_window_handle::XlibWindowHandle::empty();
-        handle.window = self.window.as_raw();
-        RawWindowHandle::Xlib(handle)
+impl Drop for RawWindow {
+    fn drop(&mut self) {
+        // The framebuffer is released while the display is still open
+        self.frame.borrow_mut().take();
+        self.restore_video_mode();
+
+        if self.popup {
+            // No further events are reported for the popup, the queued ones are dropped,
+            // as the run loop of the parent would take them otherwise
+            let window = self.window.as_raw();
+            self.popups.borrow_mut().retain(|popup| *popup != window);
+            unsafe {
+                let display = self.display.as_raw();
+                xlib::XSelectInput(display, window, 0);
+                xlib::XSync(display, xlib::False);
+                let mut event = MaybeUninit::uninit();
+                while xlib::XCheckIfEvent(
+                    display,
+                    event.as_mut_ptr(),
+                    Some(is_window_event),
+                    &window as *const xlib::Window as xlib::XPointer,
+                ) == xlib::True
+                {}
+            }
+        }
+    }
+}
+
+impl RawWindow {
+    /// Embeds the window into the socket window of an XEmbed embedder.
+    pub(crate) fn xembed(&self, socket: u64) {
+        let xembed_info = self.atom("_XEMBED_INFO");
+        self.change_atom_property(
+            "_XEMBED_INFO",
+            xembed_info,
+            &[XEMBED_VERSION, XEMBED_MAPPED],
+        );
+
+        unsafe {
+            xlib::XReparentWindow(self.display.as_raw(), self.window.as_raw(), socket, 0, 0);
+        }
+        self.display.flush();
+
+        // Replaced by the window sent with XEMBED_EMBEDDED_NOTIFY
+        *self.embedder.borrow_mut() = Some(socket);
+    }
+
+    /// Handles an _XEMBED client message and returns the event it corresponds to.
+    fn handle_xembed_message(&self, message: &xlib::XClientMessageEvent) -> Option<WindowEvent> {
+        // time, message, detail, data1, data2
+        let data = message.data.as_longs();
+
+        match data[1] {
+            XEMBED_EMBEDDED_NOTIFY => {
+                *self.embedder.borrow_mut() = Some(data[3] as xlib::Window);
+                None
+            }
+            XEMBED_WINDOW_ACTIVATE => Some(WindowEvent::Activated(true)),
+            XEMBED_WINDOW_DEACTIVATE => Some(WindowEvent::Activated(false)),
+            // The embedder keeps the X input focus and forwards key events to the window
+            XEMBED_FOCUS_IN => Some(WindowEvent::Focused(true)),
+            XEMBED_FOCUS_OUT => Some(WindowEvent::Focused(false)),
+            _ => None,
+        }
+    }
+
+    /// Tells the window manager which process and machine the window belongs to.
+    fn set_client_properties(&self) {
+        self.change_atom_property(
+            "_NET_WM_PID",
+            xlib::XA_CARDINAL,
+            &[std::process::id() as c_long],
+        );
+
+        if let Ok(hostname) = std::fs::read_to_string("/proc/sys/kernel/hostname") {
+            self.change_string_property("WM_CLIENT_MACHINE", xlib::XA_STRING, hostname.trim());
+        }
+    }
+
+    /// Releases the grabs of a popup, hides it and ends its run loop.
+    fn dismiss_popup(&self) {
+        unsafe {
+            xlib::XUngrabPointer(self.display.as_raw(), xlib::CurrentTime);
+            xlib::XUngrabKeyboard(self.display.as_raw(), xlib::CurrentTime);
+        }
+        self.window.unmap();
+        self.display.flush();
+        *self.running.borrow_mut() = false;
+    }
+
+    /// Returns the position of the window in root window coordinates.
+    fn root_position(&self) -> (i32, i32) {
+        let display = self.display.as_raw();
+
+        unsafe {
+            let (mut x, mut y, mut child) = (0, 0, 0);
+            xlib::XTranslateCoordinates(
+                display,
+                self.window.as_raw(),
+                xlib::XDefaultRootWindow(display),
+                0,
+                0,
+                &mut x,
+                &mut y,
+                &mut child,
+            );
+            (x, y)
+        }
+    }
+
+    fn atom(&self, name: &str) -> xlib::Atom {
+        let name = CString::new(name).unwrap();
+        unsafe { xlib::XInternAtom(self.display.as_raw(), name.as_ptr(), xlib::False) }
+    }
+
+    fn is_mapped(&self) -> bool {
+        unsafe {
+            let mut attributes = std::mem::zeroed();
+            xlib::XGetWindowAttributes(
+                self.display.as_raw(),
+                self.window.as_raw(),
+                &mut attributes,
+            );
+            attributes.map_state != xlib::IsUnmapped
+        }
+    }
+
+    /// Sends an EWMH client message about this window to the root window.
+    fn send_client_message(&self, message_type: &str, data: [c_long; 5]) {
+        let display = self.display.as_raw();
+
+        unsafe {
+            let mut event = xlib::XClientMessageEvent {
+                type_: xlib::ClientMessage,
+                serial: 0,
+                send_event: xlib::True,
+                display,
+                window: self.window.as_raw(),
+                message_type: self.atom(message_type),
+                format: 32,
+                data: xlib::ClientMessageData::new(),
+            };
+            event.data.as_longs_mut().copy_from_slice(&data);
+
+            xlib::XSendEvent(
+                display,
+                xlib::XDefaultRootWindow(display),
+                xlib::False,
+                SubstructureRedirectMask as c_long | SubstructureNotifyMask as c_long,
+                &mut xlib::XEvent::from(event),
+            );
+            xlib::XFlush(display);
+        }
+    }
+
+    /// Replaces a 32-bit property of this window.
+    fn change_atom_property(&self, property: &str, type_: xlib::Atom, values: &[c_long]) {
+        unsafe {
+            xlib::XChangeProperty(
+                self.display.as_raw(),
+                self.window.as_raw(),
+                self.atom(property),
+                type_,
+                32,
+                xlib::PropModeReplace,
+                values.as_ptr() as *const c_uchar,
+                values.len() as i32,
+            );
+        }
+    }
+
+    /// Replaces an 8-bit property of this window.
+    fn change_string_property(&self, property: &str, type_: xlib::Atom, value: &str) {
+        unsafe {
+            xlib::XChangeProperty(
+                self.display.as_raw(),
+                self.window.as_raw(),
+                self.atom(property),
+                type_,
+                8,
+                xlib::PropModeReplace,
+                value.as_ptr(),
+                value.len() as i32,
+            );
+        }
+    }
+
+    /// Reads an 8-bit property of this window.
+    fn get_string_property(&self, property: &str, type_: xlib::Atom) -> Vec<u8> {
+        unsafe {
+            let mut actual_type = 0;
+            let mut actual_format = 0;
+            let mut count = 0;
+            let mut bytes_after = 0;
+            let mut data = std::ptr::null_mut();
+
+            xlib::XGetWindowProperty(
+                self.display.as_raw(),
+                self.window.as_raw(),
+                self.atom(property),
+                0,
+                c_long::MAX,
+                xlib::False,
+                type_,
+                &mut actual_type,
+                &mut actual_format,
+                &mut count,
+                &mut bytes_after,
+                &mut data,
+            );
+
+            if data.is_null() {
+                return Vec::new();
+            }
+
+            let value = match actual_format {
+                8 => std::slice::from_raw_parts(data, count as usize).to_vec(),
+                _ => Vec::new(),
+            };
+            xlib::XFree(data as *mut _);
+            value
+        }
+    }
+
+    /// Reads a 32-bit property of this window.
+    fn get_atom_property(&self, property: &str, type_: xlib::Atom) -> Vec<c_long> {
+        unsafe {
+            let mut actual_type = 0;
+            let mut actual_format = 0;
+            let mut count = 0;
+            let mut bytes_after = 0;
+            let mut data = std::ptr::null_mut();
+
+            xlib::XGetWindowProperty(
+                self.display.as_raw(),
+                self.window.as_raw(),
+                self.atom(property),
+                0,
+                c_long::MAX,
+                xlib::False,
+                type_,
+                &mut actual_type,
+                &mut actual_format,
+                &mut count,
+                &mut bytes_after,
+                &mut data,
+            );
+
+            if data.is_null() {
+                return Vec::new();
+            }
+
+            let values = match actual_format {
+                32 => std::slice::from_raw_parts(data as *const c_long, count as usize).to_vec(),
+                _ => Vec::new(),
+            };
+            xlib::XFree(data as *mut _);
+            values
+        }
+    }
+
+    /// Adds or removes a _NET_WM_STATE atom.
+    /// Mapped windows ask the window manager, unmapped windows set the property
+    /// that the window manager reads when the window is mapped.
+    fn set_net_wm_state(&self, state: &str, enable: bool) {
+        let state = self.atom(state) as c_long;
+
+        if self.is_mapped() {
+            // _NET_WM_STATE_REMOVE = 0, _NET_WM_STATE_ADD = 1
+            self.send_client_message("_NET_WM_STATE", [enable as c_long, state, 0, 1, 0]);
+        } else {
+            let mut states = self.get_atom_property("_NET_WM_STATE", xlib::XA_ATOM);
+            states.retain(|s| *s != state);
+            if enable {
+                states.push(state);
+            }
+            self.change_atom_property("_NET_WM_STATE", xlib::XA_ATOM, &states);
+        }
+    }
+
+    /// Switches the CRTC driving the monitor to the given RRMode.
+    fn switch_video_mode(&self, monitor: &MonitorHandle, mode: xrandr::RRMode) {
+        let display = self.display.as_raw();
+        let Some(xrandr) = XRANDR.as_ref() else {
+            return;
+        };
+
+        unsafe {
+            let root = xlib::XDefaultRootWindow(display);
+            let resources = (xrandr.get_screen_resources_current)(display, root);
+            let output = (xrandr.get_output_info)(display, resources, monitor.id);
+            if output.is_null() {
+                (xrandr.free_screen_resources)(resources);
+                return;
+            }
+            // Modes of other outputs are rejected by the server or drive the output out of range
+            let modes = std::slice::from_raw_parts((*output).modes, (*output).nmode as usize);
+            if (*output).crtc == 0 || !modes.contains(&mode) {
+                (xrandr.free_output_info)(output);
+                (xrandr.free_screen_resources)(resources);
+                return;
+            }
+
+            let crtc = (*output).crtc;
+            let info = (xrandr.get_crtc_info)(display, resources, crtc);
+            if !info.is_null() {
+                let status = (xrandr.set_crtc_config)(
+                    display,
+                    resources,
+                    crtc,
+                    xlib::CurrentTime,
+                    (*info).x,
+                    (*info).y,
+                    mode,
+                    (*info).rotation,
+                    (*info).outputs,
+                    (*info).noutput,
+                );
+                if status == 0 {
+                    *self.saved_mode.borrow_mut() = Some((crtc, (*info).mode));
+                }
+                (xrandr.free_crtc_info)(info);
+            }
+
+            (xrandr.free_output_info)(output);
+            (xrandr.free_screen_resources)(resources);
+        }
+    }
+
+    /// Restores the video mode changed by exclusive fullscreen.
+    fn restore_video_mode(&self) {
+        let (crtc, mode) = match self.saved_mode.borrow_mut().take() {
+            Some(saved) => saved,
+            None => return,
+        };
+        let display = self.display.as_raw();
+        let Some(xrandr) = XRANDR.as_ref() else {
+            return;
+        };
+
+        unsafe {
+            let root = xlib::XDefaultRootWindow(display);
+            let resources = (xrandr.get_screen_resources_current)(display, root);
+            let info = (xrandr.get_crtc_info)(display, resources, crtc);
+            if !info.is_null() {
+                (xrandr.set_crtc_config)(
+                    display,
+                    resources,
+                    crtc,
+                    xlib::CurrentTime,
+                    (*info).x,
+                    (*info).y,
+                    mode,
+                    (*info).rotation,
+                    (*info).outputs,
+                    (*info).noutput,
+                );
+                (xrandr.free_crtc_info)(info);
+            }
+            (xrandr.free_screen_resources)(resources);
+        }
+    }
+}
+
+/// Software framebuffer of a window, shared with the X server through MIT-SHM when possible.
+struct FrameImage {
+    display: *mut xlib::Display,
+    image: *mut xlib::XImage,
+    /// Shared memory segment holding the pixels, None when they are sent with XPutImage.
+    shm: Option<Box<xshm::XShmSegmentInfo>>,
+    /// Pixels of the image without MIT-SHM.
+    pixels: Vec<u32>,
+    /// Data of the image when the visual does not use 32 bits per pixel,
+    /// converted from ```pixels``` before they are sent.
+    converted: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+/// Set by request_failed, the error handler installed around requests that may fail
+/// (XShmAttach, glXCreateContextAttribsARB).
+static REQUEST_FAILED: AtomicBool = AtomicBool::new(false);
+
+unsafe extern "C" fn request_failed(
+    _display: *mut xlib::Display,
+    _event: *mut xlib::XErrorEvent,
+) -> c_int {
+    REQUEST_FAILED.store(true, Ordering::SeqCst);
+    0
+}
+
+impl FrameImage {
+    unsafe fn new(display: *mut xlib::Display, width: u32, height: u32) -> Self {
+        let screen = xlib::XDefaultScreen(display);
+        let visual = xlib::XDefaultVisual(display, screen);
+        let depth = xlib::XDefaultDepth(display, screen) as u32;
+
+        if let Some(xshm) = XSHM.as_ref() {
+            if (xshm.query_extension)(display) == xlib::True {
+                if let Some(frame) = Self::new_shared(xshm, display, visual, depth, width, height) {
+                    return frame;
+                }
+            }
+        }
+
+        let mut pixels = vec![0; (width * height) as usize];
+        let mut converted = Vec::new();
+        let image = xlib::XCreateImage(
+            display,
+            visual,
+            depth,
+            xlib::ZPixmap,
+            0,
+            null_mut(),
+            width,
+            height,
+            32,
+            0,
+        );
+        if !image.is_null() {
+            // The pixels are only sent as they are when the image has rows of 32-bit pixels
+            // without padding, e.g. not on 16-bit visuals
+            if (*image).bits_per_pixel == 32 && (*image).bytes_per_line == width as c_int * 4 {
+                (*image).data = pixels.as_mut_ptr() as *mut c_char;
+            } else {
+                converted = vec![0; ((*image).bytes_per_line * (*image).height) as usize];
+                (*image).data = converted.as_mut_ptr() as *mut c_char;
+            }
+        }
+
+        Self {
+            display,
+            image,
+            shm: None,
+            pixels,
+            converted,
+            width,
+            height,
+        }
+    }
+
+    unsafe fn new_shared(
+        xshm: &XShm,
+        display: *mut xlib::Display,
+        visual: *mut xlib::Visual,
+        depth: u32,
+        width: u32,
+        height: u32,
+    ) -> Option<Self> {
+        let mut info: Box<xshm::XShmSegmentInfo> = Box::new(std::mem::zeroed());
+        let image = (xshm.create_image)(
+            display,
+            visual,
+            depth,
+            xlib::ZPixmap,
+            null_mut(),
+            &mut *info,
+            width,
+            height,
+        );
+        if image.is_null() {
+            return None;
+        }
+        // pixels() hands out the segment as rows of 32-bit pixels without padding
+        if (*image).bits_per_pixel != 32 || (*image).bytes_per_line != width as c_int * 4 {
+            xlib::XDestroyImage(image);
+            return None;
+        }
+
+        let size = ((*image).bytes_per_line * (*image).height) as usize;
+        info.shmid = libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | 0o600);
+        if info.shmid < 0 {
+            xlib::XDestroyImage(image);
+            return None;
+        }
+        let address = libc::shmat(info.shmid, null(), 0);
+        if address as isize == -1 {
+            libc::shmctl(info.shmid, libc::IPC_RMID, null_mut());
+            xlib::XDestroyImage(image);
+            return None;
+        }
+        info.shmaddr = address as *mut c_char;
+        info.readOnly = xlib::False;
+        (*image).data = info.shmaddr;
+
+        // Attaching fails asynchronously with BadAccess when the server runs on another machine
+        REQUEST_FAILED.store(false, Ordering::SeqCst);
+        let previous_handler = xlib::XSetErrorHandler(Some(request_failed));
+        (xshm.attach)(display, &mut *info);
+        xlib::XSync(display, xlib::False);
+        xlib::XSetErrorHandler(previous_handler);
+
+        // The segment is freed once both sides detached it
+        libc::shmctl(info.shmid, libc::IPC_RMID, null_mut());
+
+        if REQUEST_FAILED.load(Ordering::SeqCst) {
+            libc::shmdt(info.shmaddr as *const _);
+            (*image).data = null_mut();
+            xlib::XDestroyImage(image);
+            return None;
+        }
+
+        Some(Self {
+            display,
+            image,
+            shm: Some(info),
+            pixels: Vec::new(),
+            converted: Vec::new(),
+            width,
+            height,
+        })
+    }
+
+    fn pixels(&mut self) -> &mut [u32] {
+        match &self.shm {
+            Some(info) => unsafe {
+                std::slice::from_raw_parts_mut(
+                    info.shmaddr as *mut u32,
+                    (self.width * self.height) as usize,
+                )
+            },
+            None => &mut self.pixels,
+        }
+    }
+
+    /// Copies a part of the image to the same position in the window.
+    unsafe fn put(&mut self, window: xlib::Window, rect: Rect) {
+        if self.image.is_null() {
+            return;
+        }
+        if !self.converted.is_empty() {
+            self.convert(rect);
+        }
+        let gc = xlib::XDefaultGC(self.display, xlib::XDefaultScreen(self.display));
+
+        match (&self.shm, XSHM.as_ref()) {
+            (Some(_), Some(xshm)) => {
+                (xshm.put_image)(
+                    self.display,
+                    window,
+                    gc,
+                    self.image,
+                    rect.x,
+                    rect.y,
+                    rect.x,
+                    rect.y,
+                    rect.width,
+                    rect.height,
+                    xlib::False,
+                );
+            }
+            _ => {
+                xlib::XPutImage(
+                    self.display,
+                    window,
+                    gc,
+                    self.image,
+                    rect.x,
+                    rect.y,
+                    rect.x,
+                    rect.y,
+                    rect.width,
+                    rect.height,
+                );
+            }
+        }
+    }
+
+    /// Writes a part of the pixels into the image in the pixel layout of the visual.
+    unsafe fn convert(&mut self, rect: Rect) {
+        let image = &*self.image;
+        let channel = |value: u32, mask: c_ulong| match mask {
+            0 => 0,
+            _ => {
+                let shift = mask.trailing_zeros();
+                let max = mask >> shift;
+                ((value as c_ulong * max + 127) / 255) << shift
+            }
+        };
+        let masks = (image.red_mask, image.green_mask, image.blue_mask);
+
+        for y in rect.y..rect.y + rect.height as i32 {
+            for x in rect.x..rect.x + rect.width as i32 {
+                let argb = self.pixels[(y as u32 * self.width + x as u32) as usize];
+                let pixel = channel(argb >> 16 & 0xFF, masks.0)
+                    | channel(argb >> 8 & 0xFF, masks.1)
+                    | channel(argb & 0xFF, masks.2);
+                xlib::XPutPixel(self.image, x, y, pixel);
+            }
+        }
+    }
+}
+
+impl Drop for FrameImage {
+    fn drop(&mut self) {
+        unsafe {
+            if let (Some(info), Some(xshm)) = (&mut self.shm, XSHM.as_ref()) {
+                (xshm.detach)(self.display, &mut **info);
+                xlib::XSync(self.display, xlib::False);
+                libc::shmdt(info.shmaddr as *const _);
+            }
+            // The pixels belong to the Vec or the shared memory segment, not to Xlib
+            if !self.image.is_null() {
+                (*self.image).data = null_mut();
+                xlib::XDestroyImage(self.image);
+            }
+        }
+    }
+}
+
+/// Contents of a selection owned by the window, by target atom.
+type SelectionContents = Vec<(xlib::Atom, Vec<u8>)>;
+
+/// Selection owned by the window.
+struct OwnedSelection {
+    selection: xlib::Atom,
+    /// Server time at which the window acquired the selection, answered for TIMESTAMP.
+    time: xlib::Time,
+    contents: SelectionContents,
+}
+
+/// Sending of selection contents too large for one property, in chunks written whenever the
+/// requestor deleted the previous one.
+struct IncrTransfer {
+    requestor: xlib::Window,
+    property: xlib::Atom,
+    target: xlib::Atom,
+    data: Vec<u8>,
+    offset: usize,
+    /// Time of the last chunk, transfers abandoned by the requestor are dropped.
+    updated: Instant,
+}
+
+/// How long a selection owner may take to answer, per chunk of INCR transfers.
+const SELECTION_TIMEOUT: Duration = Duration::from_secs(2);
+/// Largest property written at once, larger contents are sent with INCR.
+const MAX_SELECTION_CHUNK: usize = 256 * 1024;
+
+impl RawWindow {
+    pub(crate) fn clipboard_get(
+        &self,
+        selection: Selection,
+        mime_type: &str,
+    ) -> Result<Vec<u8>, ClipboardError> {
+        let selection = self.selection_atom(selection);
+        let target = self.atom(mime_type);
+
+        if let Some(contents) = self.owned_selection(selection) {
+            return contents
+                .into_iter()
+                .find(|(t, _)| *t == target)
+                .map(|(_, data)| data)
+                .ok_or(ClipboardError::NoContent);
+        }
+        self.convert_selection(selection, target)
+    }
+
+    pub(crate) fn clipboard_set(
+        &self,
+        selection: Selection,
+        contents: Vec<(String, Vec<u8>)>,
+    ) -> Result<(), ClipboardError> {
+        let contents = contents
+            .into_iter()
+            .map(|(mime_type, data)| (self.atom(&mime_type), data))
+            .collect();
+        self.set_selection_owner(self.selection_atom(selection), contents)
+    }
+
+    pub(crate) fn clipboard_mime_types(
+        &self,
+        selection: Selection,
+    ) -> Result<Vec<String>, ClipboardError> {
+        let selection = self.selection_atom(selection);
+
+        let targets = match self.owned_selection(selection) {
+            Some(contents) => contents.into_iter().map(|(target, _)| target).collect(),
+            None => self
+                .convert_selection(selection, self.atom("TARGETS"))?
+                .chunks_exact(std::mem::size_of::<c_long>())
+                .map(|atom| c_long::from_ne_bytes(atom.try_into().unwrap()) as xlib::Atom)
+                .collect::<Vec<xlib::Atom>>(),
+        };
+
+        let meta_targets = [
+            self.atom("TARGETS"),
+            self.atom("MULTIPLE"),
+            self.atom("TIMESTAMP"),
+        ];
+        Ok(targets
+            .into_iter()
+            .filter(|target| !meta_targets.contains(target))
+            .filter_map(|target| unsafe { atom_name(self.display.as_raw(), target) })
+            .collect())
+    }
+
+    fn selection_atom(&self, selection: Selection) -> xlib::Atom {
+        match selection {
+            Selection::Clipboard => self.atom("CLIPBOARD"),
+            Selection::Primary => self.atom("PRIMARY"),
+        }
+    }
+
+    /// Returns the contents of a selection if this window owns it.
+    fn owned_selection(&self, selection: xlib::Atom) -> Option<SelectionContents> {
+        let owner = unsafe { xlib::XGetSelectionOwner(self.display.as_raw(), selection) };
+        if owner != self.window.as_raw() {
+            return None;
+        }
+
+        self.selections
+            .borrow()
+            .iter()
+            .find(|owned| owned.selection == selection)
+            .map(|owned| owned.contents.clone())
+    }
+
+    fn set_selection_owner(
+        &self,
+        selection: xlib::Atom,
+        contents: SelectionContents,
+    ) -> Result<(), ClipboardError> {
+        let display = self.display.as_raw();
+        let time = self.server_time();
+
+        unsafe {
+            xlib::XSetSelectionOwner(display, selection, self.window.as_raw(), time);
+            if xlib::XGetSelectionOwner(display, selection) != self.window.as_raw() {
+                return Err(ClipboardError::Denied);
+            }
+        }
+
+        let mut selections = self.selections.borrow_mut();
+        selections.retain(|owned| owned.selection != selection);
+        selections.push(OwnedSelection {
+            selection,
+            time,
+            contents,
+        });
+        Ok(())
+    }
+
+    /// Returns the current server time from the PropertyNotify of an empty append to a
+    /// property, since ICCCM forbids acquiring selections at CurrentTime.
+    fn server_time(&self) -> xlib::Time {
+        let property = self.atom("GWL_TIMESTAMP");
+        unsafe {
+            xlib::XChangeProperty(
+                self.display.as_raw(),
+                self.window.as_raw(),
+                property,
+                xlib::XA_INTEGER,
+                8,
+                xlib::PropModeAppend,
+                null(),
+                0,
+            );
+        }
+
+        match self.wait_selection_event(xlib::PropertyNotify, 0, property) {
+            Ok(event) => xlib::XPropertyEvent::from(event).time,
+            Err(_) => xlib::CurrentTime,
+        }
+    }
+
+    /// Asks the selection owner to convert the selection to the target and reads the result,
+    /// including INCR transfers. Other events stay queued for the run loop.
+    fn convert_selection(
+        &self,
+        selection: xlib::Atom,
+        target: xlib::Atom,
+    ) -> Result<Vec<u8>, ClipboardError> {
+        let display = self.display.as_raw();
+        let window = self.window.as_raw();
+        let property = self.atom("GWL_SELECTION");
+
+        unsafe {
+            xlib::XDeleteProperty(display, window, property);
+            xlib::XConvertSelection(
+                display,
+                selection,
+                target,
+                property,
+                window,
+                xlib::CurrentTime,
+            );
+        }
+
+        let notify = self.wait_selection_event(xlib::SelectionNotify, selection, property)?;
+        let notify = xlib::XSelectionEvent::from(notify);
+        if notify.property == 0 {
+            return Err(ClipboardError::NoContent);
+        }
+
+        // The owner wrote the property before sending SelectionNotify, its PropertyNotify
+        // would be taken for the first INCR chunk
+        self.discard_property_events(property);
+        let (type_, data) = self.take_property(property);
+        if type_ != self.atom("INCR") {
+            return Ok(data);
+        }
+
+        // Deleting the INCR property asks for the first chunk, an empty chunk ends the transfer
+        let mut data = Vec::new();
+        loop {
+            self.wait_selection_event(xlib::PropertyNotify, selection, property)?;
+            let (_, chunk) = self.take_property(property);
+            if chunk.is_empty() {
+                return Ok(data);
+            }
+            data.extend(chunk);
+        }
+    }
+
+    /// Waits for the SelectionNotify event of the selection or a new value of the property
+    /// on this window.
+    fn wait_selection_event(
+        &self,
+        event_type: c_int,
+        selection: xlib::Atom,
+        property: xlib::Atom,
+    ) -> Result<xlib::XEvent, ClipboardError> {
+        let display = self.display.as_raw();
+        let wait = SelectionWait {
+            window: self.window.as_raw(),
+            event_type,
+            selection,
+            property,
+        };
+        let deadline = Instant::now() + SELECTION_TIMEOUT;
+
+        loop {
+            unsafe {
+                let mut event = MaybeUninit::uninit();
+                if xlib::XCheckIfEvent(
+                    display,
+                    event.as_mut_ptr(),
+                    Some(is_selection_event),
+                    &wait as *const SelectionWait as xlib::XPointer,
+                ) == xlib::True
+                {
+                    return Ok(event.assume_init());
+                }
+
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                if timeout.is_zero() {
+                    return Err(ClipboardError::Timeout);
+                }
+                let mut fd = libc::pollfd {
+                    fd: xlib::XConnectionNumber(display),
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                libc::poll(&mut fd, 1, timeout.as_millis() as c_int + 1);
+            }
+        }
+    }
+
+    /// Drops the queued PropertyNotify events of new values of a property of this window.
+    fn discard_property_events(&self, property: xlib::Atom) {
+        let wait = SelectionWait {
+            window: self.window.as_raw(),
+            event_type: xlib::PropertyNotify,
+            selection: 0,
+            property,
+        };
+
+        unsafe {
+            let mut event = MaybeUninit::uninit();
+            while xlib::XCheckIfEvent(
+                self.display.as_raw(),
+                event.as_mut_ptr(),
+                Some(is_selection_event),
+                &wait as *const SelectionWait as xlib::XPointer,
+            ) == xlib::True
+            {}
+        }
+    }
+
+    /// Reads and deletes a property of this window, returning its type and its items,
+    /// which are longs for 32-bit properties like in Xlib.
+    fn take_property(&self, property: xlib::Atom) -> (xlib::Atom, Vec<u8>) {
+        unsafe {
+            let mut actual_type = 0;
+            let mut actual_format = 0;
+            let mut count = 0;
+            let mut bytes_after = 0;
+            let mut data = null_mut();
+
+            xlib::XGetWindowProperty(
+                self.display.as_raw(),
+                self.window.as_raw(),
+                property,
+                0,
+                c_long::MAX,
+                xlib::True,
+                xlib::AnyPropertyType as xlib::Atom,
+                &mut actual_type,
+                &mut actual_format,
+                &mut count,
+                &mut bytes_after,
+                &mut data,
+            );
+
+            if data.is_null() {
+                return (actual_type, Vec::new());
+            }
+
+            let item_size = match actual_format {
+                16 => std::mem::size_of::<std::ffi::c_short>(),
+                32 => std::mem::size_of::<c_long>(),
+                _ => 1,
+            };
+            let value = std::slice::from_raw_parts(data, count as usize * item_size).to_vec();
+            xlib::XFree(data as *mut _);
+            (actual_type, value)
+        }
+    }
+
+    /// Answers a request of another client for a selection owned by this window.
+    fn handle_selection_request(&self, request: &xlib::XSelectionRequestEvent) {
+        let display = self.display.as_raw();
+        // Obsolete clients leave the property empty and expect the target to be used
+        let property = match request.property {
+            0 => request.target,
+            property => property,
+        };
+
+        let selections = self.selections.borrow();
+        let owned = selections
+            .iter()
+            .find(|owned| owned.selection == request.selection);
+
+        let converted = match owned {
+            // Pairs of targets and properties, failed conversions get None as property
+            Some(owned) if request.target == self.atom("MULTIPLE") && request.property != 0 => {
+                let mut pairs = self.window_atoms(request.requestor, property);
+                for pair in pairs.chunks_exact_mut(2) {
+                    if !self.convert_owned_selection(owned, request.requestor, pair[0], pair[1]) {
+                        pair[1] = 0;
+                    }
+                }
+                let pairs = pairs.iter().map(|atom| *atom as c_long).collect();
+                self.change_long_property(
+                    request.requestor,
+                    property,
+                    self.atom("ATOM_PAIR"),
+                    pairs,
+                );
+                true
+            }
+            Some(owned) => {
+                self.convert_owned_selection(owned, request.requestor, request.target, property)
+            }
+            None => false,
+        };
+
+        unsafe {
+            let mut notify = xlib::XSelectionEvent {
+                type_: xlib::SelectionNotify,
+                serial: 0,
+                send_event: xlib::True,
+                display,
+                requestor: request.requestor,
+                selection: request.selection,
+                target: request.target,
+                property: if converted { property } else { 0 },
+                time: request.time,
+            };
+            xlib::XSendEvent(
+                display,
+                request.requestor,
+                xlib::False,
+                NoEventMask as c_long,
+                &mut notify as *mut xlib::XSelectionEvent as *mut xlib::XEvent,
+            );
+            xlib::XFlush(display);
+        }
+    }
+
+    /// Writes an owned selection converted to the target to a property of the requestor,
+    /// returns false when it has no contents for the target.
+    fn convert_owned_selection(
+        &self,
+        owned: &OwnedSelection,
+        requestor: xlib::Window,
+        target: xlib::Atom,
+        property: xlib::Atom,
+    ) -> bool {
+        if property == 0 {
+            return false;
+        }
+
+        if target == self.atom("TARGETS") {
+            let mut targets = vec![
+                self.atom("TARGETS") as c_long,
+                self.atom("MULTIPLE") as c_long,
+                self.atom("TIMESTAMP") as c_long,
+            ];
+            targets.extend(owned.contents.iter().map(|(target, _)| *target as c_long));
+            self.change_long_property(requestor, property, xlib::XA_ATOM, targets);
+            return true;
+        }
+        if target == self.atom("TIMESTAMP") {
+            let time = vec![owned.time as c_long];
+            self.change_long_property(requestor, property, xlib::XA_INTEGER, time);
+            return true;
+        }
+
+        match owned.contents.iter().find(|(t, _)| *t == target) {
+            Some((target, data)) => {
+                self.send_selection_data(requestor, property, *target, data.clone());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces a 32-bit property of another window.
+    fn change_long_property(
+        &self,
+        window: xlib::Window,
+        property: xlib::Atom,
+        type_: xlib::Atom,
+        values: Vec<c_long>,
+    ) {
+        unsafe {
+            xlib::XChangeProperty(
+                self.display.as_raw(),
+                window,
+                property,
+                type_,
+                32,
+                xlib::PropModeReplace,
+                values.as_ptr() as *const c_uchar,
+                values.len() as c_int,
+            );
+        }
+    }
+
+    /// Writes selection contents to a property of the requestor, with INCR when they are large.
+    fn send_selection_data(
+        &self,
+        requestor: xlib::Window,
+        property: xlib::Atom,
+        target: xlib::Atom,
+        data: Vec<u8>,
+    ) {
+        let display = self.display.as_raw();
+
+        unsafe {
+            if data.len() <= self.max_selection_chunk() {
+                xlib::XChangeProperty(
+                    display,
+                    requestor,
+                    property,
+                    target,
+                    8,
+                    xlib::PropModeReplace,
+                    data.as_ptr(),
+                    data.len() as c_int,
+                );
+                return;
+            }
+
+            // The requestor deletes the property to ask for each chunk
+            xlib::XSelectInput(display, requestor, PropertyChangeMask as c_long);
+            let size = data.len() as c_long;
+            xlib::XChangeProperty(
+                display,
+                requestor,
+                property,
+                self.atom("INCR"),
+                32,
+                xlib::PropModeReplace,
+                &size as *const c_long as *const c_uchar,
+                1,
+            );
+        }
+
+        self.drop_abandoned_transfers();
+        self.incr_transfers.borrow_mut().push(IncrTransfer {
+            requestor,
+            property,
+            target,
+            data,
+            offset: 0,
+            updated: Instant::now(),
+        });
+    }
+
+    /// Sends the next chunk of an INCR transfer after the requestor deleted the property.
+    fn continue_incr_transfer(&self, requestor: xlib::Window, property: xlib::Atom) {
+        self.drop_abandoned_transfers();
+
+        let display = self.display.as_raw();
+        let chunk_size = self.max_selection_chunk();
+        let mut transfers = self.incr_transfers.borrow_mut();
+
+        let Some(index) = transfers
+            .iter()
+            .position(|t| t.requestor == requestor && t.property == property)
+        else {
+            return;
+        };
+
+        let transfer = &mut transfers[index];
+        let end = (transfer.offset + chunk_size).min(transfer.data.len());
+        let chunk = &transfer.data[transfer.offset..end];
+        unsafe {
+            xlib::XChangeProperty(
+                display,
+                requestor,
+                property,
+                transfer.target,
+                8,
+                xlib::PropModeReplace,
+                chunk.as_ptr(),
+                chunk.len() as c_int,
+            );
+        }
+
+        // The empty chunk after the last one ends the transfer
+        if chunk.is_empty() {
+            transfers.remove(index);
+            if !transfers.iter().any(|t| t.requestor == requestor) {
+                unsafe {
+                    xlib::XSelectInput(display, requestor, NoEventMask as c_long);
+                }
+            }
+        } else {
+            transfer.offset = end;
+            transfer.updated = Instant::now();
+        }
+        unsafe {
+            xlib::XFlush(display);
+        }
+    }
+
+    /// Forgets the INCR transfers whose requestor did not ask for a chunk within
+    /// SELECTION_TIMEOUT, e.g. because it was closed.
+    fn drop_abandoned_transfers(&self) {
+        let display = self.display.as_raw();
+        let mut transfers = self.incr_transfers.borrow_mut();
+
+        let (abandoned, active): (Vec<_>, Vec<_>) = transfers
+            .drain(..)
+            .partition(|t| t.updated.elapsed() > SELECTION_TIMEOUT);
+        *transfers = active;
+
+        for transfer in abandoned {
+            if !transfers.iter().any(|t| t.requestor == transfer.requestor) {
+                unsafe {
+                    xlib::XSelectInput(display, transfer.requestor, NoEventMask as c_long);
+                }
+            }
+        }
+    }
+
+    /// Largest property the X server accepts in one request, up to MAX_SELECTION_CHUNK.
+    fn max_selection_chunk(&self) -> usize {
+        let display = self.display.as_raw();
+        let words = match unsafe { xlib::XExtendedMaxRequestSize(display) } {
+            0 => unsafe { xlib::XMaxRequestSize(display) },
+            words => words,
+        };
+        // Leaves room for the ChangeProperty request header
+        (words as usize * 4 - 24).min(MAX_SELECTION_CHUNK)
+    }
+}
+
+/// Arguments of is_selection_event.
+struct SelectionWait {
+    window: xlib::Window,
+    event_type: c_int,
+    selection: xlib::Atom,
+    property: xlib::Atom,
+}
+
+/// Popups share the connection of their parent, so each run loop takes only the events of
+/// its own windows. The loops of other windows leave out the events of the popups.
+unsafe extern "C" fn is_run_loop_event(
+    _display: *mut xlib::Display,
+    event: *mut xlib::XEvent,
+    arg: xlib::XPointer,
+) -> xlib::Bool {
+    let window = &*(arg as *const RawWindow);
+    let target = (*event).any.window;
+
+    let matches = match window.popup {
+        true => target == window.window.as_raw() || target == window.gl_child.get(),
+        false => !window.popups.borrow().contains(&target),
+    };
+    matches as xlib::Bool
+}
+
+unsafe extern "C" fn is_window_event(
+    _display: *mut xlib::Display,
+    event: *mut xlib::XEvent,
+    arg: xlib::XPointer,
+) -> xlib::Bool {
+    ((*event).any.window == *(arg as *const xlib::Window)) as xlib::Bool
+}
+
+unsafe extern "C" fn is_selection_event(
+    _display: *mut xlib::Display,
+    event: *mut xlib::XEvent,
+    arg: xlib::XPointer,
+) -> xlib::Bool {
+    let wait = &*(arg as *const SelectionWait);
+    let event = &*event;
+
+    let matches = match event.get_type() {
+        // The run loop handles the SelectionNotify events of XdndSelection
+        xlib::SelectionNotify if wait.event_type == xlib::SelectionNotify => {
+            event.selection.requestor == wait.window && event.selection.selection == wait.selection
+        }
+        xlib::PropertyNotify if wait.event_type == xlib::PropertyNotify => {
+            event.property.window == wait.window
+                && event.property.atom == wait.property
+                && event.property.state == xlib::PropertyNewValue
+        }
+        _ => false,
+    };
+    matches as xlib::Bool
+}
+
+/// Drag of another client over the window, announced by XdndEnter.
+struct XdndDrag {
+    source: xlib::Window,
+    /// Files read from the source, None until the uri-list requested on the first XdndPosition
+    /// arrived.
+    paths: Option<Vec<PathBuf>>,
+    /// Time of the XdndPosition the uri-list was requested with.
+    request_time: Option<xlib::Time>,
+    /// Part of the uri-list received so far when the source sends it with INCR.
+    incr_data: Option<Vec<u8>>,
+    position: PhysicalPosition,
+    source_actions: Vec<DropAction>,
+    /// Action accepted by the callback, None while the drop is rejected.
+    action: Option<DropAction>,
+}
+
+impl RawWindow {
+    pub(crate) fn set_drag_action(&self, action: Option<DropAction>) {
+        if let Some(drag) = self.drag.borrow_mut().as_mut() {
+            drag.action = action;
+        }
+    }
+
+    pub(crate) fn drag_source_actions(&self) -> Vec<DropAction> {
+        self.drag
+            .borrow()
+            .as_ref()
+            .map(|drag| drag.source_actions.clone())
+            .unwrap_or_default()
+    }
+
+    /// Handles the XDND client messages of a drag source, delivers the events they correspond to
+    /// and answers the source with the action accepted by the callback.
+    fn handle_xdnd_message<F>(
+        &self,
+        message: &xlib::XClientMessageEvent,
+        callback: &mut F,
+        control_flow: &mut ControlFlow,
+    ) where
+        F: FnMut(WindowEvent, &mut ControlFlow),
+    {
+        let data = message.data.as_longs();
+        let source = data[0] as xlib::Window;
+
+        if message.message_type == self.atom("XdndEnter") {
+            // Version in the high byte, the first bit is set if XdndTypeList has more types
+            let version = data[1] >> 24;
+            let types = match data[1] & 1 {
+                0 => data[2..5].iter().map(|t| *t as xlib::Atom).collect(),
+                _ => self.window_atoms(source, self.atom("XdndTypeList")),
+            };
+
+            // Versions before 3 use other messages, only files are taken
+            *self.drag.borrow_mut() = (version >= 3 && types.contains(&self.atom("text/uri-list")))
+                .then(|| XdndDrag {
+                    source,
+                    paths: None,
+                    request_time: None,
+                    incr_data: None,
+                    position: PhysicalPosition::default(),
+                    source_actions: Vec::new(),
+                    action: None,
+                });
+        } else if message.message_type == self.atom("XdndPosition") {
+            if let Some(event) = self.update_xdnd_drag(data) {
+                callback(event, control_flow);
+            }
+            self.send_xdnd_status(source);
+        } else if message.message_type == self.atom("XdndLeave") {
+            let drag = self.drag.borrow_mut().take();
+            if drag.is_some_and(|drag| drag.paths.is_some()) {
+                callback(WindowEvent::DragLeft, control_flow);
+            }
+        } else if message.message_type == self.atom("XdndDrop") {
+            let mut finished = [self.window.as_raw() as c_long, 0, 0, 0, 0];
+
+            let drag = self.drag.borrow_mut().take();
+            if let Some(XdndDrag {
+                paths: Some(paths),
+                position,
+                action: Some(action),
+                ..
+            }) = drag
+            {
+                callback(WindowEvent::DragDropped { paths, position }, control_flow);
+                // Whether the drop was accepted and the action taken, read since version 5
+                finished[1] = 1;
+                finished[2] = self.atom(xdnd_action_name(action)) as c_long;
+            }
+            self.send_xdnd_message(source, "XdndFinished", finished);
+        }
+    }
+
+    /// Moves the drag to the position of an XdndPosition message and returns the event for it.
+    /// The files are requested on the first position, as the source only offers them during the
+    /// drag, and the drop is rejected until they arrived.
+    fn update_xdnd_drag(&self, data: &[c_long]) -> Option<WindowEvent> {
+        let mut drag = self.drag.borrow_mut();
+        let current = drag.as_mut()?;
+
+        // Root coordinates packed as x << 16 | y, the proposed action is in the last item
+        let (x, y) = self.root_position();
+        let position = PhysicalPosition::new(
+            (data[2] >> 16 & 0xffff) as i32 - x,
+            (data[2] & 0xffff) as i32 - y,
+        );
+        current.position = position;
+        current.source_actions = self.xdnd_source_actions(current.source, data[4] as xlib::Atom);
+
+        if current.paths.is_some() {
+            return Some(WindowEvent::DragMoved { position });
+        }
+
+        if current.request_time.is_none() {
+            let time = data[3] as xlib::Time;
+            let property = self.atom("GWL_XDND");
+            unsafe {
+                let display = self.display.as_raw();
+                xlib::XDeleteProperty(display, self.window.as_raw(), property);
+                xlib::XConvertSelection(
+                    display,
+                    self.atom("XdndSelection"),
+                    self.atom("text/uri-list"),
+                    property,
+                    self.window.as_raw(),
+                    time,
+                );
+            }
+            self.display.flush();
+            current.request_time = Some(time);
+        }
+        None
+    }
+
+    /// Handles the SelectionNotify answering the request for the uri-list of the drag
+    /// and returns DragEntered if it holds the whole list.
+    fn receive_xdnd_selection(&self, notify: &xlib::XSelectionEvent) -> Option<WindowEvent> {
+        let mut drag = self.drag.borrow_mut();
+        // Answers to the requests of earlier drags are dropped
+        let current = drag
+            .as_mut()
+            .filter(|drag| drag.paths.is_none() && drag.request_time == Some(notify.time))?;
+
+        let property = self.atom("GWL_XDND");
+        if notify.property != property {
+            // The source could not convert the selection, the drag is rejected until it leaves
+            *drag = None;
+            return None;
+        }
+
+        let (type_, uri_list) = self.take_property(property);
+        if type_ == self.atom("INCR") {
+            // Deleting the INCR property asked for the first chunk
+            current.incr_data = Some(Vec::new());
+            return None;
+        }
+        finish_xdnd_transfer(&mut drag, &uri_list)
+    }
+
+    /// Appends a chunk of an INCR transfer of the uri-list, an empty chunk ends the transfer.
+    fn receive_xdnd_chunk(&self) -> Option<WindowEvent> {
+        let mut drag = self.drag.borrow_mut();
+        // New values written before the SelectionNotify are not part of the transfer
+        let incr_data = drag.as_mut()?.incr_data.as_mut()?;
+
+        let (_, chunk) = self.take_property(self.atom("GWL_XDND"));
+        if !chunk.is_empty() {
+            incr_data.extend(chunk);
+            return None;
+        }
+        let uri_list = std::mem::take(incr_data);
+        finish_xdnd_transfer(&mut drag, &uri_list)
+    }
+
+    /// Delivers the DragEntered of a finished transfer and tells the source whether the callback
+    /// accepted the drop, since the source only asks again when the pointer moves.
+    fn deliver_xdnd_entered<F>(
+        &self,
+        event: Option<WindowEvent>,
+        callback: &mut F,
+        control_flow: &mut ControlFlow,
+    ) where
+        F: FnMut(WindowEvent, &mut ControlFlow),
+    {
+        if let Some(event) = event {
+            callback(event, control_flow);
+            let source = self.drag.borrow().as_ref().map(|drag| drag.source);
+            if let Some(source) = source {
+                self.send_xdnd_status(source);
+            }
+        }
+    }
+
+    /// Returns the action proposed by the source, or the actions it lists for XdndActionAsk.
+    fn xdnd_source_actions(&self, source: xlib::Window, proposed: xlib::Atom) -> Vec<DropAction> {
+        let mut actions = vec![proposed];
+        if proposed == self.atom("XdndActionAsk") {
+            actions.extend(self.window_atoms(source, self.atom("XdndActionList")));
+        }
+
+        [DropAction::Copy, DropAction::Move, DropAction::Link]
+            .into_iter()
+            .filter(|action| actions.contains(&self.atom(xdnd_action_name(*action))))
+            .collect()
+    }
+
+    fn send_xdnd_status(&self, source: xlib::Window) {
+        let action = self.drag.borrow().as_ref().and_then(|drag| drag.action);
+
+        // Accepted in the first bit, the second bit asks for positions everywhere in the window
+        let status = match action {
+            Some(_) => 0b11,
+            None => 0b10,
+        };
+        let action = action.map_or(0, |action| self.atom(xdnd_action_name(action)) as c_long);
+        self.send_xdnd_message(
+            source,
+            "XdndStatus",
+            [self.window.as_raw() as c_long, status, 0, 0, action],
+        );
+    }
+
+    /// Sends an XDND client message from this window to the source of a drag.
+    fn send_xdnd_message(&self, source: xlib::Window, message_type: &str, data: [c_long; 5]) {
+        let display = self.display.as_raw();
+
+        unsafe {
+            let mut event = xlib::XClientMessageEvent {
+                type_: xlib::ClientMessage,
+                serial: 0,
+                send_event: xlib::True,
+                display,
+                window: source,
+                message_type: self.atom(message_type),
+                format: 32,
+                data: xlib::ClientMessageData::new(),
+            };
+            event.data.as_longs_mut().copy_from_slice(&data);
+
+            xlib::XSendEvent(
+                display,
+                source,
+                xlib::False,
+                xlib::NoEventMask,
+                &mut xlib::XEvent::from(event),
+            );
+            xlib::XFlush(display);
+        }
+    }
+
+    /// Reads an atom list property of another window, e.g. of the source of a drag or the
+    /// ATOM_PAIR list of a MULTIPLE selection request.
+    fn window_atoms(&self, window: xlib::Window, property: xlib::Atom) -> Vec<xlib::Atom> {
+        unsafe {
+            let mut actual_type = 0;
+            let mut actual_format = 0;
+            let mut count = 0;
+            let mut bytes_after = 0;
+            let mut data = null_mut();
+
+            xlib::XGetWindowProperty(
+                self.display.as_raw(),
+                window,
+                property,
+                0,
+                c_long::MAX,
+                xlib::False,
+                xlib::AnyPropertyType as xlib::Atom,
+                &mut actual_type,
+                &mut actual_format,
+                &mut count,
+                &mut bytes_after,
+                &mut data,
+            );
+
+            if data.is_null() {
+                return Vec::new();
+            }
+
+            let atoms = match actual_format {
+                32 => {
+                    std::slice::from_raw_parts(data as *const xlib::Atom, count as usize).to_vec()
+                }
+                _ => Vec::new(),
+            };
+            xlib::XFree(data as *mut _);
+            atoms
+        }
+    }
+}
+
+/// Takes the files of a received uri-list into the drag and returns DragEntered for them.
+fn finish_xdnd_transfer(drag: &mut Option<XdndDrag>, uri_list: &[u8]) -> Option<WindowEvent> {
+    let paths = parse_uri_list(uri_list);
+    if paths.is_empty() {
+        // Nothing that can be dropped, the drag is rejected until it leaves
+        *drag = None;
+        return None;
+    }
+
+    let current = drag.as_mut()?;
+    current.incr_data = None;
+    current.paths = Some(paths.clone());
+    Some(WindowEvent::DragEntered {
+        paths,
+        position: current.position,
+    })
+}
+
+fn xdnd_action_name(action: DropAction) -> &'static str {
+    match action {
+        DropAction::Copy => "XdndActionCopy",
+        DropAction::Move => "XdndActionMove",
+        DropAction::Link => "XdndActionLink",
+    }
+}
+
+#[cfg(feature = "gl")]
+const GLX_FRAMEBUFFER_SRGB_CAPABLE_ARB: c_int = 0x20B2;
+
+#[cfg(feature = "gl")]
+type GlXCreateContextAttribsARB = unsafe extern "C" fn(
+    *mut xlib::Display,
+    glx::GLXFBConfig,
+    glx::GLXContext,
+    xlib::Bool,
+    *const c_int,
+) -> glx::GLXContext;
+#[cfg(feature = "gl")]
+type GlXSwapIntervalEXT = unsafe extern "C" fn(*mut xlib::Display, glx::GLXDrawable, c_int);
+#[cfg(feature = "gl")]
+type GlXSwapIntervalMESA = unsafe extern "C" fn(c_uint) -> c_int;
+
+#[cfg(feature = "gl")]
+pub struct RawGlContext {
+    display: *mut xlib::Display,
+    /// The window, or the child window created for the visual of the framebuffer configuration.
+    window: xlib::Window,
+    context: glx::GLXContext,
+    /// Child window and its colormap, shared with the window so that it follows its size.
+    child: Option<(Rc<Cell<xlib::Window>>, xlib::Colormap)>,
+}
+
+#[cfg(feature = "gl")]
+impl IGlContext for RawGlContext {
+    fn new(window: &RawWindow, attributes: &GlAttributes) -> Result<Self, GlError> {
+        unsafe {
+            let display = window.display.as_raw();
+            let screen = xlib::XDefaultScreen(display);
+            if !glx_extension_supported(display, screen, "GLX_ARB_create_context_profile") {
+                return Err(GlError::NotSupported(
+                    "GLX_ARB_create_context_profile".to_owned(),
+                ));
+            }
+            let window_visual_id = window.visual_id;
+            let (config, visual_info) =
+                choose_fb_config(display, screen, window_visual_id, attributes)?;
+
+            let profile = match attributes.profile {
+                GlProfile::Core => glx::arb::GLX_CONTEXT_CORE_PROFILE_BIT_ARB,
+                GlProfile::Compatibility => glx::arb::GLX_CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB,
+            };
+            let flags = match attributes.debug {
+                true => glx::arb::GLX_CONTEXT_DEBUG_BIT_ARB,
+                false => 0,
+            };
+            let context_attributes = [
+                glx::arb::GLX_CONTEXT_MAJOR_VERSION_ARB,
+                attributes.version.0 as c_int,
+                glx::arb::GLX_CONTEXT_MINOR_VERSION_ARB,
+                attributes.version.1 as c_int,
+                glx::arb::GLX_CONTEXT_PROFILE_MASK_ARB,
+                profile,
+                glx::arb::GLX_CONTEXT_FLAGS_ARB,
+                flags,
+                0,
+            ];
+
+            let create_context: GlXCreateContextAttribsARB =
+                std::mem::transmute(glx_proc_address("glXCreateContextAttribsARB"));
+            // Unsupported versions are reported with an X error (GLXBadFBConfig or BadMatch)
+            REQUEST_FAILED.store(false, Ordering::SeqCst);
+            let previous_handler = xlib::XSetErrorHandler(Some(request_failed));
+            let context = create_context(
+                display,
+                config,
+                null_mut(),
+                xlib::True,
+                context_attributes.as_ptr(),
+            );
+            xlib::XSync(display, xlib::False);
+            xlib::XSetErrorHandler(previous_handler);
+
+            if context.is_null() || REQUEST_FAILED.load(Ordering::SeqCst) {
+                return Err(GlError::CreationFailed(format!(
+                    "OpenGL {}.{} is not supported",
+                    attributes.version.0, attributes.version.1
+                )));
+            }
+
+            // Configurations with multisampling or sRGB mostly have other visuals than the
+            // window, their framebuffer is a child window of that visual covering the window
+            if visual_info.visualid == window_visual_id {
+                return Ok(Self {
+                    display,
+                    window: window.window.as_raw(),
+                    context,
+                    child: None,
+                });
+            }
+
+            let parent = window.window.as_raw();
+            let colormap =
+                xlib::XCreateColormap(display, parent, visual_info.visual, xlib::AllocNone);
+            let mut child_attributes: xlib::XSetWindowAttributes = std::mem::zeroed();
+            child_attributes.colormap = colormap;
+            child_attributes.border_pixel = 0;
+            // Exposures of the child are reported like those of the window, input propagates
+            child_attributes.event_mask = ExposureMask as c_long;
+            let size = window.get_window_size();
+            let child = xlib::XCreateWindow(
+                display,
+                parent,
+                0,
+                0,
+                size.width.max(1),
+                size.height.max(1),
+                0,
+                visual_info.depth,
+                xlib::InputOutput as c_uint,
+                visual_info.visual,
+                xlib::CWColormap | xlib::CWBorderPixel | xlib::CWEventMask,
+                &mut child_attributes,
+            );
+            // Below children embedded into the window
+            xlib::XLowerWindow(display, child);
+            xlib::XMapWindow(display, child);
+            xlib::XFlush(display);
+            window.gl_child.set(child);
+
+            Ok(Self {
+                display,
+                window: child,
+                context,
+                child: Some((window.gl_child.clone(), colormap)),
+            })
+        }
+    }
+
+    fn make_current(&self) -> Result<(), GlError> {
+        unsafe {
+            match glx::glXMakeCurrent(self.display, self.window, self.context) {
+                0 => Err(GlError::MakeCurrentFailed),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    fn swap_buffers(&self) {
+        unsafe {
+            glx::glXSwapBuffers(self.display, self.window);
+        }
+    }
+
+    fn set_swap_interval(&self, interval: i32) {
+        unsafe {
+            let screen = xlib::XDefaultScreen(self.display);
+            if glx_extension_supported(self.display, screen, "GLX_EXT_swap_control") {
+                let swap_interval: GlXSwapIntervalEXT =
+                    std::mem::transmute(glx_proc_address("glXSwapIntervalEXT"));
+                swap_interval(self.display, self.window, interval);
+            } else if glx_extension_supported(self.display, screen, "GLX_MESA_swap_control") {
+                let swap_interval: GlXSwapIntervalMESA =
+                    std::mem::transmute(glx_proc_address("glXSwapIntervalMESA"));
+                swap_interval(interval.max(0) as c_uint);
+            }
+        }
+    }
+
+    fn get_proc_address(&self, name: &str) -> *const c_void {
+        glx_proc_address(name)
+    }
+
+    fn size(&self) -> PhysicalSize {
+        let (mut width, mut height) = (0, 0);
+        unsafe {
+            glx::glXQueryDrawable(self.display, self.window, glx::GLX_WIDTH, &mut width);
+            glx::glXQueryDrawable(self.display, self.window, glx::GLX_HEIGHT, &mut height);
+        }
+        PhysicalSize::new(width, height)
+    }
+
+    fn resize(&self, _size: PhysicalSize) {
+        // The GLX drawable is the window itself
+    }
+}
+
+#[cfg(feature = "gl")]
+impl Drop for RawGlContext {
+    fn drop(&mut self) {
+        unsafe {
+            if glx::glXGetCurrentContext() == self.context {
+                glx::glXMakeCurrent(self.display, 0, null_mut());
+            }
+            glx::glXDestroyContext(self.display, self.context);
+
+            if let Some((gl_child, colormap)) = &self.child {
+                gl_child.set(0);
+                xlib::XDestroyWindow(self.display, self.window);
+                xlib::XFreeColormap(self.display, *colormap);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "gl")]
+unsafe fn glx_extension_supported(display: *mut xlib::Display, screen: c_int, name: &str) -> bool {
+    let extensions = glx::glXQueryExtensionsString(display, screen);
+    !extensions.is_null()
+        && CStr::from_ptr(extensions)
+            .to_string_lossy()
+            .split(' ')
+            .any(|extension| extension == name)
+}
+
+#[cfg(feature = "gl")]
+fn glx_proc_address(name: &str) -> *const c_void {
+    match CString::new(name) {
+        Ok(name) => unsafe {
+            match glx::glXGetProcAddressARB(name.as_ptr() as *const c_uchar) {
+                Some(function) => function as *const c_void,
+                None => null(),
+            }
+        },
+        Err(_) => null(),
+    }
+}
+
+/// Returns a framebuffer configuration with the attributes and its visual.
+/// Configurations of the visual of the window are preferred, as they can render into it directly.
+#[cfg(feature = "gl")]
+unsafe fn choose_fb_config(
+    display: *mut xlib::Display,
+    screen: c_int,
+    window_visual_id: xlib::VisualID,
+    attributes: &GlAttributes,
+) -> Result<(glx::GLXFBConfig, xlib::XVisualInfo), GlError> {
+    let mut config_attributes = vec![
+        glx::GLX_X_RENDERABLE,
+        xlib::True,
+        glx::GLX_DRAWABLE_TYPE,
+        glx::GLX_WINDOW_BIT,
+        glx::GLX_RENDER_TYPE,
+        glx::GLX_RGBA_BIT,
+        glx::GLX_DOUBLEBUFFER,
+        xlib::True,
+        glx::GLX_RED_SIZE,
+        8,
+        glx::GLX_GREEN_SIZE,
+        8,
+        glx::GLX_BLUE_SIZE,
+        8,
+        glx::GLX_DEPTH_SIZE,
+        attributes.depth_bits as c_int,
+        glx::GLX_STENCIL_SIZE,
+        attributes.stencil_bits as c_int,
+    ];
+    if attributes.samples > 0 {
+        let samples = attributes.samples as c_int;
+        config_attributes.extend([glx::GLX_SAMPLE_BUFFERS, 1, glx::GLX_SAMPLES, samples]);
+    }
+    if attributes.srgb {
+        config_attributes.extend([GLX_FRAMEBUFFER_SRGB_CAPABLE_ARB, xlib::True]);
+    }
+    config_attributes.push(0);
+
+    let mut count = 0;
+    let configs = glx::glXChooseFBConfig(display, screen, config_attributes.as_ptr(), &mut count);
+    if configs.is_null() {
+        return Err(GlError::NoMatchingConfig);
+    }
+    // Sorted from the best match, configurations without a visual cannot render into windows
+    let candidates = std::slice::from_raw_parts(configs, count as usize);
+    let visual_id = |config: glx::GLXFBConfig| {
+        let mut id = 0;
+        glx::glXGetFBConfigAttrib(display, config, glx::GLX_VISUAL_ID, &mut id);
+        id as xlib::VisualID
+    };
+    let config = candidates
+        .iter()
+        .copied()
+        .find(|config| visual_id(*config) == window_visual_id)
+        .or_else(|| {
+            candidates
+                .iter()
+                .copied()
+                .find(|config| visual_id(*config) != 0)
+        });
+    xlib::XFree(configs as *mut _);
+
+    let config = config.ok_or(GlError::NoMatchingConfig)?;
+    let visual_info = glx::glXGetVisualFromFBConfig(display, config);
+    if visual_info.is_null() {
+        return Err(GlError::NoMatchingConfig);
+    }
+    let visual = *visual_info;
+    xlib::XFree(visual_info as *mut _);
+    Ok((config, visual))
+}
+
+/// Highest XDND protocol version supported as a drop target.
+const XDND_VERSION: c_long = 5;
+
+// XEmbed protocol version, _XEMBED_INFO flags and messages
+const XEMBED_VERSION: c_long = 0;
+const XEMBED_MAPPED: c_long = 1 << 0;
+const XEMBED_EMBEDDED_NOTIFY: c_long = 0;
+const XEMBED_WINDOW_ACTIVATE: c_long = 1;
+const XEMBED_WINDOW_DEACTIVATE: c_long = 2;
+const XEMBED_FOCUS_IN: c_long = 4;
+const XEMBED_FOCUS_OUT: c_long = 5;
+
+fn select_input(display: &Display, window: &Window) {
+    unsafe {
+        xlib::XSelectInput(
+            display.as_raw(),
+            window.as_raw(),
+            ExposureMask as c_long | StructureNotifyMask as c_long | PropertyChangeMask as c_long,
+        );
+    }
+}
+
+unsafe fn atom_name(display: *mut xlib::Display, atom: xlib::Atom) -> Option<String> {
+    let name = xlib::XGetAtomName(display, atom);
+    if name.is_null() {
+        return None;
+    }
+    let string = CStr::from_ptr(name).to_string_lossy().into_owned();
+    xlib::XFree(name as *mut _);
+    Some(string)
+}
+
+/// Returns the index of the Xinerama screen with the geometry of the monitor.
+fn xinerama_index(display: *mut xlib::Display, monitor: &MonitorHandle) -> Option<c_long> {
+    let xinerama = XINERAMA.as_ref()?;
+    unsafe {
+        if (xinerama.is_active)(display) == 0 {
+            return None;
+        }
+        let mut count = 0;
+        let screens = (xinerama.query_screens)(display, &mut count);
+        if screens.is_null() {
+            return None;
+        }
+        let index = std::slice::from_raw_parts(screens, count as usize)
+            .iter()
+            .find(|screen| {
+                (screen.x_org as i32, screen.y_org as i32) == monitor.position
+                    && (screen.width as u32, screen.height as u32) == monitor.size
+            })
+            .map(|screen| screen.screen_number as c_long);
+        xlib::XFree(screens as *mut _);
+        index
+    }
+}
+
+/// Returns the scale factor configured for the whole desktop,
+/// read from Xft/DPI of the XSETTINGS manager or from the Xft.dpi resource.
+fn desktop_scale_factor(display: *mut xlib::Display) -> Option<f64> {
+    unsafe {
+        let valid = |dpi: &f64| dpi.is_finite() && *dpi > 0.0;
+        xsettings_dpi(display)
+            .filter(valid)
+            .or_else(|| xft_dpi(display).filter(valid))
+            .map(|dpi| dpi / 96.0)
+    }
+}
+
+unsafe fn xft_dpi(display: *mut xlib::Display) -> Option<f64> {
+    let resources = xlib::XResourceManagerString(display);
+    if resources.is_null() {
+        return None;
+    }
+
+    CStr::from_ptr(resources)
+        .to_string_lossy()
+        .lines()
+        .find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            match key.trim() {
+                "Xft.dpi" => value.trim().parse().ok(),
+                _ => None,
+            }
+        })
+}
+
+unsafe fn xsettings_dpi(display: *mut xlib::Display) -> Option<f64> {
+    let selection = CString::new(format!("_XSETTINGS_S{}", xlib::XDefaultScreen(display))).unwrap();
+    let selection = xlib::XInternAtom(display, selection.as_ptr(), xlib::False);
+    let owner = xlib::XGetSelectionOwner(display, selection);
+    if owner == 0 {
+        return None;
+    }
+
+    let property = CString::new("_XSETTINGS_SETTINGS").unwrap();
+    let property = xlib::XInternAtom(display, property.as_ptr(), xlib::False);
+
+    let mut actual_type = 0;
+    let mut actual_format = 0;
+    let mut count = 0;
+    let mut bytes_after = 0;
+    let mut data = std::ptr::null_mut();
+    xlib::XGetWindowProperty(
+        display,
+        owner,
+        property,
+        0,
+        c_long::MAX,
+        xlib::False,
+        property,
+        &mut actual_type,
+        &mut actual_format,
+        &mut count,
+        &mut bytes_after,
+        &mut data,
+    );
+    if data.is_null() {
+        return None;
+    }
+
+    let dpi = match actual_format {
+        8 => parse_xsettings_dpi(std::slice::from_raw_parts(data, count as usize)),
+        _ => None,
+    };
+    xlib::XFree(data as *mut _);
+    dpi
+}
+
+/// Collects the modes an output supports and its current mode from the screen resources.
+unsafe fn output_modes(
+    xrandr: &XRandr,
+    display: *mut xlib::Display,
+    resources: *mut xrandr::XRRScreenResources,
+    output: xrandr::RROutput,
+    bit_depth: u16,
+) -> (Vec<ModeInfo>, Option<ModeInfo>) {
+    let info = (xrandr.get_output_info)(display, resources, output);
+    if info.is_null() {
+        return (Vec::new(), None);
+    }
+
+    let all_modes = std::slice::from_raw_parts((*resources).modes, (*resources).nmode as usize);
+    let modes = std::slice::from_raw_parts((*info).modes, (*info).nmode as usize)
+        .iter()
+        .filter_map(|id| all_modes.iter().find(|mode| mode.id == *id))
+        .map(|mode| {
+            let refresh_rate_millihertz = match mode.hTotal as u64 * mode.vTotal as u64 {
+                0 => 0,
+                dots => (mode.dotClock * 1000 / dots) as u32,
+            };
+            ModeInfo {
+                id: mode.id,
+                size: (mode.width, mode.height),
+                bit_depth,
+                refresh_rate_millihertz,
+            }
+        })
+        .collect::<Vec<ModeInfo>>();
+
+    let current_mode = match (*info).crtc {
+        0 => None,
+        crtc => {
+            let crtc_info = (xrandr.get_crtc_info)(display, resources, crtc);
+            if crtc_info.is_null() {
+                None
+            } else {
+                let id = (*crtc_info).mode;
+                (xrandr.free_crtc_info)(crtc_info);
+                modes.iter().find(|mode| mode.id == id).copied()
+            }
+        }
+    };
+
+    (xrandr.free_output_info)(info);
+    (modes, current_mode)
+}
+
+#[cfg(feature = "vulkan")]
+const VK_STRUCTURE_TYPE_XLIB_SURFACE_CREATE_INFO_KHR: i32 = 1000004000;
+
+#[cfg(feature = "vulkan")]
+#[repr(C)]
+struct VkXlibSurfaceCreateInfoKHR {
+    s_type: i32,
+    p_next: *const c_void,
+    flags: u32,
+    dpy: *mut xlib::Display,
+    window: xlib::Window,
+}
+
+#[cfg(feature = "vulkan")]
+type VkCreateXlibSurfaceKHR = unsafe extern "system" fn(
+    VkInstance,
+    *const VkXlibSurfaceCreateInfoKHR,
+    *const c_void,
+    *mut VkSurfaceKHR,
+) -> VkResult;
+
+#[cfg(feature = "vulkan")]
+impl RawWindow {
+    pub(crate) fn required_vulkan_extensions() -> &'static [&'static str] {
+        &["VK_KHR_surface", "VK_KHR_xlib_surface"]
+    }
+
+    pub(crate) unsafe fn create_vulkan_surface(
+        &self,
+        instance: VkInstance,
+    ) -> Result<VkSurfaceKHR, VulkanError> {
+        let create_surface: VkCreateXlibSurfaceKHR =
+            std::mem::transmute(instance_proc_address(instance, "vkCreateXlibSurfaceKHR")?);
+        let info = VkXlibSurfaceCreateInfoKHR {
+            s_type: VK_STRUCTURE_TYPE_XLIB_SURFACE_CREATE_INFO_KHR,
+            p_next: null(),
+            flags: 0,
+            dpy: self.display.as_raw(),
+            window: self.window.as_raw(),
+        };
+
+        let mut surface = 0;
+        match create_surface(instance, &info, null(), &mut surface) {
+            VK_SUCCESS => Ok(surface),
+            result => Err(VulkanError::SurfaceCreationFailed(result)),
+        }
+    }
+}
+
+unsafe impl HasRawWindowHandle for RawWindow {
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        let mut handle = raw_window_handle::XlibWindowHandle::empty();
+        handle.window = self.window.as_raw();
+        handle.visual_id = self.visual_id;
+        RawWindowHandle::Xlib(handle)
+    }
+}
+
+unsafe fn window_visual_id(display: *mut xlib::Display, window: xlib::Window) -> xlib::VisualID {
+    let mut attributes = MaybeUninit::<xlib::XWindowAttributes>::zeroed();
+    xlib::XGetWindowAttributes(display, window, attributes.as_mut_ptr());
+    xlib::XVisualIDFromVisual(attributes.assume_init().visual)
+}
+
+unsafe impl HasRawDisplayHandle for RawWindow {
+    fn raw_display_handle(&self) -> RawDisplayHandle {
+        let mut handle = raw_window_handle::XlibDisplayHandle::empty();
+        handle.display = self.display.as_raw() as *mut _;
+        handle.screen = unsafe { xlib::XDefaultScreen(self.display.as_raw()) };
+        RawDisplayHandle::Xlib(handle)
     }
 }
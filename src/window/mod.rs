@@ -3,23 +3,104 @@
 //! Of course, it is also possible to use the window as-is without making any changes.
 //! See the example for more details.
 
-#[cfg(target_os = "linux")]
+#[cfg(all(feature = "headless", any(feature = "xcb", feature = "wayland")))]
+compile_error!(
+    "the \"headless\" feature cannot be combined with the \"xcb\" or \"wayland\" backend"
+);
+#[cfg(all(feature = "xcb", feature = "wayland"))]
+compile_error!("the \"xcb\" and \"wayland\" backends cannot be enabled at the same time");
+
+#[cfg(all(
+    target_os = "linux",
+    not(any(feature = "xcb", feature = "wayland", feature = "headless"))
+))]
 pub mod linux;
-
-#[cfg(target_os = "linux")]
+#[cfg(all(
+    target_os = "linux",
+    not(any(feature = "xcb", feature = "wayland", feature = "headless"))
+))]
+mod xlib_ext;
+#[cfg(all(
+    target_os = "linux",
+    not(any(feature = "xcb", feature = "wayland", feature = "headless"))
+))]
 pub use self::linux::*;
+
+#[cfg(all(
+    target_os = "linux",
+    feature = "xcb",
+    not(any(feature = "wayland", feature = "headless"))
+))]
+pub mod xcb;
+#[cfg(all(
+    target_os = "linux",
+    feature = "xcb",
+    not(any(feature = "wayland", feature = "headless"))
+))]
+pub use self::xcb::*;
+
+#[cfg(all(target_os = "linux", feature = "wayland", not(feature = "headless")))]
+pub mod wayland;
+#[cfg(all(target_os = "linux", feature = "wayland", not(feature = "headless")))]
+pub use self::wayland::*;
+#[cfg(not(feature = "headless"))]
+use raw_window_handle::{HasRawDisplayHandle, RawDisplayHandle};
 use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use std::cell::RefMut;
+use std::path::PathBuf;
 
-#[cfg(target_os = "macos")]
+#[cfg(all(target_os = "macos", not(feature = "headless")))]
 pub mod macos;
-#[cfg(target_os = "macos")]
+#[cfg(all(target_os = "macos", not(feature = "headless")))]
 pub use self::macos::*;
 
-#[cfg(target_os = "windows")]
+#[cfg(all(target_os = "windows", not(feature = "headless")))]
 pub mod windows;
-#[cfg(target_os = "windows")]
+#[cfg(all(target_os = "windows", not(feature = "headless")))]
 pub use self::windows::*;
 
+#[cfg(feature = "headless")]
+pub mod headless;
+#[cfg(feature = "headless")]
+pub use self::headless::*;
+
+mod clipboard;
+pub use self::clipboard::*;
+
+mod dnd;
+pub use self::dnd::*;
+
+mod dpi;
+pub use self::dpi::*;
+
+mod icon;
+pub use self::icon::*;
+
+mod monitor;
+pub use self::monitor::*;
+
+mod popup;
+pub use self::popup::*;
+
+mod record;
+pub use self::record::*;
+
+#[cfg(feature = "gl")]
+mod gl;
+#[cfg(feature = "gl")]
+pub use self::gl::*;
+
+#[cfg(all(target_os = "linux", feature = "gl"))]
+mod egl;
+
+#[cfg(feature = "vulkan")]
+mod vulkan;
+#[cfg(feature = "vulkan")]
+pub use self::vulkan::*;
+
+#[cfg(feature = "rwh_06")]
+mod handle;
+
 /// Trait to build a structure to supplement the events that occur during window creation.
 pub trait WindowBuildAction {
     /// It is called first when WindowBuilder::build() is executed.
@@ -42,13 +123,95 @@ impl WindowBuildAction for DefWindowBuildAction {
     fn window_created(&mut self, _handle: &WindowInstance) {}
 }
 
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum WindowEvent {
-    Expose,
+    /// The given region of the window, in physical pixels, has to be redrawn.
+    Expose(Rect),
 
     KeyDown(u32),
     KeyUp(u32),
 
     CloseRequested,
+
+    /// The scale factor of the window changed, e.g. because it was moved to another monitor.
+    ScaleFactorChanged(f64),
+
+    /// The popup was dismissed, e.g. by a click outside of it or the Escape key.
+    /// The run loop of the popup returns after this event.
+    PopupDone,
+
+    /// The window gained (true) or lost (false) the keyboard focus.
+    Focused(bool),
+    /// The toplevel window containing an embedded window was activated (true) or deactivated (false).
+    Activated(bool),
+
+    /// Files from another application were dragged into the window,
+    /// at the given position in physical pixels.
+    /// The drop is rejected unless the callback calls ```Window::accept_drag```.
+    DragEntered {
+        paths: Vec<PathBuf>,
+        position: PhysicalPosition,
+    },
+    /// The dragged files were moved within the window.
+    DragMoved {
+        position: PhysicalPosition,
+    },
+    /// The dragged files were dropped onto the window with the accepted action.
+    DragDropped {
+        paths: Vec<PathBuf>,
+        position: PhysicalPosition,
+    },
+    /// The dragged files left the window or the drag was cancelled.
+    DragLeft,
+
+    /// A request whose reply is not awaited failed in the display server,
+    /// e.g. because it used a destroyed window. Only reported by the XCB backend.
+    DisplayError(String),
+}
+
+/// Stacking of the window relative to the windows of other applications.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum WindowLevel {
+    #[default]
+    Normal,
+    /// Stays above normal windows.
+    AlwaysOnTop,
+    /// Stays below normal windows.
+    AlwaysOnBottom,
+}
+
+/// Purpose of the window, used by the window manager to choose decorations, placement and stacking.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum WindowType {
+    #[default]
+    Normal,
+    Dialog,
+    /// Small persistent window such as a palette or toolbox.
+    Utility,
+    /// Torn-off toolbar.
+    Toolbar,
+    /// Shown while the application is starting.
+    Splash,
+    Tooltip,
+    Menu,
+    Notification,
+    /// Panel or dock docked to an edge of the screen.
+    Dock,
+}
+
+/// Layout of the framebuffer pixels, from the most to the least significant bits of each u32.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum PixelFormat {
+    /// 8 bits per channel with premultiplied alpha.
+    #[default]
+    Argb8888,
+    /// 8 bits per channel, the top 8 bits are ignored.
+    Xrgb8888,
+    /// 10 bits per color channel and 2 bits of premultiplied alpha.
+    Argb2101010,
+    /// 10 bits per color channel, the top 2 bits are ignored.
+    Xrgb2101010,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -60,11 +223,10 @@ pub enum ControlFlow {
 pub trait IWindow<'a> {
     fn new(
         title: String,
-        width: u32,
-        height: u32,
-        x: i32,
-        y: i32,
+        size: Size,
+        position: Position,
         border_width: u32,
+        parent: Option<RawWindowHandle>,
         build_action: Box<&'a mut dyn WindowBuildAction>,
     ) -> Self;
 
@@ -72,25 +234,81 @@ pub trait IWindow<'a> {
     where
         F: FnMut(WindowEvent, &mut ControlFlow);
 
-    fn get_instance(&self) -> WindowInstance;
+    fn get_instance(&self) -> WindowInstance<'_>;
 
     fn set_window_title(&self, title: &str);
 
+    fn title(&self) -> String;
+
     fn set_window_border_width(&self, border_width: u32);
 
+    fn set_app_id(&self, app_id: &str, instance_name: &str);
+
+    fn set_window_type(&self, window_type: WindowType);
+
+    fn set_transient_for(&self, parent: Option<&RawWindow>);
+
+    fn set_modal(&self, b: bool);
+
     fn set_undecorated(&self, b: bool);
 
+    fn set_window_icon(&self, icon: Option<Icon>);
+
     fn set_minimized(&self, b: bool);
 
     fn set_maximized(&self, b: bool);
 
+    fn set_window_level(&self, level: WindowLevel);
+
+    fn set_visible_on_all_workspaces(&self, b: bool);
+
+    fn set_workspace(&self, workspace: u32);
+
     fn show(&self);
 
     fn hide(&self);
 
-    fn get_window_pos(&self) -> (u32, u32);
+    fn get_window_pos(&self) -> PhysicalPosition;
+
+    fn set_window_pos(&self, position: Position);
+
+    fn raise(&self);
+
+    fn lower(&self);
 
-    fn get_window_size(&self) -> (u32, u32);
+    fn set_synchronized(&self, b: bool);
+
+    fn get_window_size(&self) -> PhysicalSize;
+
+    fn scale_factor(&self) -> f64;
+
+    fn set_fullscreen(&self, fullscreen: Option<Fullscreen>);
+
+    fn get_fullscreen(&self) -> Option<Fullscreen>;
+
+    fn available_monitors(&self) -> Vec<MonitorHandle>;
+
+    fn primary_monitor(&self) -> Option<MonitorHandle>;
+
+    fn current_monitor(&self) -> Option<MonitorHandle>;
+
+    fn create_popup(
+        &self,
+        size: Size,
+        anchor_rect: Rect,
+        gravity: Gravity,
+        constraint_adjustment: ConstraintAdjustment,
+    ) -> Result<Self, PopupError>
+    where
+        Self: Sized;
+
+    fn framebuffer(&self) -> RefMut<'_, [u32]>;
+
+    fn present(&self, damage: &[Rect]);
+
+    fn framebuffer_formats(&self) -> Vec<PixelFormat>;
+
+    fn set_framebuffer_format(&self, format: PixelFormat);
 }
 
 pub struct Window {
@@ -109,7 +327,7 @@ impl Window {
         self.inner.run(callback);
     }
 
-    pub fn get_instance(&self) -> WindowInstance {
+    pub fn get_instance(&self) -> WindowInstance<'_> {
         self.inner.get_instance()
     }
 
@@ -117,6 +335,10 @@ impl Window {
         self.inner.set_window_title(title);
     }
 
+    pub fn title(&self) -> String {
+        self.inner.title()
+    }
+
     pub fn set_window_border_width(&self, border_width: u32) {
         self.inner.set_window_border_width(border_width);
     }
@@ -125,6 +347,11 @@ impl Window {
         self.inner.set_undecorated(b);
     }
 
+    /// Sets the icon of the window. None restores the default icon.
+    pub fn set_window_icon(&self, icon: Option<Icon>) {
+        self.inner.set_window_icon(icon);
+    }
+
     pub fn set_maximized(&self, b: bool) {
         self.inner.set_maximized(b);
     }
@@ -133,6 +360,20 @@ impl Window {
         self.inner.set_minimized(b);
     }
 
+    pub fn set_window_level(&self, level: WindowLevel) {
+        self.inner.set_window_level(level);
+    }
+
+    /// Shows the window on every workspace (virtual desktop).
+    pub fn set_visible_on_all_workspaces(&self, b: bool) {
+        self.inner.set_visible_on_all_workspaces(b);
+    }
+
+    /// Moves the window to the workspace with the given index, starting from 0.
+    pub fn set_workspace(&self, workspace: u32) {
+        self.inner.set_workspace(workspace);
+    }
+
     pub fn show(&self) {
         self.inner.show();
     }
@@ -141,21 +382,120 @@ impl Window {
         self.inner.hide();
     }
 
-    pub fn get_window_pos(&self) -> (u32, u32) {
+    pub fn get_window_pos(&self) -> PhysicalPosition {
         self.inner.get_window_pos()
     }
 
-    pub fn get_window_size(&self) -> (u32, u32) {
+    /// Moves the window. Child windows are positioned relative to their parent.
+    /// Wayland toplevels cannot be moved by the client and ignore this.
+    pub fn set_window_pos<P: Into<Position>>(&self, position: P) {
+        self.inner.set_window_pos(position.into());
+    }
+
+    /// Stacks the window above its siblings (above the parent surface for Wayland child windows).
+    pub fn raise(&self) {
+        self.inner.raise();
+    }
+
+    /// Stacks the window below its siblings (below the parent surface for Wayland child windows).
+    pub fn lower(&self) {
+        self.inner.lower();
+    }
+
+    /// Wayland child windows only: in synchronized mode (the default) the committed state of the child
+    /// is applied together with the next commit of the parent, in desynchronized mode immediately.
+    pub fn set_synchronized(&self, b: bool) {
+        self.inner.set_synchronized(b);
+    }
+
+    pub fn get_window_size(&self) -> PhysicalSize {
         self.inner.get_window_size()
     }
+
+    /// Returns the factor to convert logical units into physical pixels for this window.
+    pub fn scale_factor(&self) -> f64 {
+        self.inner.scale_factor()
+    }
+
+    /// Enters the given fullscreen mode, or leaves fullscreen when None is passed.
+    pub fn set_fullscreen(&self, fullscreen: Option<Fullscreen>) {
+        self.inner.set_fullscreen(fullscreen);
+    }
+
+    pub fn get_fullscreen(&self) -> Option<Fullscreen> {
+        self.inner.get_fullscreen()
+    }
+
+    /// Returns the monitors connected to the display server.
+    pub fn available_monitors(&self) -> Vec<MonitorHandle> {
+        self.inner.available_monitors()
+    }
+
+    /// Returns the primary monitor, if the platform has a notion of one.
+    pub fn primary_monitor(&self) -> Option<MonitorHandle> {
+        self.inner.primary_monitor()
+    }
+
+    /// Returns the monitor the window is currently on.
+    pub fn current_monitor(&self) -> Option<MonitorHandle> {
+        self.inner.current_monitor()
+    }
+
+    /// Returns the software framebuffer: width * height pixels of the window, row by row,
+    /// in the framebuffer format (0xAARRGGBB by default).
+    /// The buffer is reallocated (and cleared) when the size of the window changed.
+    /// It has to be released before ```present``` is called.
+    pub fn framebuffer(&self) -> RefMut<'_, [u32]> {
+        self.inner.framebuffer()
+    }
+
+    /// Shows the framebuffer in the window.
+    /// ```damage``` lists the regions that changed since the last call, an empty slice presents the whole buffer.
+    pub fn present(&self, damage: &[Rect]) {
+        self.inner.present(damage);
+    }
+
+    /// Pixel formats the framebuffer can be presented in.
+    pub fn framebuffer_formats(&self) -> Vec<PixelFormat> {
+        self.inner.framebuffer_formats()
+    }
+
+    /// Changes the layout of the framebuffer pixels.
+    /// Formats that are not returned by ```framebuffer_formats``` are ignored.
+    pub fn set_framebuffer_format(&self, format: PixelFormat) {
+        self.inner.set_framebuffer_format(format);
+    }
+
+    /// Opens a popup (menu, tooltip, combo box list) next to ```anchor_rect```, which is relative to this window.
+    /// The popup grabs the keyboard and the pointer and is dismissed by a click outside of it.
+    pub fn create_popup<S: Into<Size>>(
+        &self,
+        size: S,
+        anchor_rect: Rect,
+        gravity: Gravity,
+        constraint_adjustment: ConstraintAdjustment,
+    ) -> Result<Window, PopupError> {
+        self.inner
+            .create_popup(size.into(), anchor_rect, gravity, constraint_adjustment)
+            .map(Window::new)
+    }
 }
 
+// Headless windows have no platform handles to return
+#[cfg(not(feature = "headless"))]
 unsafe impl HasRawWindowHandle for Window {
     fn raw_window_handle(&self) -> RawWindowHandle {
         self.inner.raw_window_handle()
     }
 }
 
+#[cfg(not(feature = "headless"))]
+unsafe impl HasRawDisplayHandle for Window {
+    fn raw_display_handle(&self) -> RawDisplayHandle {
+        self.inner.raw_display_handle()
+    }
+}
+
 pub struct WindowBuilder<'a> {
     title: String,
     width: u32,
@@ -163,8 +503,21 @@ pub struct WindowBuilder<'a> {
     border_width: u32,
     x: i32,
     y: i32,
+    size: Option<Size>,
+    position: Option<Position>,
     undecorated: bool,
-
+    window_icon: Option<Icon>,
+    app_id: Option<String>,
+    instance_name: Option<String>,
+    window_type: WindowType,
+    transient_for: Option<&'a Window>,
+    modal: bool,
+    parent: Option<RawWindowHandle>,
+    #[cfg(target_os = "linux")]
+    xembed_socket: Option<u64>,
+
+    // Boxed as IWindow::new takes it
+    #[allow(clippy::redundant_allocation)]
     build_action: Option<Box<&'a mut dyn WindowBuildAction>>,
 }
 
@@ -177,7 +530,18 @@ impl<'a> WindowBuilder<'a> {
             border_width: 0,
             x: 0,
             y: 0,
+            size: None,
+            position: None,
             undecorated: false,
+            window_icon: None,
+            app_id: None,
+            instance_name: None,
+            window_type: WindowType::Normal,
+            transient_for: None,
+            modal: false,
+            parent: None,
+            #[cfg(target_os = "linux")]
+            xembed_socket: None,
             build_action: Some(action),
         }
     }
@@ -198,7 +562,7 @@ impl<'a> WindowBuilder<'a> {
     }
 
     pub fn x(mut self, x: i32) -> Self {
-        self.y = x;
+        self.x = x;
         self
     }
 
@@ -207,6 +571,20 @@ impl<'a> WindowBuilder<'a> {
         self
     }
 
+    /// Sets the size of the window in physical pixels or logical units.
+    /// Overrides ```width``` and ```height```.
+    pub fn size<S: Into<Size>>(mut self, size: S) -> Self {
+        self.size = Some(size.into());
+        self
+    }
+
+    /// Sets the position of the window in physical pixels or logical units.
+    /// Overrides ```x``` and ```y```.
+    pub fn position<P: Into<Position>>(mut self, position: P) -> Self {
+        self.position = Some(position.into());
+        self
+    }
+
     pub fn border_width(mut self, width: u32) -> Self {
         self.border_width = width;
         self
@@ -217,17 +595,97 @@ impl<'a> WindowBuilder<'a> {
         self
     }
 
+    pub fn window_icon(mut self, icon: Icon) -> Self {
+        self.window_icon = Some(icon);
+        self
+    }
+
+    /// Sets the application identifier that desktops use to match the window with its .desktop file.
+    /// This is the class of WM_CLASS on X11 and the app id of the toplevel on Wayland.
+    pub fn app_id(mut self, app_id: &str) -> Self {
+        self.app_id = Some(app_id.to_owned());
+        self
+    }
+
+    /// Sets the instance part of WM_CLASS on X11. Defaults to the app id.
+    pub fn instance_name(mut self, instance_name: &str) -> Self {
+        self.instance_name = Some(instance_name.to_owned());
+        self
+    }
+
+    pub fn window_type(mut self, window_type: WindowType) -> Self {
+        self.window_type = window_type;
+        self
+    }
+
+    /// Marks the window as belonging to ```parent``` (e.g. a dialog of it).
+    /// The window manager keeps it above the parent and may center it on the parent.
+    pub fn transient_for(mut self, parent: &'a Window) -> Self {
+        self.transient_for = Some(parent);
+        self
+    }
+
+    /// Blocks input to the parent given with ```transient_for``` while the window is open.
+    pub fn modal(mut self, b: bool) -> Self {
+        self.modal = b;
+        self
+    }
+
+    /// Creates the window as a child of ```parent```, e.g. a render view inside a plugin host window.
+    /// On Wayland the window becomes a subsurface, so the parent must be another GWL window.
+    /// Parents a backend cannot use, e.g. windows of another platform, leave a top-level window.
+    pub fn parent(mut self, parent: &impl HasRawWindowHandle) -> Self {
+        self.parent = Some(parent.raw_window_handle());
+        self
+    }
+
+    /// X11 only: embeds the window into the XEmbed socket window of another application,
+    /// e.g. a plugin host. The socket window id is usually passed by the host.
+    #[cfg(target_os = "linux")]
+    pub fn xembed(mut self, socket: u64) -> Self {
+        self.xembed_socket = Some(socket);
+        self
+    }
+
     pub fn build(self) -> Window {
+        let size = self
+            .size
+            .unwrap_or_else(|| PhysicalSize::new(self.width, self.height).into());
+        let position = self
+            .position
+            .unwrap_or_else(|| PhysicalPosition::new(self.x, self.y).into());
+
         let window = Window::new(RawWindow::new(
             self.title,
-            self.width,
-            self.height,
-            self.x,
-            self.y,
+            size,
+            position,
             self.border_width,
+            self.parent,
             self.build_action.unwrap(),
         ));
-        window.set_undecorated(self.undecorated);
+        if self.undecorated {
+            window.set_undecorated(true);
+        }
+        if self.window_icon.is_some() {
+            window.set_window_icon(self.window_icon);
+        }
+        if let Some(app_id) = &self.app_id {
+            let instance_name = self.instance_name.as_ref().unwrap_or(app_id);
+            window.inner.set_app_id(app_id, instance_name);
+        }
+        if self.window_type != WindowType::Normal {
+            window.inner.set_window_type(self.window_type);
+        }
+        if let Some(parent) = self.transient_for {
+            window.inner.set_transient_for(Some(&parent.inner));
+        }
+        if self.modal {
+            window.inner.set_modal(true);
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(socket) = self.xembed_socket {
+            window.inner.xembed(socket);
+        }
         window
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,126 @@
+use crate::window::{PhysicalPosition, PhysicalSize};
+
+/// A monitor connected to the display server.
+///
+/// The handle is a snapshot taken when the monitors were enumerated.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonitorHandle {
+    /// Platform identifier (RROutput on X11, global name of the wl_output on Wayland, HMONITOR on Windows).
+    pub(crate) id: u64,
+    pub(crate) name: Option<String>,
+    pub(crate) position: (i32, i32),
+    pub(crate) size: (u32, u32),
+    pub(crate) size_mm: (u32, u32),
+    pub(crate) scale_factor: f64,
+    pub(crate) primary: bool,
+    pub(crate) current_mode: Option<ModeInfo>,
+    pub(crate) modes: Vec<ModeInfo>,
+}
+
+impl MonitorHandle {
+    /// Human readable name of the monitor (e.g. "HDMI-1"), if the platform provides one.
+    pub fn name(&self) -> Option<String> {
+        self.name.clone()
+    }
+
+    /// Position of the top-left corner of the monitor in the desktop, in pixels.
+    pub fn position(&self) -> PhysicalPosition {
+        self.position.into()
+    }
+
+    /// Resolution of the monitor in pixels.
+    pub fn size(&self) -> PhysicalSize {
+        self.size.into()
+    }
+
+    /// Physical dimensions of the monitor in millimetres.
+    /// (0, 0) when the monitor does not report them (e.g. projectors).
+    pub fn size_mm(&self) -> (u32, u32) {
+        self.size_mm
+    }
+
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    pub fn refresh_rate_millihertz(&self) -> Option<u32> {
+        self.current_mode.map(|mode| mode.refresh_rate_millihertz)
+    }
+
+    /// The video mode the monitor is currently running in.
+    pub fn current_video_mode(&self) -> Option<VideoMode> {
+        self.current_mode.map(|info| VideoMode {
+            info,
+            monitor: self.clone(),
+        })
+    }
+
+    /// Returns all video modes supported by this monitor.
+    pub fn video_modes(&self) -> Vec<VideoMode> {
+        self.modes
+            .iter()
+            .map(|info| VideoMode {
+                info: *info,
+                monitor: self.clone(),
+            })
+            .collect()
+    }
+
+    #[cfg_attr(
+        any(feature = "wayland", feature = "headless", windows),
+        allow(dead_code)
+    )]
+    pub(crate) fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.position.0
+            && y >= self.position.1
+            && x < self.position.0 + self.size.0 as i32
+            && y < self.position.1 + self.size.1 as i32
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct ModeInfo {
+    /// Platform identifier (RRMode on X11, position in the mode list on Wayland,
+    /// mode number of EnumDisplaySettingsW on Windows).
+    pub(crate) id: u64,
+    pub(crate) size: (u32, u32),
+    pub(crate) bit_depth: u16,
+    pub(crate) refresh_rate_millihertz: u32,
+}
+
+/// A video mode of a monitor, used for exclusive fullscreen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VideoMode {
+    pub(crate) info: ModeInfo,
+    pub(crate) monitor: MonitorHandle,
+}
+
+impl VideoMode {
+    pub fn size(&self) -> PhysicalSize {
+        self.info.size.into()
+    }
+
+    /// Bits per pixel, 0 when the platform does not report it (Wayland).
+    pub fn bit_depth(&self) -> u16 {
+        self.info.bit_depth
+    }
+
+    pub fn refresh_rate_millihertz(&self) -> u32 {
+        self.info.refresh_rate_millihertz
+    }
+
+    /// The monitor this video mode belongs to.
+    pub fn monitor(&self) -> MonitorHandle {
+        self.monitor.clone()
+    }
+}
+
+/// Fullscreen modes passed to ```Window::set_fullscreen```.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fullscreen {
+    /// Covers the monitor without changing its video mode.
+    /// When None is passed, the monitor the window is currently on is used.
+    Borderless(Option<MonitorHandle>),
+    /// Switches the monitor to the given video mode and covers it.
+    Exclusive(VideoMode),
+}
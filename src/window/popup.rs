@@ -0,0 +1,318 @@
+use crate::window::{PhysicalSize, Rect};
+use std::fmt;
+use std::ops::BitOr;
+
+/// Direction in which a popup extends from its anchor point.
+///
+/// The anchor point is the edge or corner of the anchor rectangle in the same direction,
+/// e.g. ```BottomRight``` places the top-left corner of the popup on the bottom-right corner
+/// of the anchor rectangle.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Gravity {
+    /// Centered on the center of the anchor rectangle.
+    None,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    BottomLeft,
+    TopRight,
+    #[default]
+    BottomRight,
+}
+
+impl Gravity {
+    /// Returns the direction on the x and y axes as -1, 0 or 1.
+    fn direction(&self) -> (i32, i32) {
+        match self {
+            Gravity::None => (0, 0),
+            Gravity::Top => (0, -1),
+            Gravity::Bottom => (0, 1),
+            Gravity::Left => (-1, 0),
+            Gravity::Right => (1, 0),
+            Gravity::TopLeft => (-1, -1),
+            Gravity::BottomLeft => (-1, 1),
+            Gravity::TopRight => (1, -1),
+            Gravity::BottomRight => (1, 1),
+        }
+    }
+}
+
+/// How a popup is moved or resized when it would not fit on the monitor.
+/// The values can be combined with ```|```, flipping is tried before sliding and resizing.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConstraintAdjustment(pub(crate) u32);
+
+impl ConstraintAdjustment {
+    pub const NONE: Self = Self(0);
+    /// Moves the popup horizontally until it fits.
+    pub const SLIDE_X: Self = Self(1);
+    /// Moves the popup vertically until it fits.
+    pub const SLIDE_Y: Self = Self(2);
+    /// Mirrors the gravity horizontally.
+    pub const FLIP_X: Self = Self(4);
+    /// Mirrors the gravity vertically.
+    pub const FLIP_Y: Self = Self(8);
+    /// Shrinks the popup horizontally.
+    pub const RESIZE_X: Self = Self(16);
+    /// Shrinks the popup vertically.
+    pub const RESIZE_Y: Self = Self(32);
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for ConstraintAdjustment {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PopupError {
+    /// The parent window cannot have popups, e.g. a foreign surface on Wayland.
+    NotSupported(String),
+}
+
+impl fmt::Display for PopupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PopupError::NotSupported(what) => write!(f, "{} cannot open popups", what),
+        }
+    }
+}
+
+impl std::error::Error for PopupError {}
+
+/// Computes the rectangle of a popup on platforms where the client places popups itself.
+/// All rectangles are in the same coordinate space (root window or screen coordinates).
+#[cfg_attr(feature = "wayland", allow(dead_code))]
+pub(crate) fn place_popup(
+    anchor_rect: Rect,
+    gravity: Gravity,
+    size: PhysicalSize,
+    constraint_adjustment: ConstraintAdjustment,
+    bounds: Rect,
+) -> Rect {
+    let (direction_x, direction_y) = gravity.direction();
+
+    let (x, width) = place_axis(
+        (anchor_rect.x, anchor_rect.width),
+        direction_x,
+        size.width,
+        (bounds.x, bounds.width),
+        (
+            constraint_adjustment.contains(ConstraintAdjustment::FLIP_X),
+            constraint_adjustment.contains(ConstraintAdjustment::SLIDE_X),
+            constraint_adjustment.contains(ConstraintAdjustment::RESIZE_X),
+        ),
+    );
+    let (y, height) = place_axis(
+        (anchor_rect.y, anchor_rect.height),
+        direction_y,
+        size.height,
+        (bounds.y, bounds.height),
+        (
+            constraint_adjustment.contains(ConstraintAdjustment::FLIP_Y),
+            constraint_adjustment.contains(ConstraintAdjustment::SLIDE_Y),
+            constraint_adjustment.contains(ConstraintAdjustment::RESIZE_Y),
+        ),
+    );
+
+    Rect::new(x, y, width, height)
+}
+
+/// Places the popup on one axis and returns its start and length.
+fn place_axis(
+    (anchor_start, anchor_length): (i32, u32),
+    direction: i32,
+    length: u32,
+    (bounds_start, bounds_length): (i32, u32),
+    (flip, slide, resize): (bool, bool, bool),
+) -> (i32, u32) {
+    let bounds_end = bounds_start + bounds_length as i32;
+    let position = |direction: i32| {
+        let anchor = anchor_start + anchor_length as i32 * (direction + 1) / 2;
+        anchor - length as i32 * (1 - direction) / 2
+    };
+    let fits =
+        |start: i32, length: u32| start >= bounds_start && start + length as i32 <= bounds_end;
+
+    let mut start = position(direction);
+    if fits(start, length) {
+        return (start, length);
+    }
+
+    if flip && direction != 0 && fits(position(-direction), length) {
+        return (position(-direction), length);
+    }
+
+    if slide {
+        start = start.min(bounds_end - length as i32).max(bounds_start);
+    }
+
+    let mut length = length;
+    if resize {
+        let end = (start + length as i32).min(bounds_end);
+        start = start.max(bounds_start);
+        length = (end - start).max(1) as u32;
+    }
+
+    (start, length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: Rect = Rect {
+        x: 0,
+        y: 0,
+        width: 1000,
+        height: 800,
+    };
+    const SIZE: PhysicalSize = PhysicalSize {
+        width: 200,
+        height: 100,
+    };
+
+    /// Places a 200x100 popup on a 1000x800 monitor with each adjustment and compares the
+    /// results to ```expected```. Adjustments on the other axis leave the popup where it is.
+    fn check(anchor_rect: Rect, gravity: Gravity, expected: [(ConstraintAdjustment, Rect); 7]) {
+        for (constraint_adjustment, expected) in expected {
+            assert_eq!(
+                place_popup(anchor_rect, gravity, SIZE, constraint_adjustment, BOUNDS),
+                expected,
+                "{:?}",
+                constraint_adjustment
+            );
+        }
+    }
+
+    #[test]
+    fn right_edge() {
+        let unconstrained = Rect::new(970, 420, 200, 100);
+        check(
+            Rect::new(950, 400, 20, 20),
+            Gravity::BottomRight,
+            [
+                (ConstraintAdjustment::NONE, unconstrained),
+                (ConstraintAdjustment::FLIP_X, Rect::new(750, 420, 200, 100)),
+                (ConstraintAdjustment::SLIDE_X, Rect::new(800, 420, 200, 100)),
+                (ConstraintAdjustment::RESIZE_X, Rect::new(970, 420, 30, 100)),
+                (ConstraintAdjustment::FLIP_Y, unconstrained),
+                (ConstraintAdjustment::SLIDE_Y, unconstrained),
+                (ConstraintAdjustment::RESIZE_Y, unconstrained),
+            ],
+        );
+    }
+
+    #[test]
+    fn left_edge() {
+        let unconstrained = Rect::new(-190, 420, 200, 100);
+        check(
+            Rect::new(10, 400, 20, 20),
+            Gravity::BottomLeft,
+            [
+                (ConstraintAdjustment::NONE, unconstrained),
+                (ConstraintAdjustment::FLIP_X, Rect::new(30, 420, 200, 100)),
+                (ConstraintAdjustment::SLIDE_X, Rect::new(0, 420, 200, 100)),
+                (ConstraintAdjustment::RESIZE_X, Rect::new(0, 420, 10, 100)),
+                (ConstraintAdjustment::FLIP_Y, unconstrained),
+                (ConstraintAdjustment::SLIDE_Y, unconstrained),
+                (ConstraintAdjustment::RESIZE_Y, unconstrained),
+            ],
+        );
+    }
+
+    #[test]
+    fn bottom_edge() {
+        let unconstrained = Rect::new(420, 770, 200, 100);
+        check(
+            Rect::new(400, 750, 20, 20),
+            Gravity::BottomRight,
+            [
+                (ConstraintAdjustment::NONE, unconstrained),
+                (ConstraintAdjustment::FLIP_Y, Rect::new(420, 650, 200, 100)),
+                (ConstraintAdjustment::SLIDE_Y, Rect::new(420, 700, 200, 100)),
+                (ConstraintAdjustment::RESIZE_Y, Rect::new(420, 770, 200, 30)),
+                (ConstraintAdjustment::FLIP_X, unconstrained),
+                (ConstraintAdjustment::SLIDE_X, unconstrained),
+                (ConstraintAdjustment::RESIZE_X, unconstrained),
+            ],
+        );
+    }
+
+    #[test]
+    fn top_edge() {
+        let unconstrained = Rect::new(420, -90, 200, 100);
+        check(
+            Rect::new(400, 10, 20, 20),
+            Gravity::TopRight,
+            [
+                (ConstraintAdjustment::NONE, unconstrained),
+                (ConstraintAdjustment::FLIP_Y, Rect::new(420, 30, 200, 100)),
+                (ConstraintAdjustment::SLIDE_Y, Rect::new(420, 0, 200, 100)),
+                (ConstraintAdjustment::RESIZE_Y, Rect::new(420, 0, 200, 10)),
+                (ConstraintAdjustment::FLIP_X, unconstrained),
+                (ConstraintAdjustment::SLIDE_X, unconstrained),
+                (ConstraintAdjustment::RESIZE_X, unconstrained),
+            ],
+        );
+    }
+
+    #[test]
+    fn slides_when_flipping_does_not_fit() {
+        // Too wide for either side of the anchor
+        let anchor_rect = Rect::new(450, 400, 100, 20);
+        let size = PhysicalSize::new(600, 100);
+        let adjustment = ConstraintAdjustment::FLIP_X | ConstraintAdjustment::SLIDE_X;
+
+        assert_eq!(
+            place_popup(anchor_rect, Gravity::BottomRight, size, adjustment, BOUNDS),
+            Rect::new(400, 420, 600, 100)
+        );
+    }
+
+    #[test]
+    fn slides_and_resizes_popups_larger_than_the_monitor() {
+        let size = PhysicalSize::new(1200, 100);
+        let adjustment = ConstraintAdjustment::SLIDE_X | ConstraintAdjustment::RESIZE_X;
+
+        assert_eq!(
+            place_popup(
+                Rect::new(500, 400, 20, 20),
+                Gravity::BottomRight,
+                size,
+                adjustment,
+                BOUNDS
+            ),
+            Rect::new(0, 420, 1000, 100)
+        );
+    }
+
+    #[test]
+    fn fitting_popups_are_not_adjusted() {
+        let all = ConstraintAdjustment::FLIP_X
+            | ConstraintAdjustment::FLIP_Y
+            | ConstraintAdjustment::SLIDE_X
+            | ConstraintAdjustment::SLIDE_Y
+            | ConstraintAdjustment::RESIZE_X
+            | ConstraintAdjustment::RESIZE_Y;
+
+        assert_eq!(
+            place_popup(
+                Rect::new(400, 300, 20, 20),
+                Gravity::None,
+                SIZE,
+                all,
+                BOUNDS
+            ),
+            Rect::new(310, 260, 200, 100)
+        );
+    }
+}
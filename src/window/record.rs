@@ -0,0 +1,610 @@
+use crate::window::{ControlFlow, PhysicalPosition, Rect, Window, WindowEvent};
+use std::io::{self, BufRead, Read, Write};
+#[cfg(unix)]
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Written at the start of binary recordings, followed by the format version.
+const BINARY_MAGIC: &[u8; 4] = b"GWLE";
+const BINARY_VERSION: u8 = 1;
+
+/// File format of an event recording.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RecordingFormat {
+    /// One JSON object per line, readable and editable by hand.
+    #[cfg(feature = "serde")]
+    JsonLines,
+    /// Compact little-endian encoding with a header.
+    Binary,
+}
+
+/// How fast recorded events are delivered by ```replay```.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReplayTiming {
+    /// Waits between the events as long as they were apart when recorded.
+    Original,
+    /// Divides the recorded delays by the given factor, e.g. 2.0 replays twice as fast.
+    /// Factors that are not positive replay like ```Immediate```, delays too long for a
+    /// ```Duration``` are clamped to ```Duration::MAX```.
+    Accelerated(f64),
+    /// Delivers all events without waiting.
+    Immediate,
+}
+
+impl ReplayTiming {
+    /// Returns when an event recorded at ```time``` is due, None when it is due immediately.
+    fn due(self, time: Duration) -> Option<Duration> {
+        match self {
+            ReplayTiming::Original => Some(time),
+            ReplayTiming::Accelerated(factor) if factor > 0.0 => Some(
+                Duration::try_from_secs_f64(time.as_secs_f64() / factor).unwrap_or(Duration::MAX),
+            ),
+            ReplayTiming::Accelerated(_) | ReplayTiming::Immediate => None,
+        }
+    }
+}
+
+/// A ```WindowEvent``` and the time it was delivered, relative to the start of the recording.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecordedEvent {
+    pub time: Duration,
+    pub event: WindowEvent,
+}
+
+/// Writes the events delivered by ```Window::run_recorded``` to a file or any other writer.
+pub struct EventRecorder<W: Write> {
+    writer: W,
+    format: RecordingFormat,
+    start: Instant,
+    /// First write error, no further events are written after it.
+    error: Option<io::Error>,
+}
+
+impl<W: Write> EventRecorder<W> {
+    pub fn new(mut writer: W, format: RecordingFormat) -> io::Result<Self> {
+        if format == RecordingFormat::Binary {
+            writer.write_all(BINARY_MAGIC)?;
+            writer.write_all(&[BINARY_VERSION])?;
+        }
+
+        Ok(Self {
+            writer,
+            format,
+            start: Instant::now(),
+            error: None,
+        })
+    }
+
+    /// Writes the event with the time elapsed since the recorder was created.
+    /// The writer is flushed, so that the recording is complete even if the process exits.
+    pub fn record(&mut self, event: &WindowEvent) -> io::Result<()> {
+        let recorded = RecordedEvent {
+            time: self.start.elapsed(),
+            event: event.clone(),
+        };
+
+        match self.format {
+            #[cfg(feature = "serde")]
+            RecordingFormat::JsonLines => {
+                serde_json::to_writer(&mut self.writer, &recorded)?;
+                self.writer.write_all(b"\n")?;
+            }
+            RecordingFormat::Binary => self.writer.write_all(&encode_event(&recorded))?,
+        }
+        self.writer.flush()
+    }
+
+    /// Returns the writer, or the first error that occurred while recording.
+    pub fn finish(self) -> io::Result<W> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.writer),
+        }
+    }
+}
+
+/// Reads all events of a recording written by ```EventRecorder```.
+pub fn read_recording<R: BufRead>(
+    mut reader: R,
+    format: RecordingFormat,
+) -> io::Result<Vec<RecordedEvent>> {
+    let mut events = Vec::new();
+
+    match format {
+        #[cfg(feature = "serde")]
+        RecordingFormat::JsonLines => {
+            for line in reader.lines() {
+                let line = line?;
+                if !line.trim().is_empty() {
+                    events.push(serde_json::from_str(&line)?);
+                }
+            }
+        }
+        RecordingFormat::Binary => {
+            let mut header = [0; 5];
+            reader.read_exact(&mut header)?;
+            if &header[..4] != BINARY_MAGIC || header[4] != BINARY_VERSION {
+                return Err(invalid_data("not a binary event recording of this version"));
+            }
+
+            while !reader.fill_buf()?.is_empty() {
+                events.push(decode_event(&mut reader)?);
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+impl Window {
+    /// Same as ```run```, but every event is written to the recorder before it is delivered.
+    /// Recording stops at the first write error, which is returned by ```EventRecorder::finish```.
+    pub fn run_recorded<W, F>(&self, recorder: &mut EventRecorder<W>, mut callback: F)
+    where
+        W: Write,
+        F: FnMut(WindowEvent, &mut ControlFlow),
+    {
+        self.run(|event, control_flow| {
+            if recorder.error.is_none() {
+                recorder.error = recorder.record(&event).err();
+            }
+            callback(event, control_flow);
+        });
+    }
+
+    /// Injects each recorded event with ```inject_event``` once it is due and delivers it with
+    /// ```run```, so that the window follows the replayed events like it follows injected ones.
+    /// Returns after the last event or when the callback sets ```ControlFlow::Exit```.
+    ///
+    /// Only the headless backend can replay into a window. The run loops of the other backends
+    /// block on the display server and have no way to inject events, so recordings made with
+    /// them are replayed with the free function ```replay```.
+    #[cfg(feature = "headless")]
+    pub fn replay<F>(&self, events: &[RecordedEvent], timing: ReplayTiming, mut callback: F)
+    where
+        F: FnMut(WindowEvent, &mut ControlFlow),
+    {
+        let mut exited = false;
+        deliver_when_due(events, timing, |event| {
+            self.inject_event(event);
+            self.run(|event, control_flow| {
+                callback(event, control_flow);
+                exited = matches!(control_flow, ControlFlow::Exit(_));
+            });
+            exited
+        });
+    }
+}
+
+/// Delivers the recorded events to the callback without a window, so that the callback sees
+/// the same sequence as when it was recorded. Nothing reaches the display server.
+/// Returns after the last event or when the callback sets ```ControlFlow::Exit```.
+///
+/// This is how recordings are replayed with the X11, Wayland and Windows backends, which cannot
+/// replay into a window. Live events of a window are not merged with the replayed ones.
+pub fn replay<F>(events: &[RecordedEvent], timing: ReplayTiming, mut callback: F)
+where
+    F: FnMut(WindowEvent, &mut ControlFlow),
+{
+    let mut control_flow = ControlFlow::Listen;
+    deliver_when_due(events, timing, |event| {
+        callback(event, &mut control_flow);
+        matches!(control_flow, ControlFlow::Exit(_))
+    });
+}
+
+/// Passes each event to ```deliver``` once it is due, until ```deliver``` returns true.
+fn deliver_when_due<F>(events: &[RecordedEvent], timing: ReplayTiming, mut deliver: F)
+where
+    F: FnMut(WindowEvent) -> bool,
+{
+    let start = Instant::now();
+
+    for recorded in events {
+        let due = timing.due(recorded.time);
+        if let Some(delay) = due.and_then(|due| due.checked_sub(start.elapsed())) {
+            thread::sleep(delay);
+        }
+
+        if deliver(recorded.event.clone()) {
+            break;
+        }
+    }
+}
+
+fn encode_event(recorded: &RecordedEvent) -> Vec<u8> {
+    let mut data = Vec::with_capacity(32);
+    data.extend((recorded.time.as_micros() as u64).to_le_bytes());
+
+    match &recorded.event {
+        WindowEvent::Expose(rect) => {
+            data.push(0);
+            data.extend(rect.x.to_le_bytes());
+            data.extend(rect.y.to_le_bytes());
+            data.extend(rect.width.to_le_bytes());
+            data.extend(rect.height.to_le_bytes());
+        }
+        WindowEvent::KeyDown(key) => {
+            data.push(1);
+            data.extend(key.to_le_bytes());
+        }
+        WindowEvent::KeyUp(key) => {
+            data.push(2);
+            data.extend(key.to_le_bytes());
+        }
+        WindowEvent::CloseRequested => data.push(3),
+        WindowEvent::ScaleFactorChanged(scale_factor) => {
+            data.push(4);
+            data.extend(scale_factor.to_le_bytes());
+        }
+        WindowEvent::PopupDone => data.push(5),
+        WindowEvent::Focused(b) => data.extend([6, *b as u8]),
+        WindowEvent::Activated(b) => data.extend([7, *b as u8]),
+        WindowEvent::DragEntered { paths, position } => {
+            data.push(8);
+            encode_paths(&mut data, paths);
+            encode_position(&mut data, position);
+        }
+        WindowEvent::DragMoved { position } => {
+            data.push(9);
+            encode_position(&mut data, position);
+        }
+        WindowEvent::DragDropped { paths, position } => {
+            data.push(10);
+            encode_paths(&mut data, paths);
+            encode_position(&mut data, position);
+        }
+        WindowEvent::DragLeft => data.push(11),
+        WindowEvent::DisplayError(message) => {
+            data.push(12);
+            encode_bytes(&mut data, message.as_bytes());
+        }
+    }
+
+    data
+}
+
+fn decode_event<R: BufRead>(reader: &mut R) -> io::Result<RecordedEvent> {
+    let time = Duration::from_micros(u64::from_le_bytes(read_array(reader)?));
+    let [tag] = read_array(reader)?;
+
+    let event = match tag {
+        0 => WindowEvent::Expose(Rect::new(
+            i32::from_le_bytes(read_array(reader)?),
+            i32::from_le_bytes(read_array(reader)?),
+            u32::from_le_bytes(read_array(reader)?),
+            u32::from_le_bytes(read_array(reader)?),
+        )),
+        1 => WindowEvent::KeyDown(u32::from_le_bytes(read_array(reader)?)),
+        2 => WindowEvent::KeyUp(u32::from_le_bytes(read_array(reader)?)),
+        3 => WindowEvent::CloseRequested,
+        4 => WindowEvent::ScaleFactorChanged(f64::from_le_bytes(read_array(reader)?)),
+        5 => WindowEvent::PopupDone,
+        6 => WindowEvent::Focused(read_array::<1, _>(reader)? != [0]),
+        7 => WindowEvent::Activated(read_array::<1, _>(reader)? != [0]),
+        8 => WindowEvent::DragEntered {
+            paths: decode_paths(reader)?,
+            position: decode_position(reader)?,
+        },
+        9 => WindowEvent::DragMoved {
+            position: decode_position(reader)?,
+        },
+        10 => WindowEvent::DragDropped {
+            paths: decode_paths(reader)?,
+            position: decode_position(reader)?,
+        },
+        11 => WindowEvent::DragLeft,
+        12 => WindowEvent::DisplayError(
+            String::from_utf8(decode_bytes(reader)?)
+                .map_err(|_| invalid_data("error message is not valid UTF-8"))?,
+        ),
+        _ => return Err(invalid_data("unknown event in binary event recording")),
+    };
+
+    Ok(RecordedEvent { time, event })
+}
+
+/// Writes the number of paths, followed by the length and the bytes of each path.
+/// Paths that are not valid Unicode are only kept on Unix.
+fn encode_paths(data: &mut Vec<u8>, paths: &[PathBuf]) {
+    data.extend((paths.len() as u32).to_le_bytes());
+    for path in paths {
+        #[cfg(unix)]
+        let bytes = path.as_os_str().as_bytes();
+        #[cfg(not(unix))]
+        let path = path.to_string_lossy();
+        #[cfg(not(unix))]
+        let bytes = path.as_bytes();
+
+        encode_bytes(data, bytes);
+    }
+}
+
+fn decode_paths<R: BufRead>(reader: &mut R) -> io::Result<Vec<PathBuf>> {
+    let count = u32::from_le_bytes(read_array(reader)?);
+    (0..count)
+        .map(|_| {
+            let bytes = decode_bytes(reader)?;
+            #[cfg(unix)]
+            let path = PathBuf::from(std::ffi::OsString::from_vec(bytes));
+            #[cfg(not(unix))]
+            let path = PathBuf::from(
+                String::from_utf8(bytes).map_err(|_| invalid_data("path is not valid UTF-8"))?,
+            );
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Writes the length of the bytes, followed by the bytes.
+fn encode_bytes(data: &mut Vec<u8>, bytes: &[u8]) {
+    data.extend((bytes.len() as u32).to_le_bytes());
+    data.extend(bytes);
+}
+
+fn decode_bytes<R: BufRead>(reader: &mut R) -> io::Result<Vec<u8>> {
+    // The length is not trusted, the buffer only grows as far as the data goes
+    let len = u32::from_le_bytes(read_array(reader)?);
+    let mut bytes = Vec::new();
+    if reader.by_ref().take(len.into()).read_to_end(&mut bytes)? != len as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+fn encode_position(data: &mut Vec<u8>, position: &PhysicalPosition) {
+    data.extend(position.x.to_le_bytes());
+    data.extend(position.y.to_le_bytes());
+}
+
+fn decode_position<R: BufRead>(reader: &mut R) -> io::Result<PhysicalPosition> {
+    Ok(PhysicalPosition::new(
+        i32::from_le_bytes(read_array(reader)?),
+        i32::from_le_bytes(read_array(reader)?),
+    ))
+}
+
+fn read_array<const N: usize, R: BufRead>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut data = [0; N];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded(micros: u64, event: WindowEvent) -> RecordedEvent {
+        RecordedEvent {
+            time: Duration::from_micros(micros),
+            event,
+        }
+    }
+
+    fn binary_recording(events: &[RecordedEvent]) -> Vec<u8> {
+        let mut data = BINARY_MAGIC.to_vec();
+        data.push(BINARY_VERSION);
+        for event in events {
+            data.extend(encode_event(event));
+        }
+        data
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let position = PhysicalPosition::new(-3, 40);
+        let paths = vec![PathBuf::from("/tmp/a b.txt"), PathBuf::from("relative/ü")];
+        let events = [
+            recorded(0, WindowEvent::Expose(Rect::new(-1, 2, 300, 400))),
+            recorded(1, WindowEvent::KeyDown(0x61)),
+            recorded(2, WindowEvent::KeyUp(0xFF0D)),
+            recorded(3, WindowEvent::CloseRequested),
+            recorded(4, WindowEvent::ScaleFactorChanged(1.25)),
+            recorded(5, WindowEvent::PopupDone),
+            recorded(6, WindowEvent::Focused(true)),
+            recorded(7, WindowEvent::Activated(false)),
+            recorded(
+                8,
+                WindowEvent::DragEntered {
+                    paths: paths.clone(),
+                    position,
+                },
+            ),
+            recorded(9, WindowEvent::DragMoved { position }),
+            recorded(10, WindowEvent::DragDropped { paths, position }),
+            recorded(11, WindowEvent::DisplayError("BadWindow".into())),
+            recorded(u64::MAX, WindowEvent::DragLeft),
+        ];
+
+        let data = binary_recording(&events);
+        let read = read_recording(&data[..], RecordingFormat::Binary).unwrap();
+        assert_eq!(read, events);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn binary_round_trip_keeps_non_utf8_paths() {
+        let path = PathBuf::from(std::ffi::OsString::from_vec(b"/tmp/\xFF\xFE".to_vec()));
+        let events = [recorded(
+            0,
+            WindowEvent::DragDropped {
+                paths: vec![path],
+                position: PhysicalPosition::new(0, 0),
+            },
+        )];
+
+        let data = binary_recording(&events);
+        let read = read_recording(&data[..], RecordingFormat::Binary).unwrap();
+        assert_eq!(read, events);
+    }
+
+    #[test]
+    fn binary_rejects_bad_header() {
+        for data in [&b"GWLF\x01"[..], &b"GWLE\x02"[..], &b"GWL"[..]] {
+            assert!(read_recording(data, RecordingFormat::Binary).is_err());
+        }
+
+        let data = binary_recording(&[]);
+        assert_eq!(
+            read_recording(&data[..], RecordingFormat::Binary).unwrap(),
+            []
+        );
+    }
+
+    #[test]
+    fn binary_rejects_truncated_events() {
+        let event = recorded(
+            1,
+            WindowEvent::DragEntered {
+                paths: vec![PathBuf::from("/tmp/file")],
+                position: PhysicalPosition::new(1, 2),
+            },
+        );
+        let data = binary_recording(&[event]);
+
+        for len in BINARY_MAGIC.len() + 2..data.len() {
+            let error = read_recording(&data[..len], RecordingFormat::Binary).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn binary_rejects_oversized_path_lengths() {
+        let event = recorded(
+            1,
+            WindowEvent::DragDropped {
+                paths: vec![PathBuf::from("/tmp/file")],
+                position: PhysicalPosition::new(1, 2),
+            },
+        );
+        let mut data = binary_recording(&[event]);
+        // Time, tag and path count come before the length of the first path
+        let len_at = BINARY_MAGIC.len() + 1 + 8 + 1 + 4;
+        data[len_at..len_at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let error = read_recording(&data[..], RecordingFormat::Binary).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn binary_rejects_unknown_events() {
+        let mut data = binary_recording(&[]);
+        data.extend(0u64.to_le_bytes());
+        data.push(13);
+
+        let error = read_recording(&data[..], RecordingFormat::Binary).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_lines_round_trip() {
+        let events = [
+            recorded(10, WindowEvent::KeyDown(0x61)),
+            recorded(20, WindowEvent::Expose(Rect::new(0, 0, 1, 1))),
+        ];
+
+        let mut data = Vec::new();
+        for event in &events {
+            serde_json::to_writer(&mut data, event).unwrap();
+            data.extend(b"\n\n");
+        }
+        let read = read_recording(&data[..], RecordingFormat::JsonLines).unwrap();
+        assert_eq!(read, events);
+    }
+
+    #[test]
+    fn replay_timing() {
+        let time = Duration::from_secs(2);
+
+        assert_eq!(ReplayTiming::Original.due(time), Some(time));
+        assert_eq!(ReplayTiming::Immediate.due(time), None);
+        assert_eq!(
+            ReplayTiming::Accelerated(4.0).due(time),
+            Some(Duration::from_millis(500))
+        );
+        for factor in [0.0, -1.0, f64::NAN] {
+            assert_eq!(ReplayTiming::Accelerated(factor).due(time), None);
+        }
+        assert_eq!(
+            ReplayTiming::Accelerated(f64::MIN_POSITIVE).due(time),
+            Some(Duration::MAX)
+        );
+    }
+
+    #[test]
+    fn replay_stops_at_exit() {
+        let events = [
+            recorded(0, WindowEvent::KeyDown(0x61)),
+            recorded(1, WindowEvent::CloseRequested),
+            recorded(2, WindowEvent::KeyDown(0x62)),
+        ];
+
+        let mut delivered = Vec::new();
+        replay(&events, ReplayTiming::Immediate, |event, control_flow| {
+            if event == WindowEvent::CloseRequested {
+                *control_flow = ControlFlow::Exit(0);
+            }
+            delivered.push(event);
+        });
+        assert_eq!(
+            delivered,
+            [WindowEvent::KeyDown(0x61), WindowEvent::CloseRequested]
+        );
+    }
+
+    #[cfg(feature = "headless")]
+    #[test]
+    fn replay_into_headless_window() {
+        use crate::window::{DefWindowBuildAction, WindowBuilder};
+
+        let mut action = DefWindowBuildAction;
+        let window = WindowBuilder::new(Box::new(&mut action))
+            .width(20)
+            .height(10)
+            .build();
+        let paths = vec![PathBuf::from("/tmp/file")];
+        let position = PhysicalPosition::new(1, 2);
+        let events = [
+            recorded(0, WindowEvent::KeyDown(0x61)),
+            recorded(
+                1_000,
+                WindowEvent::DragEntered {
+                    paths: paths.clone(),
+                    position,
+                },
+            ),
+            recorded(2_000, WindowEvent::CloseRequested),
+            recorded(3_000, WindowEvent::KeyDown(0x62)),
+        ];
+
+        let mut delivered = Vec::new();
+        let start = Instant::now();
+        window.replay(&events, ReplayTiming::Original, |event, control_flow| {
+            if event == WindowEvent::CloseRequested {
+                *control_flow = ControlFlow::Exit(0);
+            }
+            delivered.push(event);
+        });
+        assert!(start.elapsed() >= Duration::from_millis(2));
+
+        // The events went through the event path of the window, which exposed it first
+        assert_eq!(
+            delivered,
+            [
+                WindowEvent::Expose(Rect::new(0, 0, 20, 10)),
+                WindowEvent::KeyDown(0x61),
+                WindowEvent::DragEntered { paths, position },
+                WindowEvent::CloseRequested,
+            ]
+        );
+        assert!(window.is_visible());
+    }
+}
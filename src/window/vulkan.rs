@@ -0,0 +1,99 @@
+//! Vulkan surfaces for windows, created with VK_KHR_xlib_surface on X11,
+//! VK_KHR_wayland_surface on Wayland and VK_KHR_win32_surface on Windows.
+
+use crate::window::{RawWindow, Window};
+#[cfg(not(feature = "headless"))]
+use libloading::Library;
+#[cfg(not(feature = "headless"))]
+use once_cell::sync::Lazy;
+use std::ffi::c_void;
+#[cfg(not(feature = "headless"))]
+use std::ffi::{c_char, CString};
+use std::fmt;
+
+/// Handle of a VkInstance, e.g. ```instance.handle().as_raw() as VkInstance``` with ash.
+pub type VkInstance = *mut c_void;
+/// Handle of a VkSurfaceKHR.
+pub type VkSurfaceKHR = u64;
+
+// The headless backend creates no surfaces and never loads Vulkan
+#[cfg(not(feature = "headless"))]
+pub(crate) type VkResult = i32;
+#[cfg(not(feature = "headless"))]
+pub(crate) const VK_SUCCESS: VkResult = 0;
+
+#[cfg(not(feature = "headless"))]
+type VkGetInstanceProcAddr =
+    unsafe extern "system" fn(VkInstance, *const c_char) -> Option<unsafe extern "system" fn()>;
+
+#[cfg(all(windows, not(feature = "headless")))]
+const LOADER_NAMES: [&str; 1] = ["vulkan-1.dll"];
+#[cfg(not(any(windows, feature = "headless")))]
+const LOADER_NAMES: [&str; 2] = ["libvulkan.so.1", "libvulkan.so"];
+
+/// The Vulkan loader, opened at runtime so that applications without Vulkan still start.
+#[cfg(not(feature = "headless"))]
+static LOADER: Lazy<Option<Library>> = Lazy::new(|| {
+    LOADER_NAMES
+        .iter()
+        .find_map(|name| unsafe { Library::new(name).ok() })
+});
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum VulkanError {
+    /// The Vulkan loader or an instance function of the surface extension is not available.
+    NotSupported(String),
+    /// Creating the surface failed with the given VkResult.
+    SurfaceCreationFailed(i32),
+}
+
+impl fmt::Display for VulkanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VulkanError::NotSupported(what) => write!(f, "{} is not available", what),
+            VulkanError::SurfaceCreationFailed(result) => {
+                write!(f, "failed to create the surface (VkResult {})", result)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VulkanError {}
+
+/// Returns the address of an instance function, e.g. vkCreateXlibSurfaceKHR.
+#[cfg(not(feature = "headless"))]
+pub(crate) unsafe fn instance_proc_address(
+    instance: VkInstance,
+    name: &str,
+) -> Result<unsafe extern "system" fn(), VulkanError> {
+    let loader = LOADER
+        .as_ref()
+        .ok_or_else(|| VulkanError::NotSupported("the Vulkan loader".to_owned()))?;
+    let get_instance_proc_addr = loader
+        .get::<VkGetInstanceProcAddr>(b"vkGetInstanceProcAddr\0")
+        .map_err(|_| VulkanError::NotSupported("vkGetInstanceProcAddr".to_owned()))?;
+
+    let c_name = CString::new(name).unwrap();
+    get_instance_proc_addr(instance, c_name.as_ptr())
+        .ok_or_else(|| VulkanError::NotSupported(name.to_owned()))
+}
+
+impl Window {
+    /// Instance extensions that have to be enabled to create surfaces for windows.
+    pub fn required_instance_extensions() -> &'static [&'static str] {
+        RawWindow::required_vulkan_extensions()
+    }
+
+    /// Creates a Vulkan surface presenting to the window.
+    /// The surface has to be destroyed with vkDestroySurfaceKHR before the window.
+    ///
+    /// # Safety
+    /// ```instance``` has to be a valid instance created with the extensions of
+    /// ```required_instance_extensions```.
+    pub unsafe fn create_vulkan_surface(
+        &self,
+        instance: VkInstance,
+    ) -> Result<VkSurfaceKHR, VulkanError> {
+        self.inner.create_vulkan_surface(instance)
+    }
+}
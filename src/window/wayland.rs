@@ -1,48 +1,153 @@
-use crate::window::{ControlFlow, IWindow, WindowBuildAction, WindowEvent};
-use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+#[cfg(feature = "gl")]
+use crate::window::{
+    egl::{EglContext, EGL_PLATFORM_WAYLAND_KHR, EGL_WINDOW_BIT},
+    GlAttributes, GlError, IGlContext,
+};
+#[cfg(feature = "vulkan")]
+use crate::window::{
+    instance_proc_address, VkInstance, VkResult, VkSurfaceKHR, VulkanError, VK_SUCCESS,
+};
+use crate::window::{
+    parse_uri_list, ClipboardError, ConstraintAdjustment, ControlFlow, DropAction, Fullscreen,
+    Gravity, IWindow, Icon, LogicalPosition, LogicalSize, ModeInfo, MonitorHandle,
+    PhysicalPosition, PhysicalSize, PixelFormat, PopupError, Position, Rect, Selection, Size,
+    WindowBuildAction, WindowEvent, WindowLevel, WindowType,
+};
+use once_cell::sync::Lazy;
+use raw_window_handle::{
+    HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
+};
+use std::cell::{RefCell, RefMut};
+#[cfg(any(feature = "gl", feature = "vulkan"))]
+use std::ffi::c_void;
+use std::io::{ErrorKind, Read, Write};
+#[cfg(feature = "gl")]
+use std::mem::ManuallyDrop;
+use std::ops::Range;
+use std::os::fd::{AsFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::os::unix::fs::FileExt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::{fs::File, os::unix::prelude::AsRawFd};
-use std::cell::RefCell;
-use wayland_client::{protocol::{
-    wl_buffer, wl_compositor, wl_keyboard, wl_registry, wl_seat, wl_shm, wl_shm_pool,
-    wl_surface,wl_shell,
-}, Connection, Dispatch, QueueHandle, WEnum, EventQueue};
+use wayland_client::backend::{ObjectId, WaylandError};
 use wayland_client::protocol::wl_compositor::WlCompositor;
 use wayland_client::protocol::wl_display::WlDisplay;
-use wayland_client::protocol::wl_registry::WlRegistry;
-use wayland_client::protocol::wl_shell::{Event, WlShell};
+use wayland_client::protocol::wl_shell::WlShell;
 use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_client::{
+    event_created_child,
+    protocol::{
+        wl_buffer, wl_compositor, wl_data_device, wl_data_device_manager, wl_data_offer,
+        wl_data_source, wl_keyboard, wl_output, wl_pointer, wl_registry, wl_seat, wl_shell, wl_shm,
+        wl_shm_pool, wl_subcompositor, wl_subsurface, wl_surface,
+    },
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum,
+};
 
-use wayland_protocols::xdg::shell::client::{xdg_surface, xdg_toplevel, xdg_wm_base};
+use wayland_protocols::wp::fractional_scale::v1::client::{
+    wp_fractional_scale_manager_v1, wp_fractional_scale_v1,
+};
+use wayland_protocols::wp::primary_selection::zv1::client::{
+    zwp_primary_selection_device_manager_v1, zwp_primary_selection_device_v1,
+    zwp_primary_selection_offer_v1, zwp_primary_selection_source_v1,
+};
+use wayland_protocols::wp::viewporter::client::{wp_viewport, wp_viewporter};
+use wayland_protocols::xdg::dialog::v1::client::{xdg_dialog_v1, xdg_wm_dialog_v1};
+use wayland_protocols::xdg::shell::client::{
+    xdg_popup, xdg_positioner, xdg_surface, xdg_toplevel, xdg_wm_base,
+};
+use wayland_protocols::xdg::toplevel_icon::v1::client::{
+    xdg_toplevel_icon_manager_v1, xdg_toplevel_icon_v1,
+};
+use wayland_protocols::xdg::xdg_output::zv1::client::{zxdg_output_manager_v1, zxdg_output_v1};
+#[cfg(feature = "gl")]
+use wayland_sys::{egl::*, ffi_dispatch};
 
-pub struct WindowHandle {
+/// All windows share one connection, so that requests of one window can refer to the objects
+/// of another (e.g. the parent of a dialog).
+static CONNECTION: Lazy<Connection> = Lazy::new(|| Connection::connect_to_env().unwrap());
 
+/// Roles of the surfaces of the windows of this process, by which popups of child windows find
+/// the xdg surface of their toplevel or popup.
+static SURFACE_ROLES: Lazy<Mutex<Vec<SurfaceRole>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+struct SurfaceRole {
+    surface: ObjectId,
+    xdg_surface: Option<xdg_surface::XdgSurface>,
+    /// Parent surface of a child window, with the position in it in logical units.
+    parent: Option<(ObjectId, (i32, i32))>,
+}
+
+fn update_surface_role(surface: ObjectId, update: impl FnOnce(&mut SurfaceRole)) {
+    let mut roles = SURFACE_ROLES.lock().unwrap();
+    let index = match roles.iter().position(|role| role.surface == surface) {
+        Some(index) => index,
+        None => {
+            roles.push(SurfaceRole {
+                surface,
+                xdg_surface: None,
+                parent: None,
+            });
+            roles.len() - 1
+        }
+    };
+    update(&mut roles[index]);
+}
+
+/// Finds the xdg surface popups of ```surface``` are attached to, walking up from child windows,
+/// with the position of ```surface``` in it.
+fn popup_parent(surface: ObjectId) -> Option<(xdg_surface::XdgSurface, (i32, i32))> {
+    let roles = SURFACE_ROLES.lock().unwrap();
+    let mut surface = surface;
+    let mut offset = (0, 0);
+    // Each surface is visited at most once, unless the parents form a cycle
+    for _ in 0..roles.len() {
+        let role = roles.iter().find(|role| role.surface == surface)?;
+        if let Some(xdg_surface) = &role.xdg_surface {
+            return Some((xdg_surface.clone(), offset));
+        }
+        let (parent, (x, y)) = role.parent.clone()?;
+        offset = (offset.0 + x, offset.1 + y);
+        surface = parent;
+    }
+    None
 }
 
+pub struct WindowHandle {}
+
 pub struct WindowInstance<'a> {
     pub display: &'a WlDisplay,
     pub surface: &'a WlSurface,
     pub compositor: &'a WlCompositor,
-    pub shell: &'a WlShell
+    /// None on compositors without the deprecated wl_shell.
+    pub shell: Option<&'a WlShell>,
 }
 
 pub struct RawWindow {
     state: RefCell<State>,
     event_queue: RefCell<EventQueue<State>>,
-    display: WlDisplay
+    display: WlDisplay,
+    surface: WlSurface,
+    compositor: WlCompositor,
+    shell: Option<WlShell>,
+
+    fullscreen: RefCell<Option<Fullscreen>>,
+    framebuffer: RefCell<Vec<u32>>,
+    framebuffer_size: RefCell<PhysicalSize>,
 }
 
-impl IWindow for RawWindow {
+impl IWindow<'_> for RawWindow {
     fn new(
         title: String,
-        width: u32,
-        height: u32,
-        x: i32,
-        y: i32,
-        border_width: u32,
-        mut build_action: Box<dyn WindowBuildAction>,
+        size: Size,
+        position: Position,
+        _border_width: u32,
+        parent: Option<RawWindowHandle>,
+        build_action: Box<&mut dyn WindowBuildAction>,
     ) -> Self {
         build_action.pre_init();
-        let conn = Connection::connect_to_env().unwrap();
+        let conn = CONNECTION.clone();
 
         let mut event_queue = conn.new_event_queue();
         let qhandle = event_queue.handle();
@@ -50,351 +155,2708 @@ impl IWindow for RawWindow {
         let display = conn.display();
         display.get_registry(&qhandle, ());
 
+        // Wayland sizes are in surface-local (logical) coordinates, physical sizes and positions
+        // are converted again once the scale factor of the surface is known
+        let mut state = State::new(title, size);
+        // Parents that cannot be used leave a toplevel, as on the other backends
+        state.parent = parent.and_then(|parent| surface_from_handle(&conn, parent));
 
+        event_queue.blocking_dispatch(&mut state).unwrap();
 
-        let mut state = State {
-            running: true,
-            base_surface: None,
-            compositor: None,
-            shell: None,
-            buffer: None,
-            wm_base: None,
-            xdg_surface: None,
-            configured: false,
+        if state.parent.is_some() {
+            match state.subcompositor.is_some() {
+                true => state.init_subsurface(&qhandle, position),
+                // Without wl_subcompositor the window cannot be a child window
+                false => {
+                    state.parent = None;
+                    if state.wm_base.is_some() && state.base_surface.is_some() {
+                        state.init_xdg_surface(&qhandle);
+                    }
+                }
+            }
+        }
 
-            title: title.to_owned()
-        };
+        let window = Self::from_state(state, event_queue, display);
+        build_action.window_created(&window.get_instance());
+        window
+    }
+
+    fn run<F>(&self, mut callback: F)
+    where
+        F: FnMut(WindowEvent, &mut ControlFlow),
+    {
+        let mut control_flow = ControlFlow::Listen;
+
+        while self.state.borrow().running {
+            self.dispatch();
+
+            let events = std::mem::take(&mut self.state.borrow_mut().events);
+            for event in events {
+                match control_flow {
+                    ControlFlow::Listen => {
+                        callback(event, &mut control_flow);
+                    }
+                    ControlFlow::Exit(code) => {
+                        std::process::exit(code as i32);
+                    }
+                }
+            }
+        }
+    }
+
+    fn get_instance(&self) -> WindowInstance<'_> {
+        WindowInstance {
+            display: &self.display,
+            surface: &self.surface,
+            compositor: &self.compositor,
+            shell: self.shell.as_ref(),
+        }
+    }
+
+    fn set_window_title(&self, title: &str) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.title = title.to_owned();
+            if let Some((_, toplevel)) = &state.xdg_surface {
+                toplevel.set_title(title.to_owned());
+                state.base_surface.as_ref().unwrap().commit();
+            }
+        }
+        self.event_queue.borrow().flush().unwrap();
+    }
+
+    fn title(&self) -> String {
+        self.state.borrow().title.clone()
+    }
+
+    fn set_window_border_width(&self, _border_width: u32) {}
+
+    fn set_app_id(&self, app_id: &str, _instance_name: &str) {
+        {
+            let state = self.state.borrow();
+            if let Some((_, toplevel)) = &state.xdg_surface {
+                toplevel.set_app_id(app_id.to_owned());
+                state.base_surface.as_ref().unwrap().commit();
+            }
+        }
+        self.event_queue.borrow().flush().unwrap();
+    }
+
+    // xdg-shell has no window types, the compositor derives them from the parent and the dialog hints
+    fn set_window_type(&self, _window_type: WindowType) {}
+
+    fn set_transient_for(&self, parent: Option<&RawWindow>) {
+        {
+            let state = self.state.borrow();
+            if let Some((_, toplevel)) = &state.xdg_surface {
+                match parent {
+                    Some(parent) => {
+                        let parent_state = parent.state.borrow();
+                        toplevel.set_parent(parent_state.xdg_surface.as_ref().map(|(_, t)| t));
+                    }
+                    None => toplevel.set_parent(None),
+                }
+                state.base_surface.as_ref().unwrap().commit();
+            }
+        }
+        self.event_queue.borrow().flush().unwrap();
+    }
+
+    fn set_modal(&self, b: bool) {
+        {
+            let mut state = self.state.borrow_mut();
+            if state.dialog.is_none() {
+                let qh = self.event_queue.borrow().handle();
+                let dialog = match (&state.wm_dialog, &state.xdg_surface) {
+                    (Some(wm_dialog), Some((_, toplevel))) => {
+                        wm_dialog.get_xdg_dialog(toplevel, &qh, ())
+                    }
+                    _ => return,
+                };
+                state.dialog = Some(dialog);
+            }
+
+            let dialog = state.dialog.as_ref().unwrap();
+            match b {
+                true => dialog.set_modal(),
+                false => dialog.unset_modal(),
+            }
+            state.base_surface.as_ref().unwrap().commit();
+        }
+        self.event_queue.borrow().flush().unwrap();
+    }
+
+    fn set_undecorated(&self, _b: bool) {}
+
+    // xdg-shell cannot restore minimized windows, the compositor does it
+    fn set_minimized(&self, b: bool) {
+        {
+            let state = self.state.borrow();
+            if let (true, Some((_, toplevel))) = (b, &state.xdg_surface) {
+                toplevel.set_minimized();
+                state.base_surface.as_ref().unwrap().commit();
+            }
+        }
+        self.event_queue.borrow().flush().unwrap();
+    }
+
+    fn set_maximized(&self, b: bool) {
+        {
+            let state = self.state.borrow();
+            if let Some((_, toplevel)) = &state.xdg_surface {
+                match b {
+                    true => toplevel.set_maximized(),
+                    false => toplevel.unset_maximized(),
+                }
+                state.base_surface.as_ref().unwrap().commit();
+            }
+        }
+        self.event_queue.borrow().flush().unwrap();
+    }
+
+    fn set_window_icon(&self, icon: Option<Icon>) {
+        {
+            let state = self.state.borrow();
+            let (manager, toplevel) = match (&state.icon_manager, &state.xdg_surface) {
+                (Some(manager), Some((_, toplevel))) => (manager, toplevel),
+                _ => return,
+            };
+            let qh = self.event_queue.borrow().handle();
+
+            match icon {
+                None => {
+                    manager.set_icon(toplevel, None);
+                    state.base_surface.as_ref().unwrap().commit();
+                }
+                Some(icon) => {
+                    let toplevel_icon = manager.create_icon(&qh, ());
+                    let buffers = icon
+                        .sizes()
+                        .iter()
+                        .map(|icon| {
+                            let (size, pixels) = square_premultiplied_argb(icon);
+                            let buffer = state.create_shm_buffer(&qh, size, size, &pixels);
+                            toplevel_icon.add_buffer(&buffer, 1);
+                            buffer
+                        })
+                        .collect::<Vec<_>>();
+                    manager.set_icon(toplevel, Some(&toplevel_icon));
+                    state.base_surface.as_ref().unwrap().commit();
+
+                    // The icon is immutable once set, so it and its buffers are no longer needed
+                    toplevel_icon.destroy();
+                    buffers.iter().for_each(|buffer| buffer.destroy());
+                }
+            }
+        }
+        self.event_queue.borrow().flush().unwrap();
+    }
+
+    // xdg-shell leaves stacking and workspaces to the compositor
+    fn set_window_level(&self, _level: WindowLevel) {}
+
+    fn set_visible_on_all_workspaces(&self, _b: bool) {}
+
+    fn set_workspace(&self, _workspace: u32) {}
+
+    fn show(&self) {}
+
+    fn hide(&self) {}
+
+    fn get_window_pos(&self) -> PhysicalPosition {
+        // Wayland clients cannot know their global position
+        PhysicalPosition::default()
+    }
+
+    // The position and stacking of subsurfaces are applied with the next commit of the parent,
+    // toplevels are placed by the compositor
+    fn set_window_pos(&self, position: Position) {
+        self.state.borrow_mut().move_subsurface(position);
+        self.event_queue.borrow().flush().unwrap();
+    }
+
+    fn raise(&self) {
+        {
+            let state = self.state.borrow();
+            if let (Some(subsurface), Some(parent)) = (&state.subsurface, &state.parent) {
+                subsurface.place_above(parent);
+            }
+        }
+        self.event_queue.borrow().flush().unwrap();
+    }
+
+    fn lower(&self) {
+        {
+            let state = self.state.borrow();
+            if let (Some(subsurface), Some(parent)) = (&state.subsurface, &state.parent) {
+                subsurface.place_below(parent);
+            }
+        }
+        self.event_queue.borrow().flush().unwrap();
+    }
+
+    fn set_synchronized(&self, b: bool) {
+        {
+            let state = self.state.borrow();
+            if let Some(subsurface) = &state.subsurface {
+                match b {
+                    true => subsurface.set_sync(),
+                    false => subsurface.set_desync(),
+                }
+            }
+        }
+        self.event_queue.borrow().flush().unwrap();
+    }
+
+    fn get_window_size(&self) -> PhysicalSize {
+        let state = self.state.borrow();
+        state.size.to_physical(state.scale_factor)
+    }
+
+    fn scale_factor(&self) -> f64 {
+        self.state.borrow().scale_factor
+    }
+
+    fn set_fullscreen(&self, fullscreen: Option<Fullscreen>) {
+        {
+            let state = self.state.borrow();
+            if let Some((_, toplevel)) = &state.xdg_surface {
+                match &fullscreen {
+                    None => toplevel.unset_fullscreen(),
+                    // Wayland clients cannot change the video mode,
+                    // so exclusive fullscreen is treated as fullscreen on the mode's monitor.
+                    Some(Fullscreen::Borderless(monitor)) => {
+                        toplevel.set_fullscreen(monitor.as_ref().and_then(|m| state.output(m)))
+                    }
+                    Some(Fullscreen::Exclusive(mode)) => {
+                        toplevel.set_fullscreen(state.output(&mode.monitor))
+                    }
+                }
+                state.base_surface.as_ref().unwrap().commit();
+            }
+        }
+        self.event_queue.borrow().flush().unwrap();
+
+        *self.fullscreen.borrow_mut() = fullscreen;
+    }
+
+    fn get_fullscreen(&self) -> Option<Fullscreen> {
+        self.fullscreen.borrow().clone()
+    }
+
+    fn available_monitors(&self) -> Vec<MonitorHandle> {
+        self.state
+            .borrow()
+            .outputs
+            .iter()
+            .map(|(_, monitor)| monitor.clone())
+            .collect()
+    }
+
+    fn primary_monitor(&self) -> Option<MonitorHandle> {
+        // Wayland has no notion of a primary output, the first advertised one is used
+        self.available_monitors().into_iter().next()
+    }
+
+    fn current_monitor(&self) -> Option<MonitorHandle> {
+        let state = self.state.borrow();
+        let name = *state.entered_outputs.first()?;
+        state
+            .outputs
+            .iter()
+            .find(|(_, monitor)| monitor.id == name as u64)
+            .map(|(_, monitor)| monitor.clone())
+    }
+
+    fn create_popup(
+        &self,
+        size: Size,
+        anchor_rect: Rect,
+        gravity: Gravity,
+        constraint_adjustment: ConstraintAdjustment,
+    ) -> Result<Self, PopupError> {
+        // Popups of child windows are attached to their toplevel or popup, the anchor rectangle
+        // is moved by the position of the child window in it
+        let (parent_surface, (x, y)) = popup_parent(self.surface.id())
+            .ok_or_else(|| PopupError::NotSupported("a foreign Wayland surface".into()))?;
+
+        let conn = CONNECTION.clone();
+        let mut event_queue = conn.new_event_queue();
+        let display = conn.display();
+        display.get_registry(&event_queue.handle(), ());
+
+        let parent = self.state.borrow();
+        let scale_factor = parent.scale_factor;
+        // The anchor rectangle must be at least 1x1
+        let logical = |value: f64| (value / scale_factor).round() as i32;
+        let anchor_rect = (
+            logical(anchor_rect.x as f64) + x,
+            logical(anchor_rect.y as f64) + y,
+            logical(anchor_rect.width as f64).max(1),
+            logical(anchor_rect.height as f64).max(1),
+        );
+        let size = size.to_logical(scale_factor);
+
+        let mut state = State::new(String::new(), size.into());
+        state.popup_placement = Some(PopupPlacement {
+            parent: parent_surface,
+            size,
+            anchor_rect,
+            gravity,
+            constraint_adjustment,
+            grab: parent.seat.clone().zip(parent.serial),
+        });
+        drop(parent);
 
         event_queue.blocking_dispatch(&mut state).unwrap();
 
-        let instance = WindowInstance {
-            display: &display,
-            surface: &state.base_surface.as_ref().unwrap(),
-            compositor: &state.compositor.as_ref().unwrap(),
-            shell: &state.shell.as_ref().unwrap(),
+        Ok(Self::from_state(state, event_queue, display))
+    }
+
+    fn framebuffer(&self) -> RefMut<'_, [u32]> {
+        let size = self.get_window_size();
+        let mut framebuffer = self.framebuffer.borrow_mut();
+        let mut framebuffer_size = self.framebuffer_size.borrow_mut();
+
+        if *framebuffer_size != size {
+            *framebuffer = vec![0; (size.width * size.height) as usize];
+            *framebuffer_size = size;
+        }
+
+        RefMut::map(framebuffer, |framebuffer| framebuffer.as_mut_slice())
+    }
+
+    fn present(&self, damage: &[Rect]) {
+        let framebuffer = self.framebuffer.borrow();
+        let size = *self.framebuffer_size.borrow();
+        if framebuffer.is_empty() {
+            return;
+        }
+
+        let bounds = Rect::new(0, 0, size.width, size.height);
+        let damage = match damage.is_empty() {
+            true => vec![bounds],
+            false => damage
+                .iter()
+                .filter_map(|rect| rect.intersection(&bounds))
+                .collect(),
+        };
+
+        let qh = self.event_queue.borrow().handle();
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        if state.slot_pool.is_none() {
+            let shm = state.shm.as_ref().unwrap();
+            state.slot_pool = Some(SlotPool::new(shm, &qh, framebuffer.len() * 4));
+        }
+
+        // Waits until the compositor released a buffer when all slots are in use
+        let format = shm_format(state.frame_format);
+        let index = loop {
+            if let Some(index) = state.slot_pool.as_mut().unwrap().acquire(&qh, size, format) {
+                break index;
+            }
+            self.event_queue
+                .borrow_mut()
+                .blocking_dispatch(state)
+                .unwrap();
+        };
+
+        let pool = state.slot_pool.as_mut().unwrap();
+        pool.write(index, &framebuffer, &damage);
+        let slot = &mut pool.slots[index];
+        slot.busy = true;
+        let surface = state.base_surface.as_ref().unwrap();
+        surface.attach(Some(&slot.buffer), 0, 0);
+        if surface.version() >= 4 {
+            for rect in &damage {
+                surface.damage_buffer(rect.x, rect.y, rect.width as i32, rect.height as i32);
+            }
+        } else {
+            surface.damage(0, 0, i32::MAX, i32::MAX);
+        }
+        surface.commit();
+
+        // The placeholder buffer must not be attached again on the next configure
+        state.remove_placeholder();
+
+        self.event_queue.borrow().flush().unwrap();
+    }
+
+    fn framebuffer_formats(&self) -> Vec<PixelFormat> {
+        let state = self.state.borrow();
+        [
+            PixelFormat::Argb8888,
+            PixelFormat::Xrgb8888,
+            PixelFormat::Argb2101010,
+            PixelFormat::Xrgb2101010,
+        ]
+        .into_iter()
+        .filter(|format| state.shm_formats.contains(&shm_format(*format)))
+        .collect()
+    }
+
+    fn set_framebuffer_format(&self, format: PixelFormat) {
+        let mut state = self.state.borrow_mut();
+        if state.shm_formats.contains(&shm_format(format)) {
+            state.frame_format = format;
+        }
+    }
+}
+
+#[cfg(feature = "gl")]
+pub struct RawGlContext {
+    egl: ManuallyDrop<EglContext>,
+    egl_window: *mut wl_egl_window,
+}
+
+#[cfg(feature = "gl")]
+impl IGlContext for RawGlContext {
+    fn new(window: &RawWindow, attributes: &GlAttributes) -> Result<Self, GlError> {
+        let mut state = window.state.borrow_mut();
+        // The placeholder buffer must not be attached again on the next configure
+        state.remove_placeholder();
+        let size = state.size.to_physical(state.scale_factor);
+        let surface = state.base_surface.as_ref().unwrap();
+
+        unsafe {
+            let egl_window = ffi_dispatch!(
+                wayland_egl_handle(),
+                wl_egl_window_create,
+                surface.id().as_ptr(),
+                size.width as i32,
+                size.height as i32
+            );
+            let display = CONNECTION.backend().display_ptr() as *mut c_void;
+            let egl = EglContext::new(
+                EGL_PLATFORM_WAYLAND_KHR,
+                display,
+                attributes,
+                EGL_WINDOW_BIT,
+                |egl, display, config, surface_attributes| {
+                    let native_window = egl_window as *mut c_void;
+                    let surface_attributes = surface_attributes.as_ptr();
+                    (egl.create_window_surface)(display, config, native_window, surface_attributes)
+                },
+            );
+
+            match egl {
+                Ok(egl) => Ok(Self {
+                    egl: ManuallyDrop::new(egl),
+                    egl_window,
+                }),
+                Err(error) => {
+                    ffi_dispatch!(wayland_egl_handle(), wl_egl_window_destroy, egl_window);
+                    Err(error)
+                }
+            }
+        }
+    }
+
+    fn make_current(&self) -> Result<(), GlError> {
+        self.egl.make_current()
+    }
+
+    fn swap_buffers(&self) {
+        self.egl.swap_buffers();
+    }
+
+    fn set_swap_interval(&self, interval: i32) {
+        self.egl.set_swap_interval(interval);
+    }
+
+    fn get_proc_address(&self, name: &str) -> *const c_void {
+        self.egl.get_proc_address(name)
+    }
+
+    fn size(&self) -> PhysicalSize {
+        self.egl.size()
+    }
+
+    fn resize(&self, size: PhysicalSize) {
+        unsafe {
+            ffi_dispatch!(
+                wayland_egl_handle(),
+                wl_egl_window_resize,
+                self.egl_window,
+                size.width as i32,
+                size.height as i32,
+                0,
+                0
+            );
+        }
+    }
+}
+
+#[cfg(feature = "gl")]
+impl Drop for RawGlContext {
+    fn drop(&mut self) {
+        unsafe {
+            // The EGL surface has to be destroyed before the window it was created for
+            ManuallyDrop::drop(&mut self.egl);
+            ffi_dispatch!(wayland_egl_handle(), wl_egl_window_destroy, self.egl_window);
+        }
+    }
+}
+
+#[cfg(feature = "vulkan")]
+const VK_STRUCTURE_TYPE_WAYLAND_SURFACE_CREATE_INFO_KHR: i32 = 1000006000;
+
+#[cfg(feature = "vulkan")]
+#[repr(C)]
+struct VkWaylandSurfaceCreateInfoKHR {
+    s_type: i32,
+    p_next: *const c_void,
+    flags: u32,
+    display: *mut c_void,
+    surface: *mut c_void,
+}
+
+#[cfg(feature = "vulkan")]
+type VkCreateWaylandSurfaceKHR = unsafe extern "system" fn(
+    VkInstance,
+    *const VkWaylandSurfaceCreateInfoKHR,
+    *const c_void,
+    *mut VkSurfaceKHR,
+) -> VkResult;
+
+#[cfg(feature = "vulkan")]
+impl RawWindow {
+    pub(crate) fn required_vulkan_extensions() -> &'static [&'static str] {
+        &["VK_KHR_surface", "VK_KHR_wayland_surface"]
+    }
+
+    pub(crate) unsafe fn create_vulkan_surface(
+        &self,
+        instance: VkInstance,
+    ) -> Result<VkSurfaceKHR, VulkanError> {
+        let create_surface: VkCreateWaylandSurfaceKHR = std::mem::transmute(instance_proc_address(
+            instance,
+            "vkCreateWaylandSurfaceKHR",
+        )?);
+
+        let mut state = self.state.borrow_mut();
+        // The placeholder buffer must not be attached again on the next configure
+        state.remove_placeholder();
+        let info = VkWaylandSurfaceCreateInfoKHR {
+            s_type: VK_STRUCTURE_TYPE_WAYLAND_SURFACE_CREATE_INFO_KHR,
+            p_next: std::ptr::null(),
+            flags: 0,
+            display: CONNECTION.backend().display_ptr() as *mut c_void,
+            surface: state.base_surface.as_ref().unwrap().id().as_ptr() as *mut c_void,
         };
 
-        build_action.window_created(&instance);
+        let mut surface = 0;
+        match create_surface(instance, &info, std::ptr::null(), &mut surface) {
+            VK_SUCCESS => Ok(surface),
+            result => Err(VulkanError::SurfaceCreationFailed(result)),
+        }
+    }
+}
+
+unsafe impl HasRawWindowHandle for RawWindow {
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        let mut handle = raw_window_handle::WaylandWindowHandle::empty();
+        if let Some(surface) = &self.state.borrow().base_surface {
+            handle.surface = surface.id().as_ptr() as *mut _;
+        }
+        RawWindowHandle::Wayland(handle)
+    }
+}
+
+unsafe impl HasRawDisplayHandle for RawWindow {
+    fn raw_display_handle(&self) -> RawDisplayHandle {
+        let mut handle = raw_window_handle::WaylandDisplayHandle::empty();
+        handle.display = CONNECTION.backend().display_ptr() as *mut _;
+        RawDisplayHandle::Wayland(handle)
+    }
+}
+
+struct State {
+    running: bool,
+    base_surface: Option<wl_surface::WlSurface>,
+    compositor: Option<WlCompositor>,
+    shell: Option<WlShell>,
+    /// Placeholder buffer and its size, shown while placeholder is set.
+    buffer: Option<(wl_buffer::WlBuffer, PhysicalSize)>,
+    /// Set once wl_shm is bound, until the application draws the surface itself.
+    placeholder: bool,
+    wm_base: Option<xdg_wm_base::XdgWmBase>,
+    xdg_surface: Option<(xdg_surface::XdgSurface, xdg_toplevel::XdgToplevel)>,
+    configured: bool,
+    outputs: Vec<(wl_output::WlOutput, MonitorHandle)>,
+    xdg_output_manager: Option<zxdg_output_manager_v1::ZxdgOutputManagerV1>,
+    /// Global names of the outputs the surface is on, in the order it entered them.
+    entered_outputs: Vec<u32>,
+    /// Scale sent by wl_surface.preferred_buffer_scale, which takes precedence over the output scales.
+    preferred_scale: Option<i32>,
+    /// Scale sent by wp_fractional_scale_v1, which takes precedence over the integer scales.
+    fractional_scale: Option<f64>,
+    scale_factor: f64,
+    fractional_scale_manager: Option<wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1>,
+    viewporter: Option<wp_viewporter::WpViewporter>,
+    viewport: Option<wp_viewport::WpViewport>,
+    size: LogicalSize,
+    /// Size requested by the application, converted again when the scale factor changes
+    /// until the compositor sets the size.
+    requested_size: Option<Size>,
+    /// Position of a child window requested by the application, converted again when the
+    /// scale factor changes.
+    requested_position: Option<Position>,
+    /// Events waiting to be passed to the callback of run().
+    events: Vec<WindowEvent>,
+    shm: Option<wl_shm::WlShm>,
+    icon_manager: Option<xdg_toplevel_icon_manager_v1::XdgToplevelIconManagerV1>,
+    wm_dialog: Option<xdg_wm_dialog_v1::XdgWmDialogV1>,
+    dialog: Option<xdg_dialog_v1::XdgDialogV1>,
+    seat: Option<wl_seat::WlSeat>,
+    /// Serial of the last input event, needed to grab the seat for popups.
+    serial: Option<u32>,
+    /// Set on popups before the registry is dispatched, so that an xdg_popup is created instead of a toplevel.
+    popup_placement: Option<PopupPlacement>,
+    popup: Option<(xdg_surface::XdgSurface, xdg_popup::XdgPopup)>,
+    slot_pool: Option<SlotPool>,
+    /// Formats advertised by wl_shm.format.
+    shm_formats: Vec<wl_shm::Format>,
+    frame_format: PixelFormat,
+    subcompositor: Option<wl_subcompositor::WlSubcompositor>,
+    /// Surface of the parent of a child window, set before the registry is dispatched.
+    parent: Option<wl_surface::WlSurface>,
+    subsurface: Option<wl_subsurface::WlSubsurface>,
+    data_device_manager: Option<wl_data_device_manager::WlDataDeviceManager>,
+    data_device: Option<wl_data_device::WlDataDevice>,
+    /// Current clipboard contents of another client, with the MIME types they are offered in.
+    selection_offer: Option<SelectionOffer>,
+    /// Source of the clipboard while this window owns it.
+    selection_source: Option<SelectionSource>,
+    primary_selection_manager:
+        Option<zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1>,
+    primary_selection_device: Option<zwp_primary_selection_device_v1::ZwpPrimarySelectionDeviceV1>,
+    primary_offer: Option<SelectionOffer>,
+    primary_source: Option<SelectionSource>,
+    /// Drag of another client over the surface.
+    drag: Option<DataDrag>,
+
+    title: String,
+}
+
+/// Most buffers of the same size kept for presenting, for compositors holding several of them.
+const MAX_SLOTS: usize = 3;
+/// Damage rectangles kept per slot before the whole slot is copied instead.
+const MAX_SLOT_DAMAGE: usize = 32;
+
+/// wl_shm pool the framebuffer is copied into before it is attached to the surface.
+///
+/// Frames are only written into slots released by the compositor, so that it never reads a
+/// buffer while it is modified. Slots of another size or format are destroyed once they are
+/// released, and the pool grows when new slots do not fit between the remaining ones.
+struct SlotPool {
+    file: File,
+    pool: wl_shm_pool::WlShmPool,
+    /// Length of the pool in bytes, which can only grow.
+    len: usize,
+    slots: Vec<Slot>,
+}
+
+struct Slot {
+    buffer: wl_buffer::WlBuffer,
+    /// Position of the pixels in the pool, in bytes.
+    offset: usize,
+    size: PhysicalSize,
+    format: wl_shm::Format,
+    /// Whether the compositor still uses the buffer.
+    busy: bool,
+    /// Regions of the slot that are older than the framebuffer.
+    damage: Vec<Rect>,
+}
+
+impl Slot {
+    fn len(&self) -> usize {
+        (self.size.width * self.size.height * 4) as usize
+    }
+}
+
+impl SlotPool {
+    fn new(shm: &wl_shm::WlShm, qh: &QueueHandle<State>, len: usize) -> Self {
+        let file = tempfile::tempfile().unwrap();
+        file.set_len(len as u64).unwrap();
+        let pool = shm.create_pool(file.as_fd(), len as i32, qh, ());
 
         Self {
-            state: RefCell::new(state),
-            event_queue: RefCell::new(event_queue),
-            display
+            file,
+            pool,
+            len,
+            slots: Vec::new(),
         }
     }
 
-    fn run<F>(&self, mut callback: F)
-        where
-            F: FnMut(WindowEvent, &mut ControlFlow),
-    {
-        while self.state.borrow().running {
-            self.event_queue.borrow_mut().blocking_dispatch(&mut *self.state.borrow_mut()).unwrap();
+    /// Returns the index of a released slot with the given size and format, and creates one
+    /// when there are fewer than MAX_SLOTS. None means that the compositor has to release one.
+    fn acquire(
+        &mut self,
+        qh: &QueueHandle<State>,
+        size: PhysicalSize,
+        format: wl_shm::Format,
+    ) -> Option<usize> {
+        let current = |slot: &Slot| slot.size == size && slot.format == format;
+        self.slots.retain(|slot| {
+            let stale = !current(slot) && !slot.busy;
+            if stale {
+                slot.buffer.destroy();
+            }
+            !stale
+        });
+
+        if let Some(index) = self
+            .slots
+            .iter()
+            .position(|slot| current(slot) && !slot.busy)
+        {
+            return Some(index);
+        }
+        if self.slots.iter().filter(|slot| current(slot)).count() >= MAX_SLOTS {
+            return None;
+        }
+
+        let stride = size.width * 4;
+        let offset = self.allocate((stride * size.height) as usize);
+        let buffer = self.pool.create_buffer(
+            offset as i32,
+            size.width as i32,
+            size.height as i32,
+            stride as i32,
+            format,
+            qh,
+            (),
+        );
+        self.slots.push(Slot {
+            buffer,
+            offset,
+            size,
+            format,
+            busy: false,
+            damage: vec![Rect::new(0, 0, size.width, size.height)],
+        });
+        Some(self.slots.len() - 1)
+    }
+
+    /// Returns the offset of the first gap of len bytes between the slots,
+    /// growing the pool when there is none.
+    fn allocate(&mut self, len: usize) -> usize {
+        let ranges = self
+            .slots
+            .iter()
+            .map(|slot| (slot.offset, slot.offset + slot.len()))
+            .collect();
+        let offset = first_gap(ranges, len);
+
+        let pool_len = grown_pool_len(self.len, offset + len);
+        if pool_len != self.len {
+            self.len = pool_len;
+            self.file.set_len(self.len as u64).unwrap();
+            self.pool.resize(self.len as i32);
         }
+        offset
     }
 
-    fn get_instance(&self) -> WindowInstance {
-        todo!()
+    /// Copies the regions of the framebuffer that changed since the slot was last written into it.
+    fn write(&mut self, index: usize, pixels: &[u32], damage: &[Rect]) {
+        let size = self.slots[index].size;
+        for slot in self.slots.iter_mut().filter(|slot| slot.size == size) {
+            add_slot_damage(&mut slot.damage, damage, size);
+        }
+
+        let slot = &mut self.slots[index];
+        for row in damaged_rows(&std::mem::take(&mut slot.damage), size.width) {
+            let start = row.start;
+            let row = &pixels[row];
+            let bytes =
+                unsafe { std::slice::from_raw_parts(row.as_ptr() as *const u8, row.len() * 4) };
+            self.file
+                .write_all_at(bytes, (slot.offset + start * 4) as u64)
+                .unwrap();
+        }
     }
 
-    fn set_window_title(&self, title: &str) {
+    fn release(&mut self, buffer: &wl_buffer::WlBuffer) {
+        if let Some(slot) = self.slots.iter_mut().find(|slot| slot.buffer == *buffer) {
+            slot.busy = false;
+        }
     }
+}
 
-    fn set_window_border_width(&self, border_width: u32) {}
+/// Returns the offset of the first gap of len bytes between the byte ranges of the slots.
+fn first_gap(mut ranges: Vec<(usize, usize)>, len: usize) -> usize {
+    ranges.sort_unstable();
+
+    let mut offset = 0;
+    for (start, end) in ranges {
+        if start >= offset + len {
+            break;
+        }
+        offset = end;
+    }
+    offset
+}
 
-    fn set_undecorated(&self, b: bool) {
+/// Returns the length of a pool that ```end``` bytes have to fit in.
+fn grown_pool_len(len: usize, end: usize) -> usize {
+    match end > len {
+        // Doubling avoids resizing the pool for every frame of an interactive resize
+        true => end.max(len * 2),
+        false => len,
+    }
+}
 
+/// Adds the damage of a frame to the regions of a slot that are older than the framebuffer,
+/// which are replaced by the whole slot once there are too many.
+fn add_slot_damage(slot_damage: &mut Vec<Rect>, damage: &[Rect], size: PhysicalSize) {
+    slot_damage.extend_from_slice(damage);
+    if slot_damage.len() > MAX_SLOT_DAMAGE {
+        *slot_damage = vec![Rect::new(0, 0, size.width, size.height)];
+    }
+}
+
+/// Returns the pixel ranges of the framebuffer rows covered by the damage.
+fn damaged_rows(damage: &[Rect], width: u32) -> impl Iterator<Item = Range<usize>> + '_ {
+    let stride = width as usize;
+    damage.iter().flat_map(move |rect| {
+        (rect.y..rect.y + rect.height as i32).map(move |y| {
+            let start = y as usize * stride + rect.x as usize;
+            start..start + rect.width as usize
+        })
+    })
+}
+
+fn shm_format(format: PixelFormat) -> wl_shm::Format {
+    match format {
+        PixelFormat::Argb8888 => wl_shm::Format::Argb8888,
+        PixelFormat::Xrgb8888 => wl_shm::Format::Xrgb8888,
+        PixelFormat::Argb2101010 => wl_shm::Format::Argb2101010,
+        PixelFormat::Xrgb2101010 => wl_shm::Format::Xrgb2101010,
+    }
+}
+
+/// Parent and positioner parameters of a popup, in surface-local coordinates of the parent.
+struct PopupPlacement {
+    parent: xdg_surface::XdgSurface,
+    size: LogicalSize,
+    anchor_rect: (i32, i32, i32, i32),
+    gravity: Gravity,
+    constraint_adjustment: ConstraintAdjustment,
+    grab: Option<(wl_seat::WlSeat, u32)>,
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for State {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global {
+            name,
+            interface,
+            version,
+        } = event
+        {
+            match &interface[..] {
+                "wl_compositor" => {
+                    // wl_surface.preferred_buffer_scale was added in version 6
+                    let compositor = registry.bind::<wl_compositor::WlCompositor, _, _>(
+                        name,
+                        version.min(6),
+                        qh,
+                        (),
+                    );
+                    let surface = compositor.create_surface(qh, ());
+                    state.compositor = Some(compositor);
+                    state.base_surface = Some(surface);
+
+                    if state.wm_base.is_some() && state.xdg_surface.is_none() {
+                        state.init_xdg_surface(qh);
+                    }
+                    if state.viewport.is_none() {
+                        state.init_fractional_scale(qh);
+                    }
+                }
+                "wp_fractional_scale_manager_v1" => {
+                    let manager = registry
+                        .bind::<wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1, _, _>(
+                        name,
+                        1,
+                        qh,
+                        (),
+                    );
+                    state.fractional_scale_manager = Some(manager);
+
+                    if state.viewport.is_none() {
+                        state.init_fractional_scale(qh);
+                    }
+                }
+                "wp_viewporter" => {
+                    let viewporter =
+                        registry.bind::<wp_viewporter::WpViewporter, _, _>(name, 1, qh, ());
+                    state.viewporter = Some(viewporter);
+
+                    if state.viewport.is_none() {
+                        state.init_fractional_scale(qh);
+                    }
+                }
+                "wl_subcompositor" => {
+                    let subcompositor =
+                        registry.bind::<wl_subcompositor::WlSubcompositor, _, _>(name, 1, qh, ());
+                    state.subcompositor = Some(subcompositor);
+                }
+                "wl_shell" => {
+                    let shell = registry.bind::<wl_shell::WlShell, _, _>(name, 1, qh, ());
+                    state.shell = Some(shell);
+                }
+                "wl_shm" => {
+                    let shm = registry.bind::<wl_shm::WlShm, _, _>(name, 1, qh, ());
+                    state.shm = Some(shm);
+                    state.placeholder = true;
+
+                    if state.configured {
+                        state.attach_placeholder(qh);
+                        state.base_surface.as_ref().unwrap().commit();
+                    }
+                }
+                "wl_seat" => {
+                    let seat = registry.bind::<wl_seat::WlSeat, _, _>(name, 1, qh, ());
+                    state.seat = Some(seat);
+
+                    state.init_data_devices(qh);
+                }
+                "wl_data_device_manager" => {
+                    let manager = registry
+                        .bind::<wl_data_device_manager::WlDataDeviceManager, _, _>(
+                            name,
+                            version.min(3),
+                            qh,
+                            (),
+                        );
+                    state.data_device_manager = Some(manager);
+                    state.init_data_devices(qh);
+                }
+                "zwp_primary_selection_device_manager_v1" => {
+                    let manager = registry.bind::<
+                        zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1,
+                        _,
+                        _,
+                    >(name, 1, qh, ());
+                    state.primary_selection_manager = Some(manager);
+                    state.init_data_devices(qh);
+                }
+                "xdg_toplevel_icon_manager_v1" => {
+                    let manager = registry
+                        .bind::<xdg_toplevel_icon_manager_v1::XdgToplevelIconManagerV1, _, _>(
+                            name,
+                            1,
+                            qh,
+                            (),
+                        );
+                    state.icon_manager = Some(manager);
+                }
+                "xdg_wm_dialog_v1" => {
+                    let wm_dialog =
+                        registry.bind::<xdg_wm_dialog_v1::XdgWmDialogV1, _, _>(name, 1, qh, ());
+                    state.wm_dialog = Some(wm_dialog);
+                }
+                "wl_output" => {
+                    let output =
+                        registry.bind::<wl_output::WlOutput, _, _>(name, version.min(4), qh, name);
+                    if let Some(manager) = &state.xdg_output_manager {
+                        manager.get_xdg_output(&output, qh, name);
+                    }
+                    let monitor = MonitorHandle {
+                        id: name as u64,
+                        name: None,
+                        position: (0, 0),
+                        size: (0, 0),
+                        size_mm: (0, 0),
+                        scale_factor: 1.0,
+                        primary: state.outputs.is_empty(),
+                        current_mode: None,
+                        modes: Vec::new(),
+                    };
+                    state.outputs.push((output, monitor));
+                }
+                "zxdg_output_manager_v1" => {
+                    let manager = registry
+                        .bind::<zxdg_output_manager_v1::ZxdgOutputManagerV1, _, _>(
+                            name,
+                            version.min(3),
+                            qh,
+                            (),
+                        );
+                    for (output, monitor) in &state.outputs {
+                        manager.get_xdg_output(output, qh, monitor.id as u32);
+                    }
+                    state.xdg_output_manager = Some(manager);
+                }
+                "xdg_wm_base" => {
+                    let wm_base = registry.bind::<xdg_wm_base::XdgWmBase, _, _>(name, 1, qh, ());
+                    state.wm_base = Some(wm_base);
+
+                    if state.base_surface.is_some() && state.xdg_surface.is_none() {
+                        state.init_xdg_surface(qh);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, u32> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_output::WlOutput,
+        event: wl_output::Event,
+        name: &u32,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        let monitor = match state.outputs.iter_mut().find(|(_, m)| m.id == *name as u64) {
+            Some((_, monitor)) => monitor,
+            None => return,
+        };
+
+        match event {
+            wl_output::Event::Geometry {
+                x,
+                y,
+                physical_width,
+                physical_height,
+                ..
+            } => {
+                monitor.position = (x, y);
+                monitor.size_mm = (physical_width as u32, physical_height as u32);
+            }
+            wl_output::Event::Mode {
+                flags,
+                width,
+                height,
+                refresh,
+            } => {
+                let size = (width as u32, height as u32);
+                let refresh_rate_millihertz = refresh as u32;
+                // Compositors send the mode again whenever it becomes current
+                let known = monitor.modes.iter().position(|mode| {
+                    (mode.size, mode.refresh_rate_millihertz) == (size, refresh_rate_millihertz)
+                });
+                let info = ModeInfo {
+                    id: known.unwrap_or(monitor.modes.len()) as u64,
+                    size,
+                    // wl_output does not report the bit depth
+                    bit_depth: 0,
+                    refresh_rate_millihertz,
+                };
+                match known {
+                    Some(index) => monitor.modes[index] = info,
+                    None => monitor.modes.push(info),
+                }
+                if let WEnum::Value(flags) = flags {
+                    if flags.contains(wl_output::Mode::Current) {
+                        monitor.size = info.size;
+                        monitor.current_mode = Some(info);
+                    }
+                }
+            }
+            wl_output::Event::Scale { factor } => {
+                monitor.scale_factor = factor as f64;
+                state.update_scale_factor(qh);
+            }
+            wl_output::Event::Name { name } => {
+                monitor.name = Some(name);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<xdg_toplevel_icon_manager_v1::XdgToplevelIconManagerV1, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &xdg_toplevel_icon_manager_v1::XdgToplevelIconManagerV1,
+        _: xdg_toplevel_icon_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // the preferred icon sizes are ignored, every size of the icon is sent
+    }
+}
+
+impl Dispatch<xdg_toplevel_icon_v1::XdgToplevelIconV1, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &xdg_toplevel_icon_v1::XdgToplevelIconV1,
+        _: xdg_toplevel_icon_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // xdg_toplevel_icon_v1 has no event
+    }
+}
+
+impl Dispatch<wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+        _: wp_fractional_scale_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // wp_fractional_scale_manager_v1 has no event
+    }
+}
+
+impl Dispatch<wp_fractional_scale_v1::WpFractionalScaleV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &wp_fractional_scale_v1::WpFractionalScaleV1,
+        event: wp_fractional_scale_v1::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
+            // The scale is sent as a numerator over 120
+            state.fractional_scale = Some(scale as f64 / 120.0);
+            state.update_scale_factor(qh);
+        }
+    }
+}
+
+impl Dispatch<wp_viewporter::WpViewporter, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &wp_viewporter::WpViewporter,
+        _: wp_viewporter::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // wp_viewporter has no event
+    }
+}
+
+impl Dispatch<xdg_wm_dialog_v1::XdgWmDialogV1, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &xdg_wm_dialog_v1::XdgWmDialogV1,
+        _: xdg_wm_dialog_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // xdg_wm_dialog_v1 has no event
+    }
+}
+
+impl Dispatch<xdg_dialog_v1::XdgDialogV1, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &xdg_dialog_v1::XdgDialogV1,
+        _: xdg_dialog_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // xdg_dialog_v1 has no event
+    }
+}
+
+impl Dispatch<wp_viewport::WpViewport, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &wp_viewport::WpViewport,
+        _: wp_viewport::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // wp_viewport has no event
+    }
+}
+
+impl Dispatch<zxdg_output_manager_v1::ZxdgOutputManagerV1, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &zxdg_output_manager_v1::ZxdgOutputManagerV1,
+        _: zxdg_output_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // zxdg_output_manager_v1 has no event
+    }
+}
+
+impl Dispatch<zxdg_output_v1::ZxdgOutputV1, u32> for State {
+    fn event(
+        state: &mut Self,
+        _: &zxdg_output_v1::ZxdgOutputV1,
+        event: zxdg_output_v1::Event,
+        name: &u32,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let monitor = match state.outputs.iter_mut().find(|(_, m)| m.id == *name as u64) {
+            Some((_, monitor)) => monitor,
+            None => return,
+        };
+
+        // xdg_output reports the position in the compositor space, which wl_output.geometry does not
+        match event {
+            zxdg_output_v1::Event::LogicalPosition { x, y } => {
+                monitor.position = (x, y);
+            }
+            zxdg_output_v1::Event::Name { name } if monitor.name.is_none() => {
+                monitor.name = Some(name);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_shell::WlShell, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_shell::WlShell,
+        _: wl_shell::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // wl_shell has no event
+    }
+}
+
+impl Dispatch<wl_compositor::WlCompositor, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_compositor::WlCompositor,
+        _: wl_compositor::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // wl_compositor has no event
+    }
+}
+
+impl Dispatch<wl_surface::WlSurface, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_surface::WlSurface,
+        event: wl_surface::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_surface::Event::Enter { output } => {
+                if let Some((_, monitor)) = state.outputs.iter().find(|(o, _)| *o == output) {
+                    state.entered_outputs.push(monitor.id as u32);
+                }
+            }
+            wl_surface::Event::Leave { output } => {
+                if let Some((_, monitor)) = state.outputs.iter().find(|(o, _)| *o == output) {
+                    let name = monitor.id as u32;
+                    state.entered_outputs.retain(|entered| *entered != name);
+                }
+            }
+            wl_surface::Event::PreferredBufferScale { factor } => {
+                state.preferred_scale = Some(factor);
+            }
+            _ => {}
+        }
+        state.update_scale_factor(qh);
+    }
+}
+
+impl Dispatch<wl_shm::WlShm, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_shm::WlShm,
+        event: wl_shm::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_shm::Event::Format {
+            format: WEnum::Value(format),
+        } = event
+        {
+            if !state.shm_formats.contains(&format) {
+                state.shm_formats.push(format);
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_shm_pool::WlShmPool, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_shm_pool::WlShmPool,
+        _: wl_shm_pool::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // wl_shm_pool has no events
+    }
+}
+
+impl Dispatch<wl_buffer::WlBuffer, ()> for State {
+    fn event(
+        state: &mut Self,
+        buffer: &wl_buffer::WlBuffer,
+        event: wl_buffer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_buffer::Event::Release = event {
+            if let Some(pool) = &mut state.slot_pool {
+                pool.release(buffer);
+            }
+        }
+    }
+}
+
+/// Gradient shown until the application presents a frame or renders with GL or Vulkan.
+fn placeholder_pixels(size: PhysicalSize) -> Vec<u32> {
+    use std::cmp::min;
+    let (buf_x, buf_y) = (size.width, size.height);
+    let mut pixels = Vec::with_capacity((buf_x * buf_y) as usize);
+    for y in 0..buf_y {
+        for x in 0..buf_x {
+            let a = 0xFF;
+            let r = min(((buf_x - x) * 0xFF) / buf_x, ((buf_y - y) * 0xFF) / buf_y);
+            let g = min((x * 0xFF) / buf_x, ((buf_y - y) * 0xFF) / buf_y);
+            let b = min(((buf_x - x) * 0xFF) / buf_x, (y * 0xFF) / buf_y);
+
+            pixels.push((a << 24) + (r << 16) + (g << 8) + b);
+        }
+    }
+    pixels
+}
+
+/// Pads the icon to a square, as required by xdg_toplevel_icon_v1,
+/// and converts it to the premultiplied ARGB8888 of wl_shm.
+fn square_premultiplied_argb(icon: &Icon) -> (u32, Vec<u32>) {
+    let size = icon.width.max(icon.height);
+    let (left, top) = ((size - icon.width) / 2, (size - icon.height) / 2);
+
+    let mut pixels = vec![0; (size * size) as usize];
+    for (i, argb) in icon.to_argb().into_iter().enumerate() {
+        let (x, y) = (i as u32 % icon.width, i as u32 / icon.width);
+        let alpha = argb >> 24;
+        let premultiply = |shift: u32| ((argb >> shift & 0xFF) * alpha / 0xFF) << shift;
+        pixels[((top + y) * size + left + x) as usize] =
+            alpha << 24 | premultiply(16) | premultiply(8) | premultiply(0);
+    }
+
+    (size, pixels)
+}
+
+impl State {
+    fn new(title: String, size: Size) -> Self {
+        Self {
+            running: true,
+            base_surface: None,
+            compositor: None,
+            shell: None,
+            buffer: None,
+            placeholder: false,
+            wm_base: None,
+            xdg_surface: None,
+            configured: false,
+            outputs: Vec::new(),
+            xdg_output_manager: None,
+            entered_outputs: Vec::new(),
+            preferred_scale: None,
+            fractional_scale: None,
+            scale_factor: 1.0,
+            fractional_scale_manager: None,
+            viewporter: None,
+            viewport: None,
+            size: size.to_logical(1.0),
+            requested_size: Some(size),
+            requested_position: None,
+            events: Vec::new(),
+            shm: None,
+            icon_manager: None,
+            wm_dialog: None,
+            dialog: None,
+            seat: None,
+            serial: None,
+            popup_placement: None,
+            popup: None,
+            slot_pool: None,
+            // Supported by every compositor
+            shm_formats: vec![wl_shm::Format::Argb8888, wl_shm::Format::Xrgb8888],
+            frame_format: PixelFormat::default(),
+            subcompositor: None,
+            parent: None,
+            subsurface: None,
+            data_device_manager: None,
+            data_device: None,
+            selection_offer: None,
+            selection_source: None,
+            primary_selection_manager: None,
+            primary_selection_device: None,
+            primary_offer: None,
+            primary_source: None,
+            drag: None,
+
+            title,
+        }
+    }
+
+    /// Creates a wl_buffer holding a copy of the given ARGB8888 pixels.
+    fn create_shm_buffer(
+        &self,
+        qh: &QueueHandle<State>,
+        width: u32,
+        height: u32,
+        pixels: &[u32],
+    ) -> wl_buffer::WlBuffer {
+        use std::io::Write;
+
+        // One write, the placeholder of a large surface has millions of pixels
+        let bytes: Vec<u8> = pixels
+            .iter()
+            .flat_map(|pixel| pixel.to_ne_bytes())
+            .collect();
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&bytes).unwrap();
+
+        let shm = self.shm.as_ref().unwrap();
+        let pool = shm.create_pool(file.as_fd(), (width * height * 4) as i32, qh, ());
+        let buffer = pool.create_buffer(
+            0,
+            width as i32,
+            height as i32,
+            (width * 4) as i32,
+            wl_shm::Format::Argb8888,
+            qh,
+            (),
+        );
+        pool.destroy();
+        buffer
+    }
+
+    /// Attaches the placeholder buffer, re-created when the logical size or the scale factor
+    /// changed, since the buffer has to cover the logical size at the scale factor.
+    fn attach_placeholder(&mut self, qh: &QueueHandle<State>) {
+        let size = self.size.to_physical(self.scale_factor);
+        if !self.placeholder || size.width == 0 || size.height == 0 {
+            return;
+        }
+
+        if self.buffer.as_ref().map(|(_, buffer_size)| *buffer_size) != Some(size) {
+            if let Some((buffer, _)) = self.buffer.take() {
+                buffer.destroy();
+            }
+            let pixels = placeholder_pixels(size);
+            let buffer = self.create_shm_buffer(qh, size.width, size.height, &pixels);
+            self.buffer = Some((buffer, size));
+        }
+
+        let (buffer, _) = self.buffer.as_ref().unwrap();
+        self.base_surface
+            .as_ref()
+            .unwrap()
+            .attach(Some(buffer), 0, 0);
+    }
+
+    /// Stops showing the placeholder buffer once the application draws the surface itself.
+    fn remove_placeholder(&mut self) {
+        self.placeholder = false;
+        if let Some((buffer, _)) = self.buffer.take() {
+            buffer.destroy();
+        }
+    }
+
+    /// Recomputes the scale factor of the surface and tells the compositor and the application when it changed.
+    fn update_scale_factor(&mut self, qh: &QueueHandle<State>) {
+        let scale_factor = match (self.fractional_scale, self.preferred_scale) {
+            (Some(scale), _) => scale,
+            (None, Some(scale)) => scale as f64,
+            (None, None) => self
+                .outputs
+                .iter()
+                .filter(|(_, monitor)| self.entered_outputs.contains(&(monitor.id as u32)))
+                .map(|(_, monitor)| monitor.scale_factor)
+                .reduce(f64::max)
+                .unwrap_or(self.scale_factor),
+        };
+
+        if scale_factor != self.scale_factor {
+            self.scale_factor = scale_factor;
+            if let Some(size) = self.requested_size {
+                self.size = size.to_logical(scale_factor);
+            }
+            if let Some(position) = self.requested_position {
+                self.move_subsurface(position);
+            }
+            if let Some(surface) = self.base_surface.clone() {
+                // With a viewport the buffer is scaled by the compositor, so the buffer scale stays 1
+                match self.viewport {
+                    Some(_) => self.update_viewport(),
+                    None => surface.set_buffer_scale(scale_factor as i32),
+                }
+                if self.configured || self.subsurface.is_some() {
+                    self.attach_placeholder(qh);
+                }
+                surface.commit();
+            }
+            self.events
+                .push(WindowEvent::ScaleFactorChanged(scale_factor));
+        }
+    }
+
+    /// Maps the buffer, which is sized in physical pixels, onto the logical size of the surface.
+    fn update_viewport(&self) {
+        if let Some(viewport) = &self.viewport {
+            viewport.set_destination(
+                self.size.width.round() as i32,
+                self.size.height.round() as i32,
+            );
+        }
+    }
+
+    /// Creates the viewport and the fractional scale object once the surface and both managers exist.
+    fn init_fractional_scale(&mut self, qh: &QueueHandle<State>) {
+        let (surface, manager, viewporter) = match (
+            &self.base_surface,
+            &self.fractional_scale_manager,
+            &self.viewporter,
+        ) {
+            (Some(surface), Some(manager), Some(viewporter)) => (surface, manager, viewporter),
+            _ => return,
+        };
+
+        manager.get_fractional_scale(surface, qh, ());
+        self.viewport = Some(viewporter.get_viewport(surface, qh, ()));
+        self.update_viewport();
+    }
+
+    fn output(&self, monitor: &MonitorHandle) -> Option<&wl_output::WlOutput> {
+        self.outputs
+            .iter()
+            .find(|(_, m)| m.id == monitor.id)
+            .map(|(output, _)| output)
+    }
+
+    fn init_xdg_surface(&mut self, qh: &QueueHandle<State>) {
+        // Child windows are subsurfaces and have no xdg role
+        if self.parent.is_some() {
+            return;
+        }
+
+        let wm_base = self.wm_base.as_ref().unwrap();
+        let base_surface = self.base_surface.as_ref().unwrap();
+
+        let xdg_surface = wm_base.get_xdg_surface(base_surface, qh, ());
+
+        if let Some(placement) = &self.popup_placement {
+            let (x, y, width, height) = placement.anchor_rect;
+            let (anchor, gravity) = positioner_gravity(placement.gravity);
+
+            let positioner = wm_base.create_positioner(qh, ());
+            positioner.set_size(placement.size.width as i32, placement.size.height as i32);
+            positioner.set_anchor_rect(x, y, width, height);
+            positioner.set_anchor(anchor);
+            positioner.set_gravity(gravity);
+            positioner.set_constraint_adjustment(
+                xdg_positioner::ConstraintAdjustment::from_bits_truncate(
+                    placement.constraint_adjustment.0,
+                ),
+            );
+
+            let popup = xdg_surface.get_popup(Some(&placement.parent), &positioner, qh, ());
+            positioner.destroy();
+            if let Some((seat, serial)) = &placement.grab {
+                popup.grab(seat, *serial);
+            }
+            base_surface.commit();
+
+            self.popup = Some((xdg_surface, popup));
+            return;
+        }
+
+        let toplevel = xdg_surface.get_toplevel(qh, ());
+        toplevel.set_title(self.title.clone());
+
+        base_surface.commit();
+
+        self.xdg_surface = Some((xdg_surface, toplevel));
+    }
+}
+
+impl RawWindow {
+    fn from_state(state: State, event_queue: EventQueue<State>, display: WlDisplay) -> Self {
+        let xdg_surface = match (&state.xdg_surface, &state.popup) {
+            (Some((xdg_surface, _)), _) | (_, Some((xdg_surface, _))) => Some(xdg_surface.clone()),
+            _ => None,
+        };
+        update_surface_role(state.base_surface.as_ref().unwrap().id(), |role| {
+            role.xdg_surface = xdg_surface;
+        });
+
+        Self {
+            surface: state.base_surface.clone().unwrap(),
+            compositor: state.compositor.clone().unwrap(),
+            shell: state.shell.clone(),
+            state: RefCell::new(state),
+            event_queue: RefCell::new(event_queue),
+            display,
+            fullscreen: RefCell::new(None),
+            framebuffer: RefCell::new(Vec::new()),
+            framebuffer_size: RefCell::new(PhysicalSize::default()),
+        }
+    }
+}
+
+impl Drop for RawWindow {
+    fn drop(&mut self) {
+        let surface = self.surface.id();
+        SURFACE_ROLES
+            .lock()
+            .unwrap()
+            .retain(|role| role.surface != surface);
+    }
+}
+
+impl RawWindow {
+    /// Dispatches the next events like ```blocking_dispatch```, while reading the files of a drag
+    /// that entered the surface, so that a slow source does not hold up the event queue.
+    fn dispatch(&self) {
+        let mut event_queue = self.event_queue.borrow_mut();
+        let mut state = self.state.borrow_mut();
+        let Some(transfer) = state.drag.as_ref().and_then(|drag| drag.transfer.as_ref()) else {
+            event_queue.blocking_dispatch(&mut *state).unwrap();
+            return;
+        };
+        let transfer = transfer.pipe.as_raw_fd();
+
+        if event_queue.dispatch_pending(&mut *state).unwrap() > 0 {
+            return;
+        }
+        event_queue.flush().unwrap();
+
+        if let Some(guard) = event_queue.prepare_read() {
+            let mut poll_fds =
+                [guard.connection_fd().as_raw_fd(), transfer].map(|fd| libc::pollfd {
+                    fd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                });
+            let ready = unsafe {
+                libc::poll(
+                    poll_fds.as_mut_ptr(),
+                    poll_fds.len() as libc::nfds_t,
+                    SELECTION_TIMEOUT.as_millis() as i32,
+                )
+            };
+
+            if poll_fds[0].revents != 0 {
+                match guard.read() {
+                    Ok(_) => {}
+                    Err(WaylandError::Io(error)) if error.kind() == ErrorKind::WouldBlock => {}
+                    Err(error) => panic!("{}", error),
+                }
+            } else {
+                drop(guard);
+            }
+            match ready {
+                // The source did not write anything in time
+                0 => state.cancel_drag_transfer(),
+                _ if poll_fds[1].revents != 0 => state.read_drag_transfer(),
+                _ => {}
+            }
+        }
+        event_queue.dispatch_pending(&mut *state).unwrap();
+    }
+
+    // XEmbed is an X11 protocol
+    pub(crate) fn xembed(&self, _socket: u64) {}
+}
+
+/// How long the selection owner may take to write the next part of the contents.
+const SELECTION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Contents offered by a source of this client, by MIME type.
+type SourceContents = Arc<Vec<(String, Vec<u8>)>>;
+
+/// User data of wl_data_offer, filled in by the events that follow its creation.
+#[derive(Default)]
+struct DataOfferInfo {
+    mime_types: Mutex<Vec<String>>,
+    /// Drag and drop actions allowed by the source, since version 3.
+    source_actions: Mutex<Vec<DropAction>>,
+}
+
+/// Selection of another client, announced by wl_data_device or the primary selection device.
+enum SelectionOffer {
+    Data(wl_data_offer::WlDataOffer),
+    Primary(zwp_primary_selection_offer_v1::ZwpPrimarySelectionOfferV1),
+}
+
+impl SelectionOffer {
+    /// Returns the MIME types announced by the offer events.
+    fn mime_types(&self) -> Vec<String> {
+        let mime_types = match self {
+            SelectionOffer::Data(offer) => {
+                offer.data::<DataOfferInfo>().map(|info| &info.mime_types)
+            }
+            SelectionOffer::Primary(offer) => offer.data::<Mutex<Vec<String>>>(),
+        };
+        mime_types
+            .map(|mime_types| mime_types.lock().unwrap().clone())
+            .unwrap_or_default()
+    }
+
+    fn receive(&self, mime_type: &str, fd: BorrowedFd) {
+        match self {
+            SelectionOffer::Data(offer) => offer.receive(mime_type.to_owned(), fd),
+            SelectionOffer::Primary(offer) => offer.receive(mime_type.to_owned(), fd),
+        }
+    }
+
+    fn destroy(&self) {
+        match self {
+            SelectionOffer::Data(offer) => offer.destroy(),
+            SelectionOffer::Primary(offer) => offer.destroy(),
+        }
+    }
+}
+
+/// Selection owned by this window, served from the contents stored in the source.
+enum SelectionSource {
+    Data(wl_data_source::WlDataSource),
+    Primary(zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1),
+}
+
+impl SelectionSource {
+    fn contents(&self) -> SourceContents {
+        let contents = match self {
+            SelectionSource::Data(source) => source.data::<SourceContents>(),
+            SelectionSource::Primary(source) => source.data::<SourceContents>(),
+        };
+        contents.cloned().unwrap_or_default()
+    }
+
+    fn destroy(&self) {
+        match self {
+            SelectionSource::Data(source) => source.destroy(),
+            SelectionSource::Primary(source) => source.destroy(),
+        }
+    }
+}
+
+impl RawWindow {
+    pub(crate) fn clipboard_get(
+        &self,
+        selection: Selection,
+        mime_type: &str,
+    ) -> Result<Vec<u8>, ClipboardError> {
+        let state = self.state.borrow();
+
+        // Reading our own offer would block, as it is served by this thread
+        if let Some(source) = state.selection_source(selection) {
+            return source
+                .contents()
+                .iter()
+                .find(|(m, _)| m == mime_type)
+                .map(|(_, data)| data.clone())
+                .ok_or(ClipboardError::NoContent);
+        }
+
+        let offer = state
+            .selection_offer(selection)
+            .ok_or(ClipboardError::NoContent)?;
+        if !offer.mime_types().iter().any(|m| m == mime_type) {
+            return Err(ClipboardError::NoContent);
+        }
+
+        let (read, write) = pipe().map_err(|error| ClipboardError::Io(error.to_string()))?;
+        offer.receive(mime_type, write.as_fd());
+        // The write end is closed here, so that only the owner keeps the pipe open
+        drop(write);
+        drop(state);
+        self.event_queue
+            .borrow()
+            .flush()
+            .map_err(|error| ClipboardError::Io(error.to_string()))?;
+
+        read_pipe(read)
+    }
+
+    pub(crate) fn clipboard_set(
+        &self,
+        selection: Selection,
+        contents: Vec<(String, Vec<u8>)>,
+    ) -> Result<(), ClipboardError> {
+        let qh = self.event_queue.borrow().handle();
+        let mut state = self.state.borrow_mut();
+
+        // Only focused clients may set a selection, proven by the serial of an input event
+        let serial = state.serial;
+        let mime_types = contents
+            .iter()
+            .map(|(mime_type, _)| mime_type.clone())
+            .collect::<Vec<String>>();
+        let contents = Arc::new(contents);
+
+        let source = match selection {
+            Selection::Clipboard => {
+                let (Some(manager), Some(device)) =
+                    (&state.data_device_manager, &state.data_device)
+                else {
+                    return Err(ClipboardError::NotSupported(
+                        "wl_data_device_manager".to_owned(),
+                    ));
+                };
+                let serial = serial.ok_or(ClipboardError::Denied)?;
+
+                let source = manager.create_data_source(&qh, contents);
+                for mime_type in mime_types {
+                    source.offer(mime_type);
+                }
+                device.set_selection(Some(&source), serial);
+                SelectionSource::Data(source)
+            }
+            Selection::Primary => {
+                let (Some(manager), Some(device)) = (
+                    &state.primary_selection_manager,
+                    &state.primary_selection_device,
+                ) else {
+                    return Err(ClipboardError::NotSupported(
+                        "zwp_primary_selection_device_manager_v1".to_owned(),
+                    ));
+                };
+                let serial = serial.ok_or(ClipboardError::Denied)?;
+
+                let source = manager.create_source(&qh, contents);
+                for mime_type in mime_types {
+                    source.offer(mime_type);
+                }
+                device.set_selection(Some(&source), serial);
+                SelectionSource::Primary(source)
+            }
+        };
+
+        let previous = match selection {
+            Selection::Clipboard => state.selection_source.replace(source),
+            Selection::Primary => state.primary_source.replace(source),
+        };
+        if let Some(previous) = previous {
+            previous.destroy();
+        }
+        drop(state);
+        self.event_queue.borrow().flush().unwrap();
+        Ok(())
+    }
+
+    pub(crate) fn clipboard_mime_types(
+        &self,
+        selection: Selection,
+    ) -> Result<Vec<String>, ClipboardError> {
+        let state = self.state.borrow();
+
+        Ok(
+            match (
+                state.selection_source(selection),
+                state.selection_offer(selection),
+            ) {
+                (Some(source), _) => source
+                    .contents()
+                    .iter()
+                    .map(|(mime_type, _)| mime_type.clone())
+                    .collect(),
+                (None, Some(offer)) => offer.mime_types(),
+                (None, None) => Vec::new(),
+            },
+        )
+    }
+}
+
+/// Drag of another client over the surface, announced by wl_data_device.enter.
+/// DragEntered is sent once the files have been read.
+struct DataDrag {
+    offer: wl_data_offer::WlDataOffer,
+    /// Serial of the enter event, needed to accept the offer.
+    serial: u32,
+    /// Reading of the files from the source, read by the run loop between events.
+    transfer: Option<DragTransfer>,
+    paths: Vec<PathBuf>,
+    position: PhysicalPosition,
+    /// Action accepted by the callback, None while the drop is rejected.
+    action: Option<DropAction>,
+}
+
+struct DragTransfer {
+    pipe: File,
+    data: Vec<u8>,
+}
+
+impl State {
+    /// Reads the next part of the files of the drag, and announces the drag once all arrived.
+    fn read_drag_transfer(&mut self) {
+        let Some(transfer) = self.drag.as_mut().and_then(|drag| drag.transfer.as_mut()) else {
+            return;
+        };
+        let mut buffer = [0; 64 * 1024];
+        match transfer.pipe.read(&mut buffer) {
+            Ok(0) => {}
+            Ok(len) => return transfer.data.extend_from_slice(&buffer[..len]),
+            Err(error) if error.kind() == ErrorKind::Interrupted => return,
+            Err(_) => return self.cancel_drag_transfer(),
+        }
+
+        let drag = self.drag.as_mut().unwrap();
+        let data = drag.transfer.take().unwrap().data;
+        drag.paths = parse_uri_list(&data);
+        if drag.paths.is_empty() {
+            return self.cancel_drag_transfer();
+        }
+        self.events.push(WindowEvent::DragEntered {
+            paths: drag.paths.clone(),
+            position: drag.position,
+        });
+    }
+
+    /// Ignores the drag when its files cannot be read.
+    fn cancel_drag_transfer(&mut self) {
+        if let Some(drag) = self.drag.take() {
+            drag.offer.destroy();
+        }
+    }
+}
+
+impl RawWindow {
+    pub(crate) fn set_drag_action(&self, action: Option<DropAction>) {
+        {
+            let mut state = self.state.borrow_mut();
+            let Some(drag) = &mut state.drag else {
+                return;
+            };
+            drag.action = action;
+
+            drag.offer
+                .accept(drag.serial, action.map(|_| "text/uri-list".to_owned()));
+            // Before version 3 accepting the type accepts the drop, the compositor picks the action
+            if drag.offer.version() >= 3 {
+                let action = action.map_or(wl_data_device_manager::DndAction::None, dnd_action);
+                drag.offer.set_actions(action, action);
+            }
+        }
+        let _ = self.event_queue.borrow().flush();
+    }
+
+    pub(crate) fn drag_source_actions(&self) -> Vec<DropAction> {
+        let state = self.state.borrow();
+        state
+            .drag
+            .as_ref()
+            .and_then(|drag| drag.offer.data::<DataOfferInfo>())
+            .map(|info| info.source_actions.lock().unwrap().clone())
+            .unwrap_or_default()
+    }
+}
+
+fn dnd_action(action: DropAction) -> wl_data_device_manager::DndAction {
+    match action {
+        DropAction::Copy => wl_data_device_manager::DndAction::Copy,
+        DropAction::Move => wl_data_device_manager::DndAction::Move,
+        // There is no link action, so no action can be negotiated
+        DropAction::Link => wl_data_device_manager::DndAction::None,
+    }
+}
+
+fn pipe() -> std::io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    unsafe { Ok((OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))) }
+}
+
+/// Reads until the other end of the pipe is closed.
+fn read_pipe(fd: OwnedFd) -> Result<Vec<u8>, ClipboardError> {
+    let mut pipe = File::from(fd);
+    let mut data = Vec::new();
+    let mut buffer = [0; 64 * 1024];
+
+    loop {
+        let mut poll_fd = libc::pollfd {
+            fd: pipe.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut poll_fd, 1, SELECTION_TIMEOUT.as_millis() as i32) } == 0 {
+            return Err(ClipboardError::Timeout);
+        }
+
+        match pipe.read(&mut buffer) {
+            Ok(0) => return Ok(data),
+            Ok(len) => data.extend_from_slice(&buffer[..len]),
+            Err(error) if error.kind() == ErrorKind::Interrupted => {}
+            Err(error) => return Err(ClipboardError::Io(error.to_string())),
+        }
+    }
+}
+
+impl State {
+    /// Moves a child window in its parent. The position is applied with the next commit of the
+    /// parent.
+    fn move_subsurface(&mut self, position: Position) {
+        let Some(subsurface) = &self.subsurface else {
+            return;
+        };
+        self.requested_position = Some(position);
+        let position = position.to_logical(self.scale_factor);
+        let position = (position.x as i32, position.y as i32);
+        subsurface.set_position(position.0, position.1);
+        update_surface_role(self.base_surface.as_ref().unwrap().id(), |role| {
+            if let Some(parent) = &mut role.parent {
+                parent.1 = position;
+            }
+        });
+    }
+
+    fn init_subsurface(&mut self, qh: &QueueHandle<State>, position: Position) {
+        let surface = self.base_surface.clone().unwrap();
+        let subsurface = self.subcompositor.as_ref().unwrap().get_subsurface(
+            &surface,
+            self.parent.as_ref().unwrap(),
+            qh,
+            (),
+        );
+        self.subsurface = Some(subsurface);
+        let parent = self.parent.as_ref().unwrap().id();
+        update_surface_role(surface.id(), |role| role.parent = Some((parent, (0, 0))));
+        self.move_subsurface(position);
+
+        // Subsurfaces are not configured, so the buffer is attached right away
+        self.attach_placeholder(qh);
+        surface.commit();
+    }
+
+    /// Creates the devices of the seat for the selection managers bound so far.
+    fn init_data_devices(&mut self, qh: &QueueHandle<State>) {
+        let Some(seat) = &self.seat else {
+            return;
+        };
+
+        if let (Some(manager), None) = (&self.data_device_manager, &self.data_device) {
+            self.data_device = Some(manager.get_data_device(seat, qh, ()));
+        }
+        if let (Some(manager), None) = (
+            &self.primary_selection_manager,
+            &self.primary_selection_device,
+        ) {
+            self.primary_selection_device = Some(manager.get_device(seat, qh, ()));
+        }
+    }
+
+    fn selection_offer(&self, selection: Selection) -> Option<&SelectionOffer> {
+        match selection {
+            Selection::Clipboard => self.selection_offer.as_ref(),
+            Selection::Primary => self.primary_offer.as_ref(),
+        }
+    }
+
+    fn selection_source(&self, selection: Selection) -> Option<&SelectionSource> {
+        match selection {
+            Selection::Clipboard => self.selection_source.as_ref(),
+            Selection::Primary => self.primary_source.as_ref(),
+        }
+    }
+}
+
+impl Dispatch<xdg_wm_base::XdgWmBase, ()> for State {
+    fn event(
+        _: &mut Self,
+        wm_base: &xdg_wm_base::XdgWmBase,
+        event: xdg_wm_base::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let xdg_wm_base::Event::Ping { serial } = event {
+            wm_base.pong(serial);
+        }
     }
+}
 
-    fn show(&self) {
+impl Dispatch<xdg_surface::XdgSurface, ()> for State {
+    fn event(
+        state: &mut Self,
+        xdg_surface: &xdg_surface::XdgSurface,
+        event: xdg_surface::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let xdg_surface::Event::Configure { serial, .. } = event {
+            xdg_surface.ack_configure(serial);
+            state.configured = true;
+            let size = state.size.to_physical(state.scale_factor);
+            state.events.push(WindowEvent::Expose(Rect::new(
+                0,
+                0,
+                size.width,
+                size.height,
+            )));
+            state.attach_placeholder(qh);
+            state.base_surface.as_ref().unwrap().commit();
+        }
     }
+}
 
-    fn hide(&self) {
+impl Dispatch<xdg_toplevel::XdgToplevel, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &xdg_toplevel::XdgToplevel,
+        event: xdg_toplevel::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let xdg_toplevel::Event::Configure { width, height, .. } = event {
+            // 0 means that the client decides the size
+            if width > 0 && height > 0 {
+                state.size = LogicalSize::new(width as f64, height as f64);
+                state.requested_size = None;
+                state.update_viewport();
+            }
+        }
+        if let xdg_toplevel::Event::Close = event {
+            state.running = false;
+        }
     }
+}
 
-    fn get_window_pos(&self) -> (u32, u32) {
-        (0,0)
+impl Dispatch<wl_seat::WlSeat, ()> for State {
+    fn event(
+        _: &mut Self,
+        seat: &wl_seat::WlSeat,
+        event: wl_seat::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_seat::Event::Capabilities {
+            capabilities: WEnum::Value(capabilities),
+        } = event
+        {
+            if capabilities.contains(wl_seat::Capability::Keyboard) {
+                seat.get_keyboard(qh, ());
+            }
+            if capabilities.contains(wl_seat::Capability::Pointer) {
+                seat.get_pointer(qh, ());
+            }
+        }
     }
+}
 
-    fn get_window_size(&self) -> (u32, u32) {
-        (0,0)
+impl Dispatch<wl_keyboard::WlKeyboard, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_keyboard::WlKeyboard,
+        event: wl_keyboard::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_keyboard::Event::Key { key, serial, .. } = event {
+            state.serial = Some(serial);
+            if key == 1 {
+                // ESC key
+                state.running = false;
+            }
+        }
     }
 }
 
-unsafe impl HasRawWindowHandle for RawWindow {
-    fn raw_window_handle(&self) -> RawWindowHandle {
-        let mut handle = raw_window_handle::WaylandWindowHandle::empty();
-        RawWindowHandle::Wayland(handle)
+impl Dispatch<wl_pointer::WlPointer, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_pointer::WlPointer,
+        event: wl_pointer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_pointer::Event::Button { serial, .. } = event {
+            state.serial = Some(serial);
+        }
     }
 }
 
-
-struct State {
-    running: bool,
-    base_surface: Option<wl_surface::WlSurface>,
-    compositor: Option<WlCompositor>,
-    shell: Option<WlShell>,
-    buffer: Option<wl_buffer::WlBuffer>,
-    wm_base: Option<xdg_wm_base::XdgWmBase>,
-    xdg_surface: Option<(xdg_surface::XdgSurface, xdg_toplevel::XdgToplevel)>,
-    configured: bool,
-
-    title: String
+impl Dispatch<wl_data_device_manager::WlDataDeviceManager, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_data_device_manager::WlDataDeviceManager,
+        _: wl_data_device_manager::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
 }
 
-impl Dispatch<wl_registry::WlRegistry, ()> for State {
+impl Dispatch<wl_data_device::WlDataDevice, ()> for State {
     fn event(
         state: &mut Self,
-        registry: &wl_registry::WlRegistry,
-        event: wl_registry::Event,
+        _: &wl_data_device::WlDataDevice,
+        event: wl_data_device::Event,
         _: &(),
-        _: &Connection,
-        qh: &QueueHandle<Self>,
+        conn: &Connection,
+        _: &QueueHandle<Self>,
     ) {
-        if let wl_registry::Event::Global { name, interface, .. } = event {
-            match &interface[..] {
-                "wl_compositor" => {
-                    let compositor =
-                        registry.bind::<wl_compositor::WlCompositor, _, _>(name, 1, qh, ());
-                    let surface = compositor.create_surface(qh, ());
-                    state.compositor = Some(compositor);
-                    state.base_surface = Some(surface);
-
-                    if state.wm_base.is_some() && state.xdg_surface.is_none() {
-                        state.init_xdg_surface(qh);
-                    }
-                }
-                "wl_shell" => {
-                    let shell = registry.bind::<wl_shell::WlShell, _, _>(name, 1, qh, ());
-                    state.shell = Some(shell);
+        // Offers are announced with data_offer right before, together with their MIME types
+        match event {
+            wl_data_device::Event::Selection { id } => {
+                let offer = id.map(SelectionOffer::Data);
+                if let Some(previous) = std::mem::replace(&mut state.selection_offer, offer) {
+                    previous.destroy();
                 }
-                "wl_shm" => {
-                    let shm = registry.bind::<wl_shm::WlShm, _, _>(name, 1, qh, ());
-
-                    let (init_w, init_h) = (320, 240);
-
-                    let mut file = tempfile::tempfile().unwrap();
-                    draw(&mut file, (init_w, init_h));
-                    let pool =
-                        shm.create_pool(file.as_raw_fd(), (init_w * init_h * 4) as i32, qh, ());
-                    let buffer = pool.create_buffer(
-                        0,
-                        init_w as i32,
-                        init_h as i32,
-                        (init_w * 4) as i32,
-                        wl_shm::Format::Argb8888,
-                        qh,
-                        (),
-                    );
-                    state.buffer = Some(buffer.clone());
+            }
+            wl_data_device::Event::Enter {
+                serial,
+                surface,
+                x,
+                y,
+                id,
+            } => {
+                let Some(offer) = id else {
+                    return;
+                };
+                // Other windows of the process get the enter on their devices as well
+                let pipe = match state.base_surface.as_ref() == Some(&surface) {
+                    true => receive_offer_paths(&offer, conn),
+                    false => None,
+                };
+                let Some(pipe) = pipe else {
+                    offer.destroy();
+                    return;
+                };
 
-                    if state.configured {
-                        let surface = state.base_surface.as_ref().unwrap();
-                        surface.attach(Some(&buffer), 0, 0);
-                        surface.commit();
+                // The source may be slow or this client itself, so the files are read by
+                // the run loop instead of blocking the dispatch
+                state.drag = Some(DataDrag {
+                    offer,
+                    serial,
+                    transfer: Some(DragTransfer {
+                        pipe,
+                        data: Vec::new(),
+                    }),
+                    paths: Vec::new(),
+                    position: LogicalPosition::new(x, y).to_physical(state.scale_factor),
+                    action: None,
+                });
+            }
+            wl_data_device::Event::Motion { x, y, .. } => {
+                if let Some(drag) = &mut state.drag {
+                    drag.position = LogicalPosition::new(x, y).to_physical(state.scale_factor);
+                    if drag.transfer.is_none() {
+                        state.events.push(WindowEvent::DragMoved {
+                            position: drag.position,
+                        });
                     }
                 }
-                "wl_seat" => {
-                    registry.bind::<wl_seat::WlSeat, _, _>(name, 1, qh, ());
+            }
+            wl_data_device::Event::Leave => {
+                if let Some(drag) = state.drag.take() {
+                    drag.offer.destroy();
+                    if drag.transfer.is_none() {
+                        state.events.push(WindowEvent::DragLeft);
+                    }
                 }
-                "xdg_wm_base" => {
-                    let wm_base = registry.bind::<xdg_wm_base::XdgWmBase, _, _>(name, 1, qh, ());
-                    state.wm_base = Some(wm_base);
-
-                    if state.base_surface.is_some() && state.xdg_surface.is_none() {
-                        state.init_xdg_surface(qh);
+            }
+            wl_data_device::Event::Drop => {
+                // The files were read after enter, so the transfer is finished right away.
+                // Drops before that were never announced and are rejected.
+                if let Some(drag) = state.drag.take() {
+                    match drag.action {
+                        _ if drag.transfer.is_some() => {}
+                        Some(_) => {
+                            if drag.offer.version() >= 3 {
+                                drag.offer.finish();
+                            }
+                            state.events.push(WindowEvent::DragDropped {
+                                paths: drag.paths,
+                                position: drag.position,
+                            });
+                        }
+                        None => state.events.push(WindowEvent::DragLeft),
                     }
+                    drag.offer.destroy();
                 }
-                _ => {}
             }
+            _ => {}
         }
     }
-}
-
-impl Dispatch<wl_shell::WlShell, ()> for State  {
-    fn event(state: &mut Self, proxy: &WlShell, event: Event, data: &(), conn: &Connection, qhandle: &QueueHandle<Self>) {
 
-    }
+    // Offers start without MIME types, wl_data_offer.offer adds them
+    event_created_child!(State, wl_data_device::WlDataDevice, [
+        wl_data_device::EVT_DATA_OFFER_OPCODE => (
+            wl_data_offer::WlDataOffer,
+            DataOfferInfo::default()
+        ),
+    ]);
 }
 
-impl Dispatch<wl_compositor::WlCompositor, ()> for State {
+impl Dispatch<wl_data_offer::WlDataOffer, DataOfferInfo> for State {
     fn event(
         _: &mut Self,
-        _: &wl_compositor::WlCompositor,
-        _: wl_compositor::Event,
-        _: &(),
+        _: &wl_data_offer::WlDataOffer,
+        event: wl_data_offer::Event,
+        info: &DataOfferInfo,
         _: &Connection,
         _: &QueueHandle<Self>,
     ) {
-        // wl_compositor has no event
+        match event {
+            wl_data_offer::Event::Offer { mime_type } => {
+                info.mime_types.lock().unwrap().push(mime_type);
+            }
+            wl_data_offer::Event::SourceActions {
+                source_actions: WEnum::Value(actions),
+            } => {
+                *info.source_actions.lock().unwrap() = [DropAction::Copy, DropAction::Move]
+                    .into_iter()
+                    .filter(|action| actions.contains(dnd_action(*action)))
+                    .collect();
+            }
+            _ => {}
+        }
     }
 }
 
-impl Dispatch<wl_surface::WlSurface, ()> for State {
+/// Asks the source of a drag for its files and returns the pipe they are written to,
+/// None if the source does not offer text/uri-list.
+fn receive_offer_paths(offer: &wl_data_offer::WlDataOffer, conn: &Connection) -> Option<File> {
+    let offered = offer.data::<DataOfferInfo>().is_some_and(|info| {
+        info.mime_types
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|mime_type| mime_type == "text/uri-list")
+    });
+    if !offered {
+        return None;
+    }
+
+    let (read, write) = pipe().ok()?;
+    offer.receive("text/uri-list".to_owned(), write.as_fd());
+    drop(write);
+    conn.flush().ok()?;
+    Some(File::from(read))
+}
+
+impl Dispatch<wl_data_source::WlDataSource, SourceContents> for State {
     fn event(
-        _: &mut Self,
-        _: &wl_surface::WlSurface,
-        _: wl_surface::Event,
-        _: &(),
+        state: &mut Self,
+        source: &wl_data_source::WlDataSource,
+        event: wl_data_source::Event,
+        contents: &SourceContents,
         _: &Connection,
         _: &QueueHandle<Self>,
     ) {
-        // we ignore wl_surface events in this example
+        match event {
+            wl_data_source::Event::Send { mime_type, fd } => {
+                send_contents(contents, &mime_type, fd)
+            }
+            wl_data_source::Event::Cancelled => {
+                // Another client took the selection
+                if matches!(&state.selection_source, Some(SelectionSource::Data(s)) if s == source)
+                {
+                    state.selection_source = None;
+                }
+                source.destroy();
+            }
+            _ => {}
+        }
     }
 }
 
-impl Dispatch<wl_shm::WlShm, ()> for State {
+impl Dispatch<zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1, ()>
+    for State
+{
     fn event(
         _: &mut Self,
-        _: &wl_shm::WlShm,
-        _: wl_shm::Event,
+        _: &zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1,
+        _: zwp_primary_selection_device_manager_v1::Event,
         _: &(),
         _: &Connection,
         _: &QueueHandle<Self>,
     ) {
-        // we ignore wl_shm events in this example
     }
 }
 
-impl Dispatch<wl_shm_pool::WlShmPool, ()> for State {
+impl Dispatch<zwp_primary_selection_device_v1::ZwpPrimarySelectionDeviceV1, ()> for State {
     fn event(
-        _: &mut Self,
-        _: &wl_shm_pool::WlShmPool,
-        _: wl_shm_pool::Event,
+        state: &mut Self,
+        _: &zwp_primary_selection_device_v1::ZwpPrimarySelectionDeviceV1,
+        event: zwp_primary_selection_device_v1::Event,
         _: &(),
         _: &Connection,
         _: &QueueHandle<Self>,
     ) {
-        // we ignore wl_shm_pool events in this example
+        if let zwp_primary_selection_device_v1::Event::Selection { id } = event {
+            let offer = id.map(SelectionOffer::Primary);
+            if let Some(previous) = std::mem::replace(&mut state.primary_offer, offer) {
+                previous.destroy();
+            }
+        }
     }
+
+    event_created_child!(State, zwp_primary_selection_device_v1::ZwpPrimarySelectionDeviceV1, [
+        zwp_primary_selection_device_v1::EVT_DATA_OFFER_OPCODE => (
+            zwp_primary_selection_offer_v1::ZwpPrimarySelectionOfferV1,
+            Mutex::new(Vec::<String>::new())
+        ),
+    ]);
 }
 
-impl Dispatch<wl_buffer::WlBuffer, ()> for State {
+impl Dispatch<zwp_primary_selection_offer_v1::ZwpPrimarySelectionOfferV1, Mutex<Vec<String>>>
+    for State
+{
     fn event(
         _: &mut Self,
-        _: &wl_buffer::WlBuffer,
-        _: wl_buffer::Event,
-        _: &(),
+        _: &zwp_primary_selection_offer_v1::ZwpPrimarySelectionOfferV1,
+        event: zwp_primary_selection_offer_v1::Event,
+        mime_types: &Mutex<Vec<String>>,
         _: &Connection,
         _: &QueueHandle<Self>,
     ) {
-        // we ignore wl_buffer events in this example
+        if let zwp_primary_selection_offer_v1::Event::Offer { mime_type } = event {
+            mime_types.lock().unwrap().push(mime_type);
+        }
     }
 }
 
-fn draw(tmp: &mut File, (buf_x, buf_y): (u32, u32)) {
-    use std::{cmp::min, io::Write};
-    let mut buf = std::io::BufWriter::new(tmp);
-    for y in 0..buf_y {
-        for x in 0..buf_x {
-            let a = 0xFF;
-            let r = min(((buf_x - x) * 0xFF) / buf_x, ((buf_y - y) * 0xFF) / buf_y);
-            let g = min((x * 0xFF) / buf_x, ((buf_y - y) * 0xFF) / buf_y);
-            let b = min(((buf_x - x) * 0xFF) / buf_x, (y * 0xFF) / buf_y);
-
-            let color = (a << 24) + (r << 16) + (g << 8) + b;
-            buf.write_all(&color.to_ne_bytes()).unwrap();
+impl Dispatch<zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1, SourceContents>
+    for State
+{
+    fn event(
+        state: &mut Self,
+        source: &zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1,
+        event: zwp_primary_selection_source_v1::Event,
+        contents: &SourceContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwp_primary_selection_source_v1::Event::Send { mime_type, fd } => {
+                send_contents(contents, &mime_type, fd)
+            }
+            zwp_primary_selection_source_v1::Event::Cancelled => {
+                if matches!(&state.primary_source, Some(SelectionSource::Primary(s)) if s == source)
+                {
+                    state.primary_source = None;
+                }
+                source.destroy();
+            }
+            _ => {}
         }
     }
-    buf.flush().unwrap();
 }
 
-impl State {
-    fn init_xdg_surface(&mut self, qh: &QueueHandle<State>) {
-        let wm_base = self.wm_base.as_ref().unwrap();
-        let base_surface = self.base_surface.as_ref().unwrap();
-
-        let xdg_surface = wm_base.get_xdg_surface(base_surface, qh, ());
-        let toplevel = xdg_surface.get_toplevel(qh, ());
-        toplevel.set_title((&*self.title).parse().unwrap());
-
-        base_surface.commit();
-
-        self.xdg_surface = Some((xdg_surface, toplevel));
+/// Writes the contents of a source for the given MIME type to the pipe of the receiving client,
+/// on another thread, so that large contents do not block the event loop.
+fn send_contents(contents: &SourceContents, mime_type: &str, fd: OwnedFd) {
+    if let Some((_, data)) = contents.iter().find(|(m, _)| m == mime_type) {
+        let data = data.clone();
+        std::thread::spawn(move || {
+            let _ = File::from(fd).write_all(&data);
+        });
     }
 }
 
-impl Dispatch<xdg_wm_base::XdgWmBase, ()> for State {
+impl Dispatch<xdg_positioner::XdgPositioner, ()> for State {
     fn event(
         _: &mut Self,
-        wm_base: &xdg_wm_base::XdgWmBase,
-        event: xdg_wm_base::Event,
+        _: &xdg_positioner::XdgPositioner,
+        _: xdg_positioner::Event,
         _: &(),
         _: &Connection,
         _: &QueueHandle<Self>,
     ) {
-        if let xdg_wm_base::Event::Ping { serial } = event {
-            wm_base.pong(serial);
-        }
+        // xdg_positioner has no event
     }
 }
 
-impl Dispatch<xdg_surface::XdgSurface, ()> for State {
+impl Dispatch<xdg_popup::XdgPopup, ()> for State {
     fn event(
         state: &mut Self,
-        xdg_surface: &xdg_surface::XdgSurface,
-        event: xdg_surface::Event,
+        _: &xdg_popup::XdgPopup,
+        event: xdg_popup::Event,
         _: &(),
         _: &Connection,
         _: &QueueHandle<Self>,
     ) {
-        if let xdg_surface::Event::Configure { serial, .. } = event {
-            xdg_surface.ack_configure(serial);
-            state.configured = true;
-            let surface = state.base_surface.as_ref().unwrap();
-            if let Some(ref buffer) = state.buffer {
-                surface.attach(Some(buffer), 0, 0);
-                surface.commit();
+        match event {
+            xdg_popup::Event::Configure { width, height, .. } => {
+                state.size = LogicalSize::new(width as f64, height as f64);
+                state.requested_size = None;
+                state.update_viewport();
+            }
+            xdg_popup::Event::PopupDone => {
+                if let Some((xdg_surface, popup)) = state.popup.take() {
+                    popup.destroy();
+                    xdg_surface.destroy();
+                }
+                state.events.push(WindowEvent::PopupDone);
+                state.running = false;
             }
+            _ => {}
         }
     }
 }
 
-impl Dispatch<xdg_toplevel::XdgToplevel, ()> for State {
-    fn event(
-        state: &mut Self,
-        _: &xdg_toplevel::XdgToplevel,
-        event: xdg_toplevel::Event,
-        _: &(),
-        _: &Connection,
-        _: &QueueHandle<Self>,
-    ) {
-        if let xdg_toplevel::Event::Close {} = event {
-            state.running = false;
-        }
+/// Returns the anchor and gravity of an xdg_positioner, the popup is anchored in the direction it extends to.
+fn positioner_gravity(gravity: Gravity) -> (xdg_positioner::Anchor, xdg_positioner::Gravity) {
+    match gravity {
+        Gravity::None => (xdg_positioner::Anchor::None, xdg_positioner::Gravity::None),
+        Gravity::Top => (xdg_positioner::Anchor::Top, xdg_positioner::Gravity::Top),
+        Gravity::Bottom => (
+            xdg_positioner::Anchor::Bottom,
+            xdg_positioner::Gravity::Bottom,
+        ),
+        Gravity::Left => (xdg_positioner::Anchor::Left, xdg_positioner::Gravity::Left),
+        Gravity::Right => (
+            xdg_positioner::Anchor::Right,
+            xdg_positioner::Gravity::Right,
+        ),
+        Gravity::TopLeft => (
+            xdg_positioner::Anchor::TopLeft,
+            xdg_positioner::Gravity::TopLeft,
+        ),
+        Gravity::BottomLeft => (
+            xdg_positioner::Anchor::BottomLeft,
+            xdg_positioner::Gravity::BottomLeft,
+        ),
+        Gravity::TopRight => (
+            xdg_positioner::Anchor::TopRight,
+            xdg_positioner::Gravity::TopRight,
+        ),
+        Gravity::BottomRight => (
+            xdg_positioner::Anchor::BottomRight,
+            xdg_positioner::Gravity::BottomRight,
+        ),
     }
 }
 
-impl Dispatch<wl_seat::WlSeat, ()> for State {
+impl Dispatch<wl_subcompositor::WlSubcompositor, ()> for State {
     fn event(
         _: &mut Self,
-        seat: &wl_seat::WlSeat,
-        event: wl_seat::Event,
+        _: &wl_subcompositor::WlSubcompositor,
+        _: wl_subcompositor::Event,
         _: &(),
         _: &Connection,
-        qh: &QueueHandle<Self>,
+        _: &QueueHandle<Self>,
     ) {
-        if let wl_seat::Event::Capabilities { capabilities: WEnum::Value(capabilities) } = event {
-            if capabilities.contains(wl_seat::Capability::Keyboard) {
-                seat.get_keyboard(qh, ());
-            }
-        }
+        // wl_subcompositor has no event
     }
 }
 
-impl Dispatch<wl_keyboard::WlKeyboard, ()> for State {
+impl Dispatch<wl_subsurface::WlSubsurface, ()> for State {
     fn event(
-        state: &mut Self,
-        _: &wl_keyboard::WlKeyboard,
-        event: wl_keyboard::Event,
+        _: &mut Self,
+        _: &wl_subsurface::WlSubsurface,
+        _: wl_subsurface::Event,
         _: &(),
         _: &Connection,
         _: &QueueHandle<Self>,
     ) {
-        if let wl_keyboard::Event::Key { key, .. } = event {
-            if key == 1 {
-                // ESC key
-                state.running = false;
-            }
-        }
+        // wl_subsurface has no event
+    }
+}
+
+/// Wraps the wl_surface of a raw window handle.
+/// Objects can only be shared within a connection, so the surface must belong to a GWL window,
+/// which are all created on the shared ```CONNECTION```. Returns None for handles of other
+/// platforms and for other surfaces.
+fn surface_from_handle(
+    conn: &Connection,
+    handle: RawWindowHandle,
+) -> Option<wl_surface::WlSurface> {
+    let RawWindowHandle::Wayland(handle) = handle else {
+        return None;
+    };
+    if handle.surface.is_null() {
+        return None;
+    }
+
+    let id =
+        unsafe { ObjectId::from_ptr(wl_surface::WlSurface::interface(), handle.surface as *mut _) }
+            .ok()?;
+    let known = SURFACE_ROLES
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|role| role.surface == id);
+    if !known {
+        return None;
+    }
+    wl_surface::WlSurface::from_id(conn, id).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slot_allocation() {
+        // Empty pool, gap at the start, gap between slots, after the last slot
+        assert_eq!(first_gap(Vec::new(), 100), 0);
+        assert_eq!(first_gap(vec![(100, 200)], 100), 0);
+        assert_eq!(first_gap(vec![(100, 200)], 101), 200);
+        assert_eq!(first_gap(vec![(200, 300), (0, 100)], 100), 100);
+        assert_eq!(first_gap(vec![(200, 300), (0, 100)], 101), 300);
+        assert_eq!(first_gap(vec![(0, 100), (100, 200)], 1), 200);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn pool_growth() {
+        assert_eq!(grown_pool_len(400, 400), 400);
+        assert_eq!(grown_pool_len(400, 100), 400);
+        // Doubled, unless the slot needs more
+        assert_eq!(grown_pool_len(400, 500), 800);
+        assert_eq!(grown_pool_len(400, 1000), 1000);
+    }
+
+    #[test]
+    fn slot_damage() {
+        let size = PhysicalSize::new(10, 10);
+        let mut damage = vec![Rect::new(0, 0, 1, 1)];
+        add_slot_damage(&mut damage, &[Rect::new(2, 2, 3, 3)], size);
+        assert_eq!(damage, [Rect::new(0, 0, 1, 1), Rect::new(2, 2, 3, 3)]);
+
+        // Too many rectangles are replaced by the whole slot
+        let many = vec![Rect::new(1, 1, 1, 1); MAX_SLOT_DAMAGE];
+        add_slot_damage(&mut damage, &many, size);
+        assert_eq!(damage, [Rect::new(0, 0, 10, 10)]);
+    }
+
+    #[test]
+    fn damaged_row_ranges() {
+        let damage = [Rect::new(1, 2, 3, 2), Rect::new(0, 0, 10, 1)];
+        let rows: Vec<_> = damaged_rows(&damage, 10).collect();
+        assert_eq!(rows, [21..24, 31..34, 0..10]);
+    }
+}
@@ -1,16 +1,37 @@
-use crate::window::{ControlFlow, DefWindowBuildAction, IWindow, WindowBuildAction, WindowEvent};
+#[cfg(feature = "vulkan")]
+use crate::window::{
+    instance_proc_address, VkInstance, VkResult, VkSurfaceKHR, VulkanError, VK_SUCCESS,
+};
+use crate::window::{
+    place_popup, ClipboardError, ConstraintAdjustment, ControlFlow, DefWindowBuildAction,
+    DropAction, Fullscreen, Gravity, IWindow, Icon, ModeInfo, MonitorHandle, PhysicalPosition,
+    PhysicalSize, PixelFormat, PopupError, Position, Rect, Selection, Size, WindowBuildAction,
+    WindowEvent, WindowLevel, WindowType,
+};
+#[cfg(feature = "gl")]
+use crate::window::{GlAttributes, GlError, IGlContext};
 use once_cell::sync::Lazy;
-use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
-use std::cell::RefCell;
+use raw_window_handle::{
+    HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
+};
+use std::cell::{RefCell, RefMut};
 use std::ffi::{c_int, c_void, OsStr};
+use std::marker::PhantomData;
 use std::mem::size_of;
 use std::os::windows::ffi::OsStrExt;
 use std::ptr::null_mut;
-use winapi::shared::minwindef::{DWORD, HMODULE, LPARAM, LPCVOID, LRESULT, UINT, WPARAM};
-use winapi::shared::windef::HWND;
+use winapi::shared::minwindef::{
+    BOOL, DWORD, FALSE, HMODULE, LPARAM, LPCVOID, LRESULT, TRUE, UINT, WORD, WPARAM,
+};
+use winapi::shared::ntdef::LONG;
+use winapi::shared::windef::{HDC, HICON, HMONITOR, HWND, LPRECT, POINT, RECT};
 use winapi::um::dwmapi::{DwmExtendFrameIntoClientArea, DwmSetWindowAttribute};
 use winapi::um::libloaderapi::GetModuleHandleW;
 use winapi::um::uxtheme::MARGINS;
+use winapi::um::wingdi::{
+    CreateDCW, DeleteDC, GetDeviceCaps, StretchDIBits, BITMAPINFO, BITMAPINFOHEADER, BI_RGB,
+    DEVMODEW, DIB_RGB_COLORS, HORZSIZE, LOGPIXELSX, SRCCOPY, VERTSIZE,
+};
 use winapi::um::winuser::*;
 use winapi::ENUM;
 
@@ -30,9 +51,10 @@ pub struct WindowHandle {
     pub hinstance: HMODULE,
 }
 
-pub struct WindowInstance {
+pub struct WindowInstance<'a> {
     pub hwnd: HWND,
     pub hinstance: HMODULE,
+    _window: PhantomData<&'a RawWindow>,
 }
 
 pub struct RawWindow {
@@ -40,16 +62,26 @@ pub struct RawWindow {
     hinstance: HMODULE,
 
     border_width: RefCell<u32>,
+
+    fullscreen: RefCell<Option<Fullscreen>>,
+    /// Style and rectangle to restore after leaving fullscreen.
+    saved_placement: RefCell<Option<(LONG, RECT)>>,
+    icon: RefCell<HICON>,
+    /// Whether the owner window is disabled by this window.
+    modal: RefCell<bool>,
+    /// Popups capture the mouse and are dismissed by clicks outside of them.
+    popup: bool,
+    framebuffer: RefCell<Vec<u32>>,
+    framebuffer_size: RefCell<PhysicalSize>,
 }
 
 impl IWindow<'_> for RawWindow {
     fn new(
         title: String,
-        width: u32,
-        height: u32,
-        x: i32,
-        y: i32,
+        size: Size,
+        position: Position,
         border_width: u32,
+        parent: Option<RawWindowHandle>,
         mut build_action: Box<&mut dyn WindowBuildAction>,
     ) -> Self {
         build_action.pre_init();
@@ -84,32 +116,64 @@ impl IWindow<'_> for RawWindow {
 
                 let mut msg = 0;
 
+                // The monitor is not known before the window exists, so the system DPI is used
+                let hdc = GetDC(null_mut());
+                let scale_factor = GetDeviceCaps(hdc, LOGPIXELSX) as f64 / 96.0;
+                ReleaseDC(null_mut(), hdc);
+                let size = size.to_physical(scale_factor);
+                let position = position.to_physical(scale_factor);
+
+                let (style, parent) = match parent {
+                    Some(RawWindowHandle::Win32(parent)) => {
+                        (WS_CHILD | WS_CLIPSIBLINGS, parent.hwnd as HWND)
+                    }
+                    _ => (WS_OVERLAPPEDWINDOW, null_mut()),
+                };
+
                 let hwnd = CreateWindowExW(
                     0,
                     window_class.as_ptr(),
                     title_wide.as_ptr(),
-                    WS_OVERLAPPEDWINDOW,
-                    x,
-                    y,
-                    width as c_int,
-                    height as c_int,
-                    std::ptr::null_mut(),
+                    style,
+                    position.x,
+                    position.y,
+                    size.width as c_int,
+                    size.height as c_int,
+                    parent,
                     std::ptr::null_mut(),
                     hinstance,
                     &mut msg as *mut i32 as _,
                 );
-                let handle = WindowInstance { hwnd, hinstance };
+                let handle = WindowInstance {
+                    hwnd,
+                    hinstance,
+                    _window: PhantomData,
+                };
                 build_action.window_created(&handle);
                 Self {
                     hwnd,
                     hinstance,
                     border_width: RefCell::new(border_width),
+                    fullscreen: RefCell::new(None),
+                    saved_placement: RefCell::new(None),
+                    icon: RefCell::new(null_mut()),
+                    modal: RefCell::new(false),
+                    popup: false,
+                    framebuffer: RefCell::new(Vec::new()),
+                    framebuffer_size: RefCell::new(PhysicalSize::default()),
                 }
             },
             Some(handle) => Self {
                 hwnd: handle.hwnd,
                 hinstance: handle.hinstance,
                 border_width: RefCell::new(border_width),
+                fullscreen: RefCell::new(None),
+                saved_placement: RefCell::new(None),
+                icon: RefCell::new(null_mut()),
+                modal: RefCell::new(false),
+                popup: false,
+                framebuffer: RefCell::new(Vec::new()),
+                framebuffer_size: RefCell::new(PhysicalSize::default()),
             },
         }
     }
@@ -143,17 +207,53 @@ impl IWindow<'_> for RawWindow {
                                 DwmExtendFrameIntoClientArea(self.hwnd, &margins);
                             }
                             WM_PAINT => {
-                                callback(WindowEvent::Expose, &mut control_flow);
+                                let mut rect: RECT = std::mem::zeroed();
+                                GetUpdateRect(self.hwnd, &mut rect, FALSE);
+                                let rect = Rect::new(
+                                    rect.left,
+                                    rect.top,
+                                    (rect.right - rect.left) as u32,
+                                    (rect.bottom - rect.top) as u32,
+                                );
+                                callback(WindowEvent::Expose(rect), &mut control_flow);
                             }
 
                             WM_DESTROY => {
+                                if *self.modal.borrow() {
+                                    EnableWindow(GetWindow(self.hwnd, GW_OWNER), TRUE);
+                                }
                                 callback(WindowEvent::CloseRequested, &mut control_flow);
                             }
 
-                            _ => {},
+                            _ => {}
                         }
 
                         match message.message {
+                            WM_LBUTTONDOWN | WM_RBUTTONDOWN | WM_MBUTTONDOWN
+                                if self.popup && message.hwnd == self.hwnd =>
+                            {
+                                // The popup captures the mouse, so clicks outside of it are reported as well
+                                let x = (message.lParam & 0xFFFF) as i16 as c_int;
+                                let y = ((message.lParam >> 16) & 0xFFFF) as i16 as c_int;
+                                let mut rect: RECT = std::mem::zeroed();
+                                GetClientRect(self.hwnd, &mut rect);
+                                if x < rect.left
+                                    || y < rect.top
+                                    || x >= rect.right
+                                    || y >= rect.bottom
+                                {
+                                    self.dismiss_popup();
+                                    callback(WindowEvent::PopupDone, &mut control_flow);
+                                    return;
+                                }
+                            }
+
+                            WM_KEYDOWN if self.popup && message.wParam == VK_ESCAPE as WPARAM => {
+                                self.dismiss_popup();
+                                callback(WindowEvent::PopupDone, &mut control_flow);
+                                return;
+                            }
+
                             WM_KEYDOWN => {
                                 callback(
                                     WindowEvent::KeyDown(message.wParam as u32),
@@ -178,10 +278,11 @@ impl IWindow<'_> for RawWindow {
         }
     }
 
-    fn get_instance(&self) -> WindowInstance {
+    fn get_instance(&self) -> WindowInstance<'_> {
         WindowInstance {
             hwnd: self.hwnd,
             hinstance: self.hinstance,
+            _window: PhantomData,
         }
     }
 
@@ -196,10 +297,82 @@ impl IWindow<'_> for RawWindow {
         }
     }
 
+    fn title(&self) -> String {
+        unsafe {
+            let length = GetWindowTextLengthW(self.hwnd);
+            let mut buffer = vec![0u16; length as usize + 1];
+            let copied = GetWindowTextW(self.hwnd, buffer.as_mut_ptr(), buffer.len() as c_int);
+            String::from_utf16_lossy(&buffer[..copied as usize])
+        }
+    }
+
     fn set_window_border_width(&self, border_width: u32) {
         *self.border_width.borrow_mut() = border_width;
     }
 
+    fn set_app_id(&self, _app_id: &str, _instance_name: &str) {
+        // Windows groups taskbar buttons by executable, WM_CLASS and app ids have no equivalent
+    }
+
+    fn set_window_type(&self, window_type: WindowType) {
+        let popup = matches!(
+            window_type,
+            WindowType::Splash
+                | WindowType::Tooltip
+                | WindowType::Menu
+                | WindowType::Notification
+                | WindowType::Dock
+        );
+        // Tool windows get a small caption and no taskbar button
+        let tool = !matches!(
+            window_type,
+            WindowType::Normal | WindowType::Dialog | WindowType::Splash
+        );
+
+        unsafe {
+            let style = match popup {
+                true => WS_POPUP | WS_BORDER,
+                false => WS_OVERLAPPEDWINDOW,
+            };
+            SetWindowLongW(self.hwnd, GWL_STYLE, style as LONG);
+
+            let mut ex_style = GetWindowLongW(self.hwnd, GWL_EXSTYLE) as DWORD;
+            match tool {
+                true => ex_style |= WS_EX_TOOLWINDOW,
+                false => ex_style &= !WS_EX_TOOLWINDOW,
+            }
+            SetWindowLongW(self.hwnd, GWL_EXSTYLE, ex_style as LONG);
+
+            SetWindowPos(
+                self.hwnd,
+                null_mut(),
+                0,
+                0,
+                0,
+                0,
+                SWP_FRAMECHANGED | SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE | SWP_NOZORDER,
+            );
+        }
+    }
+
+    fn set_transient_for(&self, parent: Option<&RawWindow>) {
+        // The owner of a top-level window is stored in the GWLP_HWNDPARENT slot
+        let owner = parent.map_or(null_mut(), |parent| parent.hwnd);
+        unsafe {
+            SetWindowLongPtrW(self.hwnd, GWLP_HWNDPARENT, owner as _);
+        }
+    }
+
+    fn set_modal(&self, b: bool) {
+        unsafe {
+            let owner = GetWindow(self.hwnd, GW_OWNER);
+            if !owner.is_null() {
+                EnableWindow(owner, !b as BOOL);
+            }
+        }
+        *self.modal.borrow_mut() = b;
+    }
+
     fn set_undecorated(&self, b: bool) {
         match b {
             true => unsafe {
@@ -240,13 +413,45 @@ impl IWindow<'_> for RawWindow {
         }
     }
 
-    fn set_minimized(&self, b: bool) {
-        match b {
-            true => {
-                unsafe {
-                    ShowWindow(self.hwnd,SW_MINIMIZE);
+    fn set_window_icon(&self, icon: Option<Icon>) {
+        unsafe {
+            let hicon = match icon {
+                None => null_mut(),
+                Some(icon) => {
+                    // CreateIcon takes BGRA pixels and a monochrome mask that is unused for 32-bit icons
+                    let bgra = icon
+                        .rgba
+                        .chunks_exact(4)
+                        .flat_map(|p| [p[2], p[1], p[0], p[3]])
+                        .collect::<Vec<u8>>();
+                    let mask = vec![0u8; ((icon.width + 15) / 16 * 2 * icon.height) as usize];
+                    CreateIcon(
+                        self.hinstance,
+                        icon.width as c_int,
+                        icon.height as c_int,
+                        1,
+                        32,
+                        mask.as_ptr(),
+                        bgra.as_ptr(),
+                    )
                 }
+            };
+
+            SendMessageW(self.hwnd, WM_SETICON, ICON_SMALL as WPARAM, hicon as LPARAM);
+            SendMessageW(self.hwnd, WM_SETICON, ICON_BIG as WPARAM, hicon as LPARAM);
+
+            let old = self.icon.replace(hicon);
+            if !old.is_null() {
+                DestroyIcon(old);
             }
+        }
+    }
+
+    fn set_minimized(&self, b: bool) {
+        match b {
+            true => unsafe {
+                ShowWindow(self.hwnd, SW_MINIMIZE);
+            },
             false => {
                 self.show();
             }
@@ -255,17 +460,41 @@ impl IWindow<'_> for RawWindow {
 
     fn set_maximized(&self, b: bool) {
         match b {
-            true => {
-                unsafe {
-                    ShowWindow(self.hwnd,SW_MAXIMIZE);
-                }
-            }
+            true => unsafe {
+                ShowWindow(self.hwnd, SW_MAXIMIZE);
+            },
             false => {
                 self.show();
             }
         }
     }
 
+    fn set_window_level(&self, level: WindowLevel) {
+        let insert_after = match level {
+            WindowLevel::Normal => HWND_NOTOPMOST,
+            WindowLevel::AlwaysOnTop => HWND_TOPMOST,
+            WindowLevel::AlwaysOnBottom => HWND_BOTTOM,
+        };
+
+        unsafe {
+            SetWindowPos(
+                self.hwnd,
+                insert_after,
+                0,
+                0,
+                0,
+                0,
+                SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE,
+            );
+        }
+    }
+
+    // Virtual desktops are only reachable through the IVirtualDesktopManager COM interface,
+    // which cannot pin a window or move it by index.
+    fn set_visible_on_all_workspaces(&self, _b: bool) {}
+
+    fn set_workspace(&self, _workspace: u32) {}
+
     fn show(&self) {
         unsafe {
             ShowWindow(self.hwnd, SW_SHOW);
@@ -278,24 +507,440 @@ impl IWindow<'_> for RawWindow {
         }
     }
 
-    fn get_window_pos(&self) -> (u32, u32) {
+    fn get_window_pos(&self) -> PhysicalPosition {
         unsafe {
             let mut rect = std::mem::zeroed();
             GetWindowRect(self.hwnd, &mut rect);
-            (rect.left.try_into().unwrap(), rect.top.try_into().unwrap())
+            PhysicalPosition::new(rect.left, rect.top)
+        }
+    }
+
+    fn set_window_pos(&self, position: Position) {
+        let position = position.to_physical(self.scale_factor());
+        unsafe {
+            SetWindowPos(
+                self.hwnd,
+                null_mut(),
+                position.x,
+                position.y,
+                0,
+                0,
+                SWP_NOSIZE | SWP_NOZORDER | SWP_NOACTIVATE,
+            );
         }
     }
 
-    fn get_window_size(&self) -> (u32, u32) {
+    fn raise(&self) {
+        unsafe {
+            SetWindowPos(
+                self.hwnd,
+                HWND_TOP,
+                0,
+                0,
+                0,
+                0,
+                SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE,
+            );
+        }
+    }
+
+    fn lower(&self) {
+        unsafe {
+            SetWindowPos(
+                self.hwnd,
+                HWND_BOTTOM,
+                0,
+                0,
+                0,
+                0,
+                SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE,
+            );
+        }
+    }
+
+    // Child windows are always drawn independently of their parent
+    fn set_synchronized(&self, _b: bool) {}
+
+    fn get_window_size(&self) -> PhysicalSize {
         unsafe {
             let mut rect = std::mem::zeroed();
             GetWindowRect(self.hwnd, &mut rect);
-            (
-                (rect.right-rect.left) as u32,
-                (rect.bottom-rect.top) as u32,
+            PhysicalSize::new(
+                (rect.right - rect.left) as u32,
+                (rect.bottom - rect.top) as u32,
+            )
+        }
+    }
+
+    fn scale_factor(&self) -> f64 {
+        self.current_monitor()
+            .map(|monitor| monitor.scale_factor)
+            .unwrap_or(1.0)
+    }
+
+    fn set_fullscreen(&self, fullscreen: Option<Fullscreen>) {
+        unsafe {
+            if let Some(Fullscreen::Exclusive(_)) = *self.fullscreen.borrow() {
+                ChangeDisplaySettingsExW(std::ptr::null(), null_mut(), null_mut(), 0, null_mut());
+            }
+
+            match &fullscreen {
+                None => {
+                    if let Some((style, rect)) = self.saved_placement.borrow_mut().take() {
+                        SetWindowLongW(self.hwnd, GWL_STYLE, style);
+                        SetWindowPos(
+                            self.hwnd,
+                            null_mut(),
+                            rect.left,
+                            rect.top,
+                            rect.right - rect.left,
+                            rect.bottom - rect.top,
+                            SWP_FRAMECHANGED | SWP_NOACTIVATE | SWP_NOZORDER,
+                        );
+                    }
+                }
+                Some(fullscreen) => {
+                    let hmonitor = match fullscreen {
+                        Fullscreen::Borderless(Some(monitor)) => monitor.id as HMONITOR,
+                        Fullscreen::Borderless(None) => {
+                            MonitorFromWindow(self.hwnd, MONITOR_DEFAULTTONEAREST)
+                        }
+                        Fullscreen::Exclusive(mode) => {
+                            let hmonitor = mode.monitor.id as HMONITOR;
+                            let info = monitor_info(hmonitor);
+                            let mut devmode: DEVMODEW = std::mem::zeroed();
+                            devmode.dmSize = size_of::<DEVMODEW>() as WORD;
+                            if EnumDisplaySettingsW(
+                                info.szDevice.as_ptr(),
+                                mode.info.id as DWORD,
+                                &mut devmode,
+                            ) != 0
+                            {
+                                ChangeDisplaySettingsExW(
+                                    info.szDevice.as_ptr(),
+                                    &mut devmode,
+                                    null_mut(),
+                                    CDS_FULLSCREEN,
+                                    null_mut(),
+                                );
+                            }
+                            hmonitor
+                        }
+                    };
+
+                    if self.saved_placement.borrow().is_none() {
+                        let mut rect = std::mem::zeroed();
+                        GetWindowRect(self.hwnd, &mut rect);
+                        *self.saved_placement.borrow_mut() =
+                            Some((GetWindowLongW(self.hwnd, GWL_STYLE), rect));
+                    }
+
+                    // Queried after the mode switch so that the rectangle matches the new mode
+                    let rect = monitor_info(hmonitor).rcMonitor;
+                    SetWindowLongW(self.hwnd, GWL_STYLE, (WS_POPUP | WS_VISIBLE) as LONG);
+                    SetWindowPos(
+                        self.hwnd,
+                        HWND_TOP,
+                        rect.left,
+                        rect.top,
+                        rect.right - rect.left,
+                        rect.bottom - rect.top,
+                        SWP_FRAMECHANGED | SWP_NOACTIVATE,
+                    );
+                }
+            }
+        }
+
+        *self.fullscreen.borrow_mut() = fullscreen;
+    }
+
+    fn get_fullscreen(&self) -> Option<Fullscreen> {
+        self.fullscreen.borrow().clone()
+    }
+
+    fn available_monitors(&self) -> Vec<MonitorHandle> {
+        let mut hmonitors: Vec<HMONITOR> = Vec::new();
+
+        unsafe {
+            EnumDisplayMonitors(
+                null_mut(),
+                std::ptr::null(),
+                Some(enum_monitors),
+                &mut hmonitors as *mut Vec<HMONITOR> as LPARAM,
+            );
+        }
+
+        hmonitors
+            .into_iter()
+            .map(|hmonitor| unsafe { monitor_handle(hmonitor) })
+            .collect()
+    }
+
+    fn primary_monitor(&self) -> Option<MonitorHandle> {
+        self.available_monitors()
+            .into_iter()
+            .find(|monitor| monitor.primary)
+    }
+
+    fn current_monitor(&self) -> Option<MonitorHandle> {
+        let hmonitor = unsafe { MonitorFromWindow(self.hwnd, MONITOR_DEFAULTTONEAREST) };
+        self.available_monitors()
+            .into_iter()
+            .find(|monitor| monitor.id == hmonitor as u64)
+    }
+
+    fn create_popup(
+        &self,
+        size: Size,
+        anchor_rect: Rect,
+        gravity: Gravity,
+        constraint_adjustment: ConstraintAdjustment,
+    ) -> Result<Self, PopupError> {
+        unsafe {
+            // Popups are placed in screen coordinates, inside the work area of the monitor
+            let mut origin = POINT { x: 0, y: 0 };
+            ClientToScreen(self.hwnd, &mut origin);
+            let anchor_rect = Rect::new(
+                anchor_rect.x + origin.x,
+                anchor_rect.y + origin.y,
+                anchor_rect.width,
+                anchor_rect.height,
+            );
+            let work = monitor_info(MonitorFromWindow(self.hwnd, MONITOR_DEFAULTTONEAREST)).rcWork;
+            let bounds = Rect::new(
+                work.left,
+                work.top,
+                (work.right - work.left) as u32,
+                (work.bottom - work.top) as u32,
+            );
+            let rect = place_popup(
+                anchor_rect,
+                gravity,
+                size.to_physical(self.scale_factor()),
+                constraint_adjustment,
+                bounds,
+            );
+
+            let window_class = OsStr::new("window")
+                .encode_wide()
+                .chain(Some(0).into_iter())
+                .collect::<Vec<_>>();
+
+            // The parent becomes the owner, so the popup stays above it
+            let hwnd = CreateWindowExW(
+                WS_EX_TOOLWINDOW | WS_EX_TOPMOST,
+                window_class.as_ptr(),
+                null_mut(),
+                WS_POPUP | WS_BORDER,
+                rect.x,
+                rect.y,
+                rect.width as c_int,
+                rect.height as c_int,
+                self.hwnd,
+                null_mut(),
+                self.hinstance,
+                null_mut(),
+            );
+            ShowWindow(hwnd, SW_SHOW);
+            SetCapture(hwnd);
+
+            Ok(Self {
+                hwnd,
+                hinstance: self.hinstance,
+                border_width: RefCell::new(0),
+                fullscreen: RefCell::new(None),
+                saved_placement: RefCell::new(None),
+                icon: RefCell::new(null_mut()),
+                modal: RefCell::new(false),
+                popup: true,
+                framebuffer: RefCell::new(Vec::new()),
+                framebuffer_size: RefCell::new(PhysicalSize::default()),
+            })
+        }
+    }
+
+    fn framebuffer(&self) -> RefMut<'_, [u32]> {
+        let size = self.client_size();
+        let mut framebuffer = self.framebuffer.borrow_mut();
+        let mut framebuffer_size = self.framebuffer_size.borrow_mut();
+
+        if *framebuffer_size != size {
+            *framebuffer = vec![0; (size.width * size.height) as usize];
+            *framebuffer_size = size;
+        }
+
+        RefMut::map(framebuffer, |framebuffer| framebuffer.as_mut_slice())
+    }
+
+    fn present(&self, damage: &[Rect]) {
+        let framebuffer = self.framebuffer.borrow();
+        let size = *self.framebuffer_size.borrow();
+        if framebuffer.is_empty() {
+            return;
+        }
+
+        let bounds = Rect::new(0, 0, size.width, size.height);
+        let damage = match damage.is_empty() {
+            true => vec![bounds],
+            false => damage
+                .iter()
+                .filter_map(|rect| rect.intersection(&bounds))
+                .collect(),
+        };
+
+        unsafe {
+            let mut info: BITMAPINFO = std::mem::zeroed();
+            info.bmiHeader.biSize = size_of::<BITMAPINFOHEADER>() as DWORD;
+            info.bmiHeader.biWidth = size.width as LONG;
+            // Negative height for rows stored from top to bottom
+            info.bmiHeader.biHeight = -(size.height as LONG);
+            info.bmiHeader.biPlanes = 1;
+            info.bmiHeader.biBitCount = 32;
+            info.bmiHeader.biCompression = BI_RGB;
+
+            let hdc = GetDC(self.hwnd);
+            for rect in damage {
+                // The source rectangle of top-down DIBs is measured from the bottom row
+                StretchDIBits(
+                    hdc,
+                    rect.x,
+                    rect.y,
+                    rect.width as c_int,
+                    rect.height as c_int,
+                    rect.x,
+                    size.height as c_int - rect.y - rect.height as c_int,
+                    rect.width as c_int,
+                    rect.height as c_int,
+                    framebuffer.as_ptr() as *const _,
+                    &info,
+                    DIB_RGB_COLORS,
+                    SRCCOPY,
+                );
+            }
+            ReleaseDC(self.hwnd, hdc);
+        }
+    }
+
+    fn framebuffer_formats(&self) -> Vec<PixelFormat> {
+        vec![PixelFormat::Argb8888, PixelFormat::Xrgb8888]
+    }
+
+    fn set_framebuffer_format(&self, _format: PixelFormat) {
+        // StretchDIBits ignores the alpha channel, so both supported formats share the same layout
+    }
+}
+
+impl RawWindow {
+    /// Size of the client area, which the framebuffer covers.
+    fn client_size(&self) -> PhysicalSize {
+        unsafe {
+            let mut rect = std::mem::zeroed();
+            GetClientRect(self.hwnd, &mut rect);
+            PhysicalSize::new(
+                (rect.right - rect.left) as u32,
+                (rect.bottom - rect.top) as u32,
             )
         }
     }
+
+    /// Releases the mouse capture of a popup and hides it.
+    fn dismiss_popup(&self) {
+        unsafe {
+            ReleaseCapture();
+            ShowWindow(self.hwnd, SW_HIDE);
+        }
+    }
+}
+
+unsafe extern "system" fn enum_monitors(
+    hmonitor: HMONITOR,
+    _hdc: HDC,
+    _rect: LPRECT,
+    data: LPARAM,
+) -> BOOL {
+    let hmonitors = &mut *(data as *mut Vec<HMONITOR>);
+    hmonitors.push(hmonitor);
+    TRUE
+}
+
+unsafe fn monitor_info(hmonitor: HMONITOR) -> MONITORINFOEXW {
+    let mut info: MONITORINFOEXW = std::mem::zeroed();
+    info.cbSize = size_of::<MONITORINFOEXW>() as DWORD;
+    GetMonitorInfoW(
+        hmonitor,
+        &mut info as *mut MONITORINFOEXW as *mut MONITORINFO,
+    );
+    info
+}
+
+fn mode_info(id: u64, devmode: &DEVMODEW) -> ModeInfo {
+    ModeInfo {
+        id,
+        size: (devmode.dmPelsWidth, devmode.dmPelsHeight),
+        bit_depth: devmode.dmBitsPerPel as u16,
+        refresh_rate_millihertz: devmode.dmDisplayFrequency * 1000,
+    }
+}
+
+unsafe fn monitor_handle(hmonitor: HMONITOR) -> MonitorHandle {
+    let info = monitor_info(hmonitor);
+    let rect = info.rcMonitor;
+    let device = info.szDevice.as_ptr();
+
+    let mut modes = Vec::new();
+    let mut devmode: DEVMODEW = std::mem::zeroed();
+    devmode.dmSize = size_of::<DEVMODEW>() as WORD;
+    while EnumDisplaySettingsW(device, modes.len() as DWORD, &mut devmode) != 0 {
+        modes.push(mode_info(modes.len() as u64, &devmode));
+    }
+
+    let current_mode = match EnumDisplaySettingsW(device, ENUM_CURRENT_SETTINGS, &mut devmode) {
+        0 => None,
+        _ => {
+            let current = mode_info(0, &devmode);
+            modes
+                .iter()
+                .find(|mode| {
+                    (mode.size, mode.bit_depth, mode.refresh_rate_millihertz)
+                        == (
+                            current.size,
+                            current.bit_depth,
+                            current.refresh_rate_millihertz,
+                        )
+                })
+                .copied()
+                .or(Some(current))
+        }
+    };
+
+    let hdc = CreateDCW(device, device, std::ptr::null(), std::ptr::null());
+    let size_mm = (
+        GetDeviceCaps(hdc, HORZSIZE) as u32,
+        GetDeviceCaps(hdc, VERTSIZE) as u32,
+    );
+    let scale_factor = GetDeviceCaps(hdc, LOGPIXELSX) as f64 / 96.0;
+    DeleteDC(hdc);
+
+    let name_len = info
+        .szDevice
+        .iter()
+        .position(|c| *c == 0)
+        .unwrap_or(info.szDevice.len());
+
+    MonitorHandle {
+        id: hmonitor as u64,
+        name: Some(String::from_utf16_lossy(&info.szDevice[..name_len])),
+        position: (rect.left, rect.top),
+        size: (
+            (rect.right - rect.left) as u32,
+            (rect.bottom - rect.top) as u32,
+        ),
+        size_mm,
+        scale_factor,
+        primary: info.dwFlags & MONITORINFOF_PRIMARY != 0,
+        current_mode,
+        modes,
+    }
 }
 
 unsafe impl HasRawWindowHandle for RawWindow {
@@ -307,6 +952,12 @@ unsafe impl HasRawWindowHandle for RawWindow {
     }
 }
 
+unsafe impl HasRawDisplayHandle for RawWindow {
+    fn raw_display_handle(&self) -> RawDisplayHandle {
+        RawDisplayHandle::Windows(raw_window_handle::WindowsDisplayHandle::empty())
+    }
+}
+
 static mut MSG: RefCell<Lazy<MSG>> = RefCell::new(Lazy::new(|| unsafe { std::mem::zeroed() }));
 
 extern "system" fn wndproc(hWnd: HWND, Msg: UINT, wParam: WPARAM, lParam: LPARAM) -> LRESULT {
@@ -334,3 +985,128 @@ unsafe fn set_msg(Msg: UINT, wParam: WPARAM, lParam: LPARAM) {
     MSG.borrow_mut().wParam = wParam;
     MSG.borrow_mut().lParam = lParam;
 }
+
+/// The Win32 clipboard is not implemented yet, all operations fail with NotSupported.
+impl RawWindow {
+    pub(crate) fn clipboard_get(
+        &self,
+        _selection: Selection,
+        _mime_type: &str,
+    ) -> Result<Vec<u8>, ClipboardError> {
+        Err(ClipboardError::NotSupported(
+            "the Win32 clipboard".to_owned(),
+        ))
+    }
+
+    pub(crate) fn clipboard_set(
+        &self,
+        _selection: Selection,
+        _contents: Vec<(String, Vec<u8>)>,
+    ) -> Result<(), ClipboardError> {
+        Err(ClipboardError::NotSupported(
+            "the Win32 clipboard".to_owned(),
+        ))
+    }
+
+    pub(crate) fn clipboard_mime_types(
+        &self,
+        _selection: Selection,
+    ) -> Result<Vec<String>, ClipboardError> {
+        Err(ClipboardError::NotSupported(
+            "the Win32 clipboard".to_owned(),
+        ))
+    }
+}
+
+/// OLE drag and drop is not implemented yet, the window is no drop target.
+impl RawWindow {
+    pub(crate) fn set_drag_action(&self, _action: Option<DropAction>) {}
+
+    pub(crate) fn drag_source_actions(&self) -> Vec<DropAction> {
+        Vec::new()
+    }
+}
+
+/// WGL contexts are not implemented yet, creating one fails with GlError::NotSupported.
+#[cfg(feature = "gl")]
+pub enum RawGlContext {}
+
+#[cfg(feature = "gl")]
+impl IGlContext for RawGlContext {
+    fn new(_window: &RawWindow, _attributes: &GlAttributes) -> Result<Self, GlError> {
+        Err(GlError::NotSupported("WGL".to_owned()))
+    }
+
+    fn make_current(&self) -> Result<(), GlError> {
+        match *self {}
+    }
+
+    fn swap_buffers(&self) {
+        match *self {}
+    }
+
+    fn set_swap_interval(&self, _interval: i32) {
+        match *self {}
+    }
+
+    fn get_proc_address(&self, _name: &str) -> *const c_void {
+        match *self {}
+    }
+
+    fn size(&self) -> PhysicalSize {
+        match *self {}
+    }
+
+    fn resize(&self, _size: PhysicalSize) {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "vulkan")]
+const VK_STRUCTURE_TYPE_WIN32_SURFACE_CREATE_INFO_KHR: i32 = 1000009000;
+
+#[cfg(feature = "vulkan")]
+#[repr(C)]
+struct VkWin32SurfaceCreateInfoKHR {
+    s_type: i32,
+    p_next: *const c_void,
+    flags: u32,
+    hinstance: HMODULE,
+    hwnd: HWND,
+}
+
+#[cfg(feature = "vulkan")]
+type VkCreateWin32SurfaceKHR = unsafe extern "system" fn(
+    VkInstance,
+    *const VkWin32SurfaceCreateInfoKHR,
+    *const c_void,
+    *mut VkSurfaceKHR,
+) -> VkResult;
+
+#[cfg(feature = "vulkan")]
+impl RawWindow {
+    pub(crate) fn required_vulkan_extensions() -> &'static [&'static str] {
+        &["VK_KHR_surface", "VK_KHR_win32_surface"]
+    }
+
+    pub(crate) unsafe fn create_vulkan_surface(
+        &self,
+        instance: VkInstance,
+    ) -> Result<VkSurfaceKHR, VulkanError> {
+        let create_surface: VkCreateWin32SurfaceKHR =
+            std::mem::transmute(instance_proc_address(instance, "vkCreateWin32SurfaceKHR")?);
+        let info = VkWin32SurfaceCreateInfoKHR {
+            s_type: VK_STRUCTURE_TYPE_WIN32_SURFACE_CREATE_INFO_KHR,
+            p_next: std::ptr::null(),
+            flags: 0,
+            hinstance: self.hinstance,
+            hwnd: self.hwnd,
+        };
+
+        let mut surface = 0;
+        match create_surface(instance, &info, std::ptr::null(), &mut surface) {
+            VK_SUCCESS => Ok(surface),
+            result => Err(VulkanError::SurfaceCreationFailed(result)),
+        }
+    }
+}
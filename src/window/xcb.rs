@@ -0,0 +1,2399 @@
+//! X11 backend built on XCB through x11rb, selected with the xcb feature instead of Xlib.
+//! Replies are awaited only where they are needed, errors of other requests arrive as events.
+
+#[cfg(feature = "gl")]
+use crate::window::egl::{EglContext, EGL_PLATFORM_XCB_EXT, EGL_WINDOW_BIT};
+use crate::window::{
+    dpi_scale_factor, parse_uri_list, parse_xsettings_dpi, place_popup, ClipboardError,
+    ConstraintAdjustment, ControlFlow, DropAction, Fullscreen, Gravity, IWindow, Icon, ModeInfo,
+    MonitorHandle, PhysicalPosition, PhysicalSize, PixelFormat, PopupError, Position, Rect,
+    Selection, Size, WindowBuildAction, WindowEvent, WindowLevel, WindowType,
+};
+#[cfg(feature = "vulkan")]
+use crate::window::{
+    instance_proc_address, VkInstance, VkResult, VkSurfaceKHR, VulkanError, VK_SUCCESS,
+};
+#[cfg(feature = "gl")]
+use crate::window::{GlAttributes, GlError, IGlContext};
+use raw_window_handle::{
+    HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
+};
+use std::cell::{RefCell, RefMut};
+use std::collections::VecDeque;
+#[cfg(any(feature = "gl", feature = "vulkan"))]
+use std::ffi::c_void;
+use std::os::fd::AsRawFd;
+use std::path::PathBuf;
+use std::ptr::{null, null_mut};
+use std::time::{Duration, Instant};
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::errors::{ConnectionError, ReplyError};
+use x11rb::protocol::randr::{self, ConnectionExt as _};
+use x11rb::protocol::shm::{self, ConnectionExt as _};
+use x11rb::protocol::xinerama::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{self, ConnectionExt as _};
+use x11rb::protocol::Event;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::xcb_ffi::XCBConnection;
+use x11rb::{COPY_DEPTH_FROM_PARENT, CURRENT_TIME, NONE};
+
+pub struct WindowHandle {
+    pub connection: XCBConnection,
+    pub window: xproto::Window,
+}
+
+pub struct WindowInstance<'a> {
+    pub connection: &'a XCBConnection,
+    pub window: xproto::Window,
+}
+
+pub struct RawWindow {
+    connection: XCBConnection,
+    /// Index of the screen the window is on in the connection setup.
+    screen: usize,
+    window: xproto::Window,
+    /// Looked up once, some renderers ask for the raw window handle every frame.
+    visual_id: xproto::Visualid,
+    atoms: Atoms,
+
+    fullscreen: RefCell<Option<Fullscreen>>,
+    /// CRTC and mode to restore after leaving exclusive fullscreen.
+    saved_mode: RefCell<Option<(randr::Crtc, randr::Mode)>>,
+    /// Popups grab the pointer and the keyboard and are dismissed by clicks outside of them.
+    popup: bool,
+    running: RefCell<bool>,
+    /// Embedder (socket) window when the window is embedded with XEmbed.
+    embedder: RefCell<Option<xproto::Window>>,
+    frame: RefCell<Option<FrameImage>>,
+    selections: RefCell<Vec<OwnedSelection>>,
+    incr_transfers: RefCell<Vec<IncrTransfer>>,
+    /// Events read while waiting for a selection, delivered by the run loop.
+    deferred_events: RefCell<VecDeque<Event>>,
+    /// Drag of another client over the window.
+    drag: RefCell<Option<XdndDrag>>,
+}
+
+const XK_ESCAPE: xproto::Keysym = 0xff1b;
+
+x11rb::atom_manager! {
+    /// Atoms the window uses, interned together when it is created instead of with a round trip
+    /// on each use.
+    Atoms: AtomsCookie {
+        ATOM_PAIR,
+        CLIPBOARD,
+        GWL_SELECTION,
+        GWL_TIMESTAMP,
+        GWL_XDND,
+        INCR,
+        MULTIPLE,
+        PRIMARY,
+        TARGETS,
+        TIMESTAMP,
+        UTF8_STRING,
+        XdndActionAsk,
+        XdndActionCopy,
+        XdndActionLink,
+        XdndActionList,
+        XdndActionMove,
+        XdndAware,
+        XdndDrop,
+        XdndEnter,
+        XdndFinished,
+        XdndLeave,
+        XdndPosition,
+        XdndSelection,
+        XdndStatus,
+        XdndTypeList,
+        _MOTIF_WM_HINTS,
+        _NET_WM_DESKTOP,
+        _NET_WM_FULLSCREEN_MONITORS,
+        _NET_WM_ICON,
+        _NET_WM_ICON_NAME,
+        _NET_WM_NAME,
+        _NET_WM_PID,
+        _NET_WM_STATE,
+        _NET_WM_STATE_ABOVE,
+        _NET_WM_STATE_BELOW,
+        _NET_WM_STATE_FULLSCREEN,
+        _NET_WM_STATE_MODAL,
+        _NET_WM_STATE_STICKY,
+        _NET_WM_WINDOW_TYPE,
+        _NET_WM_WINDOW_TYPE_DIALOG,
+        _NET_WM_WINDOW_TYPE_DOCK,
+        _NET_WM_WINDOW_TYPE_MENU,
+        _NET_WM_WINDOW_TYPE_NORMAL,
+        _NET_WM_WINDOW_TYPE_NOTIFICATION,
+        _NET_WM_WINDOW_TYPE_POPUP_MENU,
+        _NET_WM_WINDOW_TYPE_SPLASH,
+        _NET_WM_WINDOW_TYPE_TOOLBAR,
+        _NET_WM_WINDOW_TYPE_TOOLTIP,
+        _NET_WM_WINDOW_TYPE_UTILITY,
+        _XEMBED,
+        _XEMBED_INFO,
+        TEXT_URI_LIST: b"text/uri-list",
+    }
+}
+
+impl IWindow<'_> for RawWindow {
+    fn new(
+        title: String,
+        size: Size,
+        position: Position,
+        border_width: u32,
+        parent: Option<RawWindowHandle>,
+        build_action: Box<&mut dyn WindowBuildAction>,
+    ) -> Self {
+        build_action.pre_init();
+
+        match build_action.override_window_handle() {
+            None => {
+                let (connection, screen) =
+                    XCBConnection::connect(None).expect("failed to connect to the X server");
+                let setup_screen = &connection.setup().roots[screen];
+
+                // The monitor is not known before the window exists, so the desktop wide factor is used
+                let scale_factor = desktop_scale_factor(&connection, screen).unwrap_or(1.0);
+                let size = size.to_physical(scale_factor);
+                let position = position.to_physical(scale_factor);
+
+                // Unlike safex, XCB creates windows below foreign windows directly
+                let parent = match parent {
+                    Some(RawWindowHandle::Xcb(parent)) => parent.window,
+                    Some(RawWindowHandle::Xlib(parent)) => parent.window as xproto::Window,
+                    _ => setup_screen.root,
+                };
+
+                let window = connection
+                    .generate_id()
+                    .expect("failed to allocate a window id");
+                let aux = xproto::CreateWindowAux::new()
+                    .background_pixel(setup_screen.white_pixel)
+                    .event_mask(event_mask());
+                // Errors, e.g. a BadMatch for a parent of another depth, leave no window to use
+                connection
+                    .create_window(
+                        COPY_DEPTH_FROM_PARENT,
+                        window,
+                        parent,
+                        position.x as i16,
+                        position.y as i16,
+                        size.width as u16,
+                        size.height as u16,
+                        border_width as u16,
+                        xproto::WindowClass::INPUT_OUTPUT,
+                        setup_screen.root_visual,
+                        &aux,
+                    )
+                    .map_err(ReplyError::from)
+                    .and_then(|cookie| cookie.check())
+                    .expect("failed to create the window");
+                let atoms = intern_atoms(&connection);
+
+                let handle = WindowInstance {
+                    connection: &connection,
+                    window,
+                };
+
+                build_action.window_created(&handle);
+
+                let raw = Self {
+                    visual_id: setup_screen.root_visual,
+                    atoms,
+                    connection,
+                    screen,
+                    window,
+                    fullscreen: RefCell::new(None),
+                    saved_mode: RefCell::new(None),
+                    popup: false,
+                    running: RefCell::new(true),
+                    embedder: RefCell::new(None),
+                    frame: RefCell::new(None),
+                    selections: RefCell::new(Vec::new()),
+                    incr_transfers: RefCell::new(Vec::new()),
+                    deferred_events: RefCell::new(VecDeque::new()),
+                    drag: RefCell::new(None),
+                };
+                raw.set_window_title(&title);
+                raw.set_client_properties();
+                raw.change_atom_property(
+                    raw.atoms.XdndAware,
+                    xproto::AtomEnum::ATOM.into(),
+                    &[XDND_VERSION],
+                );
+                raw
+            }
+
+            Some(handle) => {
+                let aux = xproto::ChangeWindowAttributesAux::new().event_mask(event_mask());
+                let _ = handle
+                    .connection
+                    .change_window_attributes(handle.window, &aux);
+                let screen = screen_of(&handle.connection, handle.window);
+                let visual_id = window_visual_id(&handle.connection, handle.window);
+                let atoms = intern_atoms(&handle.connection);
+                Self {
+                    visual_id,
+                    atoms,
+                    connection: handle.connection,
+                    screen,
+                    window: handle.window,
+                    fullscreen: RefCell::new(None),
+                    saved_mode: RefCell::new(None),
+                    popup: false,
+                    running: RefCell::new(true),
+                    embedder: RefCell::new(None),
+                    frame: RefCell::new(None),
+                    selections: RefCell::new(Vec::new()),
+                    incr_transfers: RefCell::new(Vec::new()),
+                    deferred_events: RefCell::new(VecDeque::new()),
+                    drag: RefCell::new(None),
+                }
+            }
+        }
+    }
+
+    fn run<F>(&self, mut callback: F)
+    where
+        F: FnMut(WindowEvent, &mut ControlFlow),
+    {
+        let mut control_flow = ControlFlow::Listen;
+        let mut scale_factor = self.scale_factor();
+        // Position of the last ConfigureNotify
+        let mut position = None;
+
+        self.show();
+
+        while *self.running.borrow() {
+            // Unlike Xlib's IO error handler, a lost connection ends the loop instead of the process
+            let deferred = self.deferred_events.borrow_mut().pop_front();
+            let event = match deferred {
+                Some(event) => event,
+                None => match self.connection.wait_for_event() {
+                    Ok(event) => event,
+                    Err(_) => break,
+                },
+            };
+
+            match control_flow {
+                ControlFlow::Listen => match event {
+                    Event::Expose(expose) => {
+                        // Each exposed rectangle arrives separately, count tells how many follow
+                        let rect = Rect::new(
+                            expose.x as i32,
+                            expose.y as i32,
+                            expose.width as u32,
+                            expose.height as u32,
+                        );
+                        callback(WindowEvent::Expose(rect), &mut control_flow);
+                    }
+                    Event::ClientMessage(message) if message.type_ == self.atoms._XEMBED => {
+                        if let Some(event) = self.handle_xembed_message(&message) {
+                            callback(event, &mut control_flow);
+                        }
+                    }
+                    Event::ClientMessage(message) => {
+                        self.handle_xdnd_message(&message, &mut callback, &mut control_flow);
+                    }
+                    Event::SelectionRequest(request) => self.handle_selection_request(&request),
+                    Event::SelectionClear(clear) => {
+                        // Another client took the selection
+                        self.selections
+                            .borrow_mut()
+                            .retain(|owned| owned.selection != clear.selection);
+                    }
+                    Event::SelectionNotify(notify)
+                        if notify.selection == self.atoms.XdndSelection =>
+                    {
+                        // Answer to the request for the files of a drag
+                        let event = self.receive_xdnd_selection(&notify);
+                        self.deliver_xdnd_entered(event, &mut callback, &mut control_flow);
+                    }
+                    Event::PropertyNotify(notify) if notify.state == xproto::Property::DELETE => {
+                        self.continue_incr_transfer(notify.window, notify.atom);
+                    }
+                    Event::PropertyNotify(notify)
+                        if notify.window == self.window && notify.atom == self.atoms.GWL_XDND =>
+                    {
+                        let event = self.receive_xdnd_chunk();
+                        self.deliver_xdnd_entered(event, &mut callback, &mut control_flow);
+                    }
+                    Event::ConfigureNotify(configure) => {
+                        // Moving the window may have moved it onto a monitor with another scale
+                        // factor, looking that up takes round trips, so only after it moved
+                        let new_position = (configure.x, configure.y);
+                        if position == Some(new_position) {
+                            continue;
+                        }
+                        position = Some(new_position);
+
+                        let new_scale_factor = self.scale_factor();
+                        if new_scale_factor != scale_factor {
+                            scale_factor = new_scale_factor;
+                            callback(
+                                WindowEvent::ScaleFactorChanged(scale_factor),
+                                &mut control_flow,
+                            );
+                        }
+                    }
+                    Event::ButtonPress(button) if self.popup => {
+                        // The pointer is grabbed, so clicks outside of the popup are reported as well
+                        let size = self.get_window_size();
+                        if button.event_x < 0
+                            || button.event_y < 0
+                            || button.event_x as i32 >= size.width as i32
+                            || button.event_y as i32 >= size.height as i32
+                        {
+                            self.dismiss_popup();
+                            callback(WindowEvent::PopupDone, &mut control_flow);
+                        }
+                    }
+                    Event::KeyPress(key) if self.popup && self.keysym(key.detail) == XK_ESCAPE => {
+                        self.dismiss_popup();
+                        callback(WindowEvent::PopupDone, &mut control_flow);
+                    }
+                    Event::Error(error) => {
+                        let message = format!(
+                            "{:?} error in {} request (value {:#x})",
+                            error.error_kind,
+                            error.request_name.unwrap_or("unknown"),
+                            error.bad_value
+                        );
+                        callback(WindowEvent::DisplayError(message), &mut control_flow);
+                    }
+                    _ => {}
+                },
+                ControlFlow::Exit(code) => {
+                    std::process::exit(code as i32);
+                }
+            }
+        }
+    }
+
+    fn get_instance(&self) -> WindowInstance<'_> {
+        WindowInstance {
+            connection: &self.connection,
+            window: self.window,
+        }
+    }
+
+    fn set_window_title(&self, title: &str) {
+        // WM_NAME is Latin-1, so the UTF-8 EWMH properties are set as well
+        let latin1: Vec<u8> = title
+            .chars()
+            .map(|c| u8::try_from(c).unwrap_or(b'?'))
+            .collect();
+        let utf8_string = self.atoms.UTF8_STRING;
+        self.change_string_property(
+            xproto::AtomEnum::WM_NAME.into(),
+            xproto::AtomEnum::STRING.into(),
+            &latin1,
+        );
+        self.change_string_property(self.atoms._NET_WM_NAME, utf8_string, title.as_bytes());
+        self.change_string_property(self.atoms._NET_WM_ICON_NAME, utf8_string, title.as_bytes());
+        self.flush();
+    }
+
+    fn title(&self) -> String {
+        let title = self.get_property(self.atoms._NET_WM_NAME, self.atoms.UTF8_STRING);
+        match title {
+            Some(title) if title.format == 8 => String::from_utf8_lossy(&title.value).into_owned(),
+            _ => String::new(),
+        }
+    }
+
+    fn set_window_border_width(&self, border_width: u32) {
+        let aux = xproto::ConfigureWindowAux::new().border_width(border_width);
+        let _ = self.connection.configure_window(self.window, &aux);
+        self.flush();
+    }
+
+    fn set_app_id(&self, app_id: &str, instance_name: &str) {
+        // Instance and class, each terminated with a null byte
+        let class = [instance_name.as_bytes(), &[0], app_id.as_bytes(), &[0]].concat();
+        self.change_string_property(
+            xproto::AtomEnum::WM_CLASS.into(),
+            xproto::AtomEnum::STRING.into(),
+            &class,
+        );
+        self.flush();
+    }
+
+    fn set_window_type(&self, window_type: WindowType) {
+        let name = match window_type {
+            WindowType::Normal => self.atoms._NET_WM_WINDOW_TYPE_NORMAL,
+            WindowType::Dialog => self.atoms._NET_WM_WINDOW_TYPE_DIALOG,
+            WindowType::Utility => self.atoms._NET_WM_WINDOW_TYPE_UTILITY,
+            WindowType::Toolbar => self.atoms._NET_WM_WINDOW_TYPE_TOOLBAR,
+            WindowType::Splash => self.atoms._NET_WM_WINDOW_TYPE_SPLASH,
+            WindowType::Tooltip => self.atoms._NET_WM_WINDOW_TYPE_TOOLTIP,
+            WindowType::Menu => self.atoms._NET_WM_WINDOW_TYPE_MENU,
+            WindowType::Notification => self.atoms._NET_WM_WINDOW_TYPE_NOTIFICATION,
+            WindowType::Dock => self.atoms._NET_WM_WINDOW_TYPE_DOCK,
+        };
+        self.change_atom_property(
+            self.atoms._NET_WM_WINDOW_TYPE,
+            xproto::AtomEnum::ATOM.into(),
+            &[name],
+        );
+        self.flush();
+    }
+
+    fn set_transient_for(&self, parent: Option<&RawWindow>) {
+        let property = xproto::AtomEnum::WM_TRANSIENT_FOR.into();
+        match parent {
+            Some(parent) => {
+                self.change_atom_property(
+                    property,
+                    xproto::AtomEnum::WINDOW.into(),
+                    &[parent.window],
+                );
+            }
+            None => {
+                let _ = self.connection.delete_property(self.window, property);
+            }
+        }
+        self.flush();
+    }
+
+    fn set_modal(&self, b: bool) {
+        self.set_net_wm_state(self.atoms._NET_WM_STATE_MODAL, b);
+    }
+
+    fn set_undecorated(&self, b: bool) {
+        // flags (MWM_HINTS_DECORATIONS), functions, decorations, input mode, status
+        let property = self.atoms._MOTIF_WM_HINTS;
+        self.change_atom_property(property, property, &[1 << 1, 0, !b as u32, 0, 0]);
+        self.flush();
+    }
+
+    fn set_window_icon(&self, icon: Option<Icon>) {
+        match icon {
+            None => {
+                let _ = self
+                    .connection
+                    .delete_property(self.window, self.atoms._NET_WM_ICON);
+            }
+            Some(icon) => {
+                // width, height and the ARGB pixels of every size one after another
+                let data = icon
+                    .sizes()
+                    .iter()
+                    .flat_map(|icon| [icon.width, icon.height].into_iter().chain(icon.to_argb()))
+                    .collect::<Vec<u32>>();
+                self.change_atom_property(
+                    self.atoms._NET_WM_ICON,
+                    xproto::AtomEnum::CARDINAL.into(),
+                    &data,
+                );
+            }
+        }
+        self.flush();
+    }
+
+    fn set_minimized(&self, b: bool) {
+        match b {
+            true => self.hide(),
+            false => self.show(),
+        }
+    }
+
+    fn set_maximized(&self, b: bool) {
+        match b {
+            true => self.hide(),
+            false => self.show(),
+        }
+    }
+
+    fn set_window_level(&self, level: WindowLevel) {
+        self.set_net_wm_state(
+            self.atoms._NET_WM_STATE_ABOVE,
+            level == WindowLevel::AlwaysOnTop,
+        );
+        self.set_net_wm_state(
+            self.atoms._NET_WM_STATE_BELOW,
+            level == WindowLevel::AlwaysOnBottom,
+        );
+    }
+
+    fn set_visible_on_all_workspaces(&self, b: bool) {
+        self.set_net_wm_state(self.atoms._NET_WM_STATE_STICKY, b);
+    }
+
+    fn set_workspace(&self, workspace: u32) {
+        if self.is_mapped() {
+            // desktop, source indication
+            self.send_client_message(self.atoms._NET_WM_DESKTOP, [workspace, 1, 0, 0, 0]);
+        } else {
+            self.change_atom_property(
+                self.atoms._NET_WM_DESKTOP,
+                xproto::AtomEnum::CARDINAL.into(),
+                &[workspace],
+            );
+            self.flush();
+        }
+    }
+
+    fn show(&self) {
+        let _ = self.connection.map_window(self.window);
+        self.flush();
+    }
+
+    fn hide(&self) {
+        let _ = self.connection.unmap_window(self.window);
+        self.flush();
+    }
+
+    fn get_window_pos(&self) -> PhysicalPosition {
+        match self.geometry() {
+            Some(geometry) => PhysicalPosition::new(geometry.x as i32, geometry.y as i32),
+            None => PhysicalPosition::new(0, 0),
+        }
+    }
+
+    fn set_window_pos(&self, position: Position) {
+        let position = position.to_physical(self.scale_factor());
+        let aux = xproto::ConfigureWindowAux::new()
+            .x(position.x)
+            .y(position.y);
+        let _ = self.connection.configure_window(self.window, &aux);
+        self.flush();
+    }
+
+    fn raise(&self) {
+        let aux = xproto::ConfigureWindowAux::new().stack_mode(xproto::StackMode::ABOVE);
+        let _ = self.connection.configure_window(self.window, &aux);
+        self.flush();
+    }
+
+    fn lower(&self) {
+        let aux = xproto::ConfigureWindowAux::new().stack_mode(xproto::StackMode::BELOW);
+        let _ = self.connection.configure_window(self.window, &aux);
+        self.flush();
+    }
+
+    // X11 child windows are always drawn independently of their parent
+    fn set_synchronized(&self, _b: bool) {}
+
+    fn get_window_size(&self) -> PhysicalSize {
+        match self.geometry() {
+            Some(geometry) => PhysicalSize::new(geometry.width as u32, geometry.height as u32),
+            None => PhysicalSize::new(0, 0),
+        }
+    }
+
+    fn scale_factor(&self) -> f64 {
+        match self.current_monitor() {
+            Some(monitor) => monitor.scale_factor,
+            None => desktop_scale_factor(&self.connection, self.screen).unwrap_or(1.0),
+        }
+    }
+
+    fn set_fullscreen(&self, fullscreen: Option<Fullscreen>) {
+        self.restore_video_mode();
+
+        match &fullscreen {
+            None => {
+                self.set_net_wm_state(self.atoms._NET_WM_STATE_FULLSCREEN, false);
+            }
+            Some(fullscreen) => {
+                let monitor = match fullscreen {
+                    Fullscreen::Borderless(Some(monitor)) => Some(monitor.clone()),
+                    Fullscreen::Borderless(None) => self.current_monitor(),
+                    Fullscreen::Exclusive(mode) => {
+                        self.switch_video_mode(&mode.monitor, mode.info.id as randr::Mode);
+                        Some(mode.monitor.clone())
+                    }
+                };
+
+                // _NET_WM_FULLSCREEN_MONITORS takes Xinerama indices, which do not necessarily
+                // follow the order of the RandR monitors
+                let index = monitor.and_then(|monitor| xinerama_index(&self.connection, &monitor));
+                if let Some(index) = index {
+                    // top, bottom, left, right, source indication
+                    let monitors = [index, index, index, index, 1];
+                    if self.is_mapped() {
+                        self.send_client_message(self.atoms._NET_WM_FULLSCREEN_MONITORS, monitors);
+                    } else {
+                        self.change_atom_property(
+                            self.atoms._NET_WM_FULLSCREEN_MONITORS,
+                            xproto::AtomEnum::CARDINAL.into(),
+                            &monitors[..4],
+                        );
+                    }
+                }
+
+                self.set_net_wm_state(self.atoms._NET_WM_STATE_FULLSCREEN, true);
+            }
+        }
+
+        *self.fullscreen.borrow_mut() = fullscreen;
+    }
+
+    fn get_fullscreen(&self) -> Option<Fullscreen> {
+        self.fullscreen.borrow().clone()
+    }
+
+    fn available_monitors(&self) -> Vec<MonitorHandle> {
+        self.monitors().unwrap_or_default()
+    }
+
+    fn primary_monitor(&self) -> Option<MonitorHandle> {
+        let monitors = self.available_monitors();
+        monitors
+            .iter()
+            .find(|monitor| monitor.primary)
+            .or(monitors.first())
+            .cloned()
+    }
+
+    fn current_monitor(&self) -> Option<MonitorHandle> {
+        let size = self.get_window_size();
+        let (x, y) = self.root_position();
+        let (x, y) = (x + size.width as i32 / 2, y + size.height as i32 / 2);
+
+        let monitors = self.available_monitors();
+        monitors
+            .iter()
+            .find(|monitor| monitor.contains(x, y))
+            .or(monitors.first())
+            .cloned()
+    }
+
+    fn create_popup(
+        &self,
+        size: Size,
+        anchor_rect: Rect,
+        gravity: Gravity,
+        constraint_adjustment: ConstraintAdjustment,
+    ) -> Result<Self, PopupError> {
+        let (connection, screen) =
+            XCBConnection::connect(None).expect("failed to connect to the X server");
+        let setup_screen = &connection.setup().roots[screen];
+
+        // Override-redirect windows are placed by the client, in root window coordinates
+        let (x, y) = self.root_position();
+        let anchor_rect = Rect::new(
+            anchor_rect.x + x,
+            anchor_rect.y + y,
+            anchor_rect.width,
+            anchor_rect.height,
+        );
+        let bounds = match self.current_monitor() {
+            Some(monitor) => Rect::new(
+                monitor.position.0,
+                monitor.position.1,
+                monitor.size.0,
+                monitor.size.1,
+            ),
+            None => Rect::new(
+                0,
+                0,
+                setup_screen.width_in_pixels as u32,
+                setup_screen.height_in_pixels as u32,
+            ),
+        };
+        let rect = place_popup(
+            anchor_rect,
+            gravity,
+            size.to_physical(self.scale_factor()),
+            constraint_adjustment,
+            bounds,
+        );
+
+        let window = connection
+            .generate_id()
+            .expect("failed to allocate a window id");
+        let aux = xproto::CreateWindowAux::new()
+            .background_pixel(setup_screen.white_pixel)
+            .override_redirect(1)
+            .event_mask(
+                event_mask() | xproto::EventMask::BUTTON_PRESS | xproto::EventMask::KEY_PRESS,
+            );
+        connection
+            .create_window(
+                COPY_DEPTH_FROM_PARENT,
+                window,
+                setup_screen.root,
+                rect.x as i16,
+                rect.y as i16,
+                rect.width as u16,
+                rect.height as u16,
+                0,
+                xproto::WindowClass::INPUT_OUTPUT,
+                setup_screen.root_visual,
+                &aux,
+            )
+            .map_err(ReplyError::from)
+            .and_then(|cookie| cookie.check())
+            .expect("failed to create the popup");
+
+        let popup = Self {
+            visual_id: setup_screen.root_visual,
+            atoms: self.atoms,
+            connection,
+            screen,
+            window,
+            fullscreen: RefCell::new(None),
+            saved_mode: RefCell::new(None),
+            popup: true,
+            running: RefCell::new(true),
+            embedder: RefCell::new(None),
+            frame: RefCell::new(None),
+            selections: RefCell::new(Vec::new()),
+            incr_transfers: RefCell::new(Vec::new()),
+            deferred_events: RefCell::new(VecDeque::new()),
+            drag: RefCell::new(None),
+        };
+        popup.change_atom_property(
+            popup.atoms._NET_WM_WINDOW_TYPE,
+            xproto::AtomEnum::ATOM.into(),
+            &[popup.atoms._NET_WM_WINDOW_TYPE_POPUP_MENU],
+        );
+        popup.set_transient_for(Some(self));
+        popup.set_client_properties();
+
+        // Grabs fail on windows that are not viewable yet, override-redirect windows are viewable
+        // as soon as the server processed the map request
+        let connection = &popup.connection;
+        let _ = connection.map_window(window);
+        let _ = connection.sync();
+        let pointer_mask = xproto::EventMask::BUTTON_PRESS
+            | xproto::EventMask::BUTTON_RELEASE
+            | xproto::EventMask::POINTER_MOTION;
+        // Both grabs are sent before waiting for the replies
+        let pointer = connection.grab_pointer(
+            false,
+            window,
+            pointer_mask,
+            xproto::GrabMode::ASYNC,
+            xproto::GrabMode::ASYNC,
+            NONE,
+            NONE,
+            CURRENT_TIME,
+        );
+        let keyboard = connection.grab_keyboard(
+            false,
+            window,
+            CURRENT_TIME,
+            xproto::GrabMode::ASYNC,
+            xproto::GrabMode::ASYNC,
+        );
+        if let (Ok(pointer), Ok(keyboard)) = (pointer, keyboard) {
+            let _ = (pointer.reply(), keyboard.reply());
+        }
+
+        Ok(popup)
+    }
+
+    fn framebuffer(&self) -> RefMut<'_, [u32]> {
+        let size = self.get_window_size();
+        let mut frame = self.frame.borrow_mut();
+
+        let resized = match frame.as_ref() {
+            Some(frame) => (frame.width, frame.height) != (size.width, size.height),
+            None => true,
+        };
+        if resized {
+            // The old image is released before the new one is created
+            if let Some(frame) = frame.take() {
+                frame.release(&self.connection);
+            }
+            let depth = self.setup_screen().root_depth;
+            *frame = Some(FrameImage::new(
+                &self.connection,
+                self.window,
+                depth,
+                size.width,
+                size.height,
+            ));
+        }
+
+        RefMut::map(frame, |frame| frame.as_mut().unwrap().pixels())
+    }
+
+    fn present(&self, damage: &[Rect]) {
+        let frame = self.frame.borrow();
+        let frame = match frame.as_ref() {
+            Some(frame) => frame,
+            None => return,
+        };
+
+        let bounds = Rect::new(0, 0, frame.width, frame.height);
+        let damage = match damage.is_empty() {
+            true => vec![bounds],
+            false => damage
+                .iter()
+                .filter_map(|rect| rect.intersection(&bounds))
+                .collect(),
+        };
+
+        for rect in damage {
+            if frame.put(&self.connection, self.window, rect).is_err() {
+                return;
+            }
+        }
+        // The server has to read the pixels before the application changes them again
+        let _ = self.connection.sync();
+    }
+
+    fn framebuffer_formats(&self) -> Vec<PixelFormat> {
+        vec![PixelFormat::Argb8888, PixelFormat::Xrgb8888]
+    }
+
+    fn set_framebuffer_format(&self, _format: PixelFormat) {
+        // The window has no alpha channel, so both supported formats share the same layout
+    }
+}
+
+impl Drop for RawWindow {
+    fn drop(&mut self) {
+        // The framebuffer is released while the connection is still open
+        if let Some(frame) = self.frame.borrow_mut().take() {
+            frame.release(&self.connection);
+        }
+        self.restore_video_mode();
+    }
+}
+
+impl RawWindow {
+    /// Embeds the window into the socket window of an XEmbed embedder.
+    pub(crate) fn xembed(&self, socket: u64) {
+        let xembed_info = self.atoms._XEMBED_INFO;
+        self.change_atom_property(xembed_info, xembed_info, &[XEMBED_VERSION, XEMBED_MAPPED]);
+
+        let _ = self
+            .connection
+            .reparent_window(self.window, socket as xproto::Window, 0, 0);
+        self.flush();
+
+        // Replaced by the window sent with XEMBED_EMBEDDED_NOTIFY
+        *self.embedder.borrow_mut() = Some(socket as xproto::Window);
+    }
+
+    /// Handles an _XEMBED client message and returns the event it corresponds to.
+    fn handle_xembed_message(&self, message: &xproto::ClientMessageEvent) -> Option<WindowEvent> {
+        // time, message, detail, data1, data2
+        let data = message.data.as_data32();
+
+        match data[1] {
+            XEMBED_EMBEDDED_NOTIFY => {
+                *self.embedder.borrow_mut() = Some(data[3]);
+                None
+            }
+            XEMBED_WINDOW_ACTIVATE => Some(WindowEvent::Activated(true)),
+            XEMBED_WINDOW_DEACTIVATE => Some(WindowEvent::Activated(false)),
+            // The embedder keeps the X input focus and forwards key events to the window
+            XEMBED_FOCUS_IN => Some(WindowEvent::Focused(true)),
+            XEMBED_FOCUS_OUT => Some(WindowEvent::Focused(false)),
+            _ => None,
+        }
+    }
+
+    /// Tells the window manager which process and machine the window belongs to.
+    fn set_client_properties(&self) {
+        self.change_atom_property(
+            self.atoms._NET_WM_PID,
+            xproto::AtomEnum::CARDINAL.into(),
+            &[std::process::id()],
+        );
+
+        if let Ok(hostname) = std::fs::read_to_string("/proc/sys/kernel/hostname") {
+            self.change_string_property(
+                xproto::AtomEnum::WM_CLIENT_MACHINE.into(),
+                xproto::AtomEnum::STRING.into(),
+                hostname.trim().as_bytes(),
+            );
+        }
+    }
+
+    /// Releases the grabs of a popup, hides it and ends its run loop.
+    fn dismiss_popup(&self) {
+        let _ = self.connection.ungrab_pointer(CURRENT_TIME);
+        let _ = self.connection.ungrab_keyboard(CURRENT_TIME);
+        self.hide();
+        *self.running.borrow_mut() = false;
+    }
+
+    fn setup_screen(&self) -> &xproto::Screen {
+        &self.connection.setup().roots[self.screen]
+    }
+
+    fn flush(&self) {
+        let _ = self.connection.flush();
+    }
+
+    fn geometry(&self) -> Option<xproto::GetGeometryReply> {
+        self.connection.get_geometry(self.window).ok()?.reply().ok()
+    }
+
+    /// Returns the position of the window in root window coordinates.
+    fn root_position(&self) -> (i32, i32) {
+        let root = self.setup_screen().root;
+        let reply = self
+            .connection
+            .translate_coordinates(self.window, root, 0, 0)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok());
+        match reply {
+            Some(reply) => (reply.dst_x as i32, reply.dst_y as i32),
+            None => (0, 0),
+        }
+    }
+
+    fn atom(&self, name: &str) -> xproto::Atom {
+        intern_atom(&self.connection, name).unwrap_or(NONE)
+    }
+
+    /// Returns the first keysym of the keycode, without modifiers.
+    fn keysym(&self, keycode: xproto::Keycode) -> xproto::Keysym {
+        let reply = self
+            .connection
+            .get_keyboard_mapping(keycode, 1)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok());
+        reply
+            .and_then(|reply| reply.keysyms.first().copied())
+            .unwrap_or(NONE)
+    }
+
+    fn is_mapped(&self) -> bool {
+        let reply = self
+            .connection
+            .get_window_attributes(self.window)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok());
+        match reply {
+            Some(attributes) => attributes.map_state != xproto::MapState::UNMAPPED,
+            None => false,
+        }
+    }
+
+    /// Sends an EWMH client message about this window to the root window.
+    fn send_client_message(&self, message_type: xproto::Atom, data: [u32; 5]) {
+        let event = xproto::ClientMessageEvent::new(32, self.window, message_type, data);
+        let _ = self.connection.send_event(
+            false,
+            self.setup_screen().root,
+            xproto::EventMask::SUBSTRUCTURE_REDIRECT | xproto::EventMask::SUBSTRUCTURE_NOTIFY,
+            event,
+        );
+        self.flush();
+    }
+
+    /// Replaces a 32-bit property of this window.
+    fn change_atom_property(&self, property: xproto::Atom, type_: xproto::Atom, values: &[u32]) {
+        let _ = self.connection.change_property32(
+            xproto::PropMode::REPLACE,
+            self.window,
+            property,
+            type_,
+            values,
+        );
+    }
+
+    /// Replaces an 8-bit property of this window.
+    fn change_string_property(&self, property: xproto::Atom, type_: xproto::Atom, value: &[u8]) {
+        let _ = self.connection.change_property8(
+            xproto::PropMode::REPLACE,
+            self.window,
+            property,
+            type_,
+            value,
+        );
+    }
+
+    /// Reads a property of this window.
+    fn get_property(
+        &self,
+        property: xproto::Atom,
+        type_: xproto::Atom,
+    ) -> Option<xproto::GetPropertyReply> {
+        self.connection
+            .get_property(false, self.window, property, type_, 0, u32::MAX)
+            .ok()?
+            .reply()
+            .ok()
+    }
+
+    /// Adds or removes a _NET_WM_STATE atom.
+    /// Mapped windows ask the window manager, unmapped windows set the property
+    /// that the window manager reads when the window is mapped.
+    fn set_net_wm_state(&self, state: xproto::Atom, enable: bool) {
+        if self.is_mapped() {
+            // _NET_WM_STATE_REMOVE = 0, _NET_WM_STATE_ADD = 1
+            self.send_client_message(self.atoms._NET_WM_STATE, [enable as u32, state, 0, 1, 0]);
+        } else {
+            let property = self.atoms._NET_WM_STATE;
+            let mut states = self
+                .get_property(property, xproto::AtomEnum::ATOM.into())
+                .and_then(|reply| Some(reply.value32()?.collect::<Vec<u32>>()))
+                .unwrap_or_default();
+            states.retain(|s| *s != state);
+            if enable {
+                states.push(state);
+            }
+            self.change_atom_property(property, xproto::AtomEnum::ATOM.into(), &states);
+            self.flush();
+        }
+    }
+
+    /// Enumerates the monitors of the screen with XRandR.
+    fn monitors(&self) -> Result<Vec<MonitorHandle>, ReplyError> {
+        let connection = &self.connection;
+
+        // Monitors were added in XRandR 1.5
+        if connection
+            .extension_information(randr::X11_EXTENSION_NAME)?
+            .is_none()
+        {
+            return Ok(Vec::new());
+        }
+        let version = connection.randr_query_version(1, 5)?.reply()?;
+        if (version.major_version, version.minor_version) < (1, 5) {
+            return Ok(Vec::new());
+        }
+
+        // Both requests are sent before waiting for the first reply
+        let root = self.setup_screen().root;
+        let monitors = connection.randr_get_monitors(root, true)?;
+        let resources = connection.randr_get_screen_resources_current(root)?;
+        let (monitors, resources) = (monitors.reply()?, resources.reply()?);
+        let depth = self.setup_screen().root_depth as u16;
+        let desktop_scale_factor = desktop_scale_factor(connection, self.screen);
+
+        let handles = monitors
+            .monitors
+            .iter()
+            .map(|info| {
+                let output = info.outputs.first().copied().unwrap_or(NONE);
+                let (modes, current_mode) = output_modes(connection, &resources, output, depth);
+                let size = (info.width as u32, info.height as u32);
+                let size_mm = (info.width_in_millimeters, info.height_in_millimeters);
+                MonitorHandle {
+                    id: output as u64,
+                    name: atom_name(connection, info.name),
+                    position: (info.x as i32, info.y as i32),
+                    size,
+                    size_mm,
+                    scale_factor: desktop_scale_factor
+                        .unwrap_or_else(|| dpi_scale_factor(size, size_mm)),
+                    primary: info.primary,
+                    current_mode,
+                    modes,
+                }
+            })
+            .collect();
+
+        Ok(handles)
+    }
+
+    /// Switches the CRTC driving the monitor to the given RRMode.
+    fn switch_video_mode(&self, monitor: &MonitorHandle, mode: randr::Mode) {
+        let _ = (|| -> Result<(), ReplyError> {
+            let connection = &self.connection;
+            let root = self.setup_screen().root;
+            let resources = connection
+                .randr_get_screen_resources_current(root)?
+                .reply()?;
+            let output = connection
+                .randr_get_output_info(monitor.id as randr::Output, resources.config_timestamp)?
+                .reply()?;
+            // Modes of other outputs are rejected by the server or drive the output out of range
+            if output.crtc == 0 || !output.modes.contains(&mode) {
+                return Ok(());
+            }
+            let crtc = output.crtc;
+            let info = connection
+                .randr_get_crtc_info(crtc, resources.config_timestamp)?
+                .reply()?;
+
+            let reply = connection
+                .randr_set_crtc_config(
+                    crtc,
+                    CURRENT_TIME,
+                    resources.config_timestamp,
+                    info.x,
+                    info.y,
+                    mode,
+                    info.rotation,
+                    &info.outputs,
+                )?
+                .reply()?;
+            if reply.status == randr::SetConfig::SUCCESS {
+                *self.saved_mode.borrow_mut() = Some((crtc, info.mode));
+            }
+            Ok(())
+        })();
+    }
+
+    /// Restores the video mode changed by exclusive fullscreen.
+    fn restore_video_mode(&self) {
+        let (crtc, mode) = match self.saved_mode.borrow_mut().take() {
+            Some(saved) => saved,
+            None => return,
+        };
+
+        let _ = (|| -> Result<(), ReplyError> {
+            let connection = &self.connection;
+            let root = self.setup_screen().root;
+            let resources = connection
+                .randr_get_screen_resources_current(root)?
+                .reply()?;
+            let info = connection
+                .randr_get_crtc_info(crtc, resources.config_timestamp)?
+                .reply()?;
+            connection
+                .randr_set_crtc_config(
+                    crtc,
+                    CURRENT_TIME,
+                    resources.config_timestamp,
+                    info.x,
+                    info.y,
+                    mode,
+                    info.rotation,
+                    &info.outputs,
+                )?
+                .reply()?;
+            Ok(())
+        })();
+    }
+}
+
+/// Software framebuffer of a window, shared with the X server through MIT-SHM when possible.
+struct FrameImage {
+    gc: xproto::Gcontext,
+    depth: u8,
+    /// Shared memory segment and the pixels mapped from it, None when they are sent with PutImage.
+    shm: Option<(shm::Seg, *mut u32)>,
+    /// Pixels of the image without MIT-SHM.
+    pixels: Vec<u32>,
+    width: u32,
+    height: u32,
+}
+
+impl FrameImage {
+    fn new(
+        connection: &XCBConnection,
+        window: xproto::Window,
+        depth: u8,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let gc = connection.generate_id().unwrap_or(NONE);
+        let _ = connection.create_gc(gc, window, &xproto::CreateGCAux::new());
+
+        let len = (width * height) as usize;
+        let shm = match connection.extension_information(shm::X11_EXTENSION_NAME) {
+            Ok(Some(_)) => unsafe { Self::attach_shared(connection, len) },
+            _ => None,
+        };
+
+        Self {
+            gc,
+            depth,
+            shm,
+            pixels: match shm {
+                Some(_) => Vec::new(),
+                None => vec![0; len],
+            },
+            width,
+            height,
+        }
+    }
+
+    unsafe fn attach_shared(
+        connection: &XCBConnection,
+        len: usize,
+    ) -> Option<(shm::Seg, *mut u32)> {
+        let shmid = libc::shmget(libc::IPC_PRIVATE, len * 4, libc::IPC_CREAT | 0o600);
+        if shmid < 0 {
+            return None;
+        }
+        let address = libc::shmat(shmid, null(), 0);
+
+        // Attaching fails with BadAccess when the server runs on another machine,
+        // the checked request reports that without an error handler
+        let attached = match (address as isize, connection.generate_id()) {
+            (-1, _) | (_, Err(_)) => None,
+            (_, Ok(seg)) => connection
+                .shm_attach(seg, shmid as u32, false)
+                .ok()
+                .and_then(|cookie| cookie.check().ok())
+                .map(|_| seg),
+        };
+
+        // The segment is freed once both sides detached it
+        libc::shmctl(shmid, libc::IPC_RMID, null_mut());
+
+        match attached {
+            Some(seg) => Some((seg, address as *mut u32)),
+            None => {
+                if address as isize != -1 {
+                    libc::shmdt(address);
+                }
+                None
+            }
+        }
+    }
+
+    fn pixels(&mut self) -> &mut [u32] {
+        match self.shm {
+            Some((_, address)) => unsafe {
+                std::slice::from_raw_parts_mut(address, (self.width * self.height) as usize)
+            },
+            None => &mut self.pixels,
+        }
+    }
+
+    /// Copies a part of the image to the same position in the window.
+    fn put(
+        &self,
+        connection: &XCBConnection,
+        window: xproto::Window,
+        rect: Rect,
+    ) -> Result<(), ConnectionError> {
+        if let Some((seg, _)) = self.shm {
+            connection.shm_put_image(
+                window,
+                self.gc,
+                self.width as u16,
+                self.height as u16,
+                rect.x as u16,
+                rect.y as u16,
+                rect.width as u16,
+                rect.height as u16,
+                rect.x as i16,
+                rect.y as i16,
+                self.depth,
+                xproto::ImageFormat::Z_PIXMAP.into(),
+                false,
+                seg,
+                0,
+            )?;
+            return Ok(());
+        }
+
+        // PutImage carries the pixels in the request, so the rows are split over as many
+        // requests as the maximum request length requires
+        let big_endian = connection.setup().image_byte_order == xproto::ImageOrder::MSB_FIRST;
+        let stride = rect.width as usize * 4;
+        let rows_per_request = ((connection.maximum_request_bytes() - 32) / stride).max(1);
+        let (x, width) = (rect.x as usize, rect.width as usize);
+
+        let rows = rect.y as usize..rect.y as usize + rect.height as usize;
+        for first_row in rows.clone().step_by(rows_per_request) {
+            let count = rows_per_request.min(rows.end - first_row);
+            let mut data = Vec::with_capacity(count * stride);
+            for row in first_row..first_row + count {
+                let start = row * self.width as usize + x;
+                for pixel in &self.pixels[start..start + width] {
+                    data.extend(match big_endian {
+                        true => pixel.to_be_bytes(),
+                        false => pixel.to_le_bytes(),
+                    });
+                }
+            }
+            connection.put_image(
+                xproto::ImageFormat::Z_PIXMAP,
+                window,
+                self.gc,
+                rect.width as u16,
+                count as u16,
+                rect.x as i16,
+                first_row as i16,
+                0,
+                self.depth,
+                &data,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn release(self, connection: &XCBConnection) {
+        if let Some((seg, address)) = self.shm {
+            let _ = connection.shm_detach(seg);
+            let _ = connection.sync();
+            unsafe {
+                libc::shmdt(address as *const _);
+            }
+        }
+        let _ = connection.free_gc(self.gc);
+    }
+}
+
+/// Contents of a selection owned by the window, by target atom.
+type SelectionContents = Vec<(xproto::Atom, Vec<u8>)>;
+
+/// Selection owned by the window.
+struct OwnedSelection {
+    selection: xproto::Atom,
+    /// Server time at which the window acquired the selection, answered for TIMESTAMP.
+    time: xproto::Timestamp,
+    contents: SelectionContents,
+}
+
+/// Sending of selection contents too large for one property, in chunks written whenever the
+/// requestor deleted the previous one.
+struct IncrTransfer {
+    requestor: xproto::Window,
+    property: xproto::Atom,
+    target: xproto::Atom,
+    data: Vec<u8>,
+    offset: usize,
+    /// Time of the last chunk, transfers abandoned by the requestor are dropped.
+    updated: Instant,
+}
+
+/// How long a selection owner may take to answer, per chunk of INCR transfers.
+const SELECTION_TIMEOUT: Duration = Duration::from_secs(2);
+/// Largest property written at once, larger contents are sent with INCR.
+const MAX_SELECTION_CHUNK: usize = 256 * 1024;
+
+impl RawWindow {
+    pub(crate) fn clipboard_get(
+        &self,
+        selection: Selection,
+        mime_type: &str,
+    ) -> Result<Vec<u8>, ClipboardError> {
+        let selection = self.selection_atom(selection);
+        let target = self.atom(mime_type);
+
+        if let Some(contents) = self.owned_selection(selection) {
+            return contents
+                .into_iter()
+                .find(|(t, _)| *t == target)
+                .map(|(_, data)| data)
+                .ok_or(ClipboardError::NoContent);
+        }
+        self.convert_selection(selection, target)
+    }
+
+    pub(crate) fn clipboard_set(
+        &self,
+        selection: Selection,
+        contents: Vec<(String, Vec<u8>)>,
+    ) -> Result<(), ClipboardError> {
+        let contents = contents
+            .into_iter()
+            .map(|(mime_type, data)| (self.atom(&mime_type), data))
+            .collect();
+        self.set_selection_owner(self.selection_atom(selection), contents)
+    }
+
+    pub(crate) fn clipboard_mime_types(
+        &self,
+        selection: Selection,
+    ) -> Result<Vec<String>, ClipboardError> {
+        let selection = self.selection_atom(selection);
+
+        let targets = match self.owned_selection(selection) {
+            Some(contents) => contents.into_iter().map(|(target, _)| target).collect(),
+            None => self
+                .convert_selection(selection, self.atoms.TARGETS)?
+                .chunks_exact(4)
+                .map(|atom| u32::from_ne_bytes(atom.try_into().unwrap()))
+                .collect::<Vec<xproto::Atom>>(),
+        };
+
+        let meta_targets = [
+            self.atoms.TARGETS,
+            self.atoms.MULTIPLE,
+            self.atoms.TIMESTAMP,
+        ];
+        Ok(targets
+            .into_iter()
+            .filter(|target| !meta_targets.contains(target))
+            .filter_map(|target| atom_name(&self.connection, target))
+            .collect())
+    }
+
+    fn selection_atom(&self, selection: Selection) -> xproto::Atom {
+        match selection {
+            Selection::Clipboard => self.atoms.CLIPBOARD,
+            Selection::Primary => self.atoms.PRIMARY,
+        }
+    }
+
+    /// Returns the contents of a selection if this window owns it.
+    fn owned_selection(&self, selection: xproto::Atom) -> Option<SelectionContents> {
+        let owner = self
+            .connection
+            .get_selection_owner(selection)
+            .ok()?
+            .reply()
+            .ok()?
+            .owner;
+        if owner != self.window {
+            return None;
+        }
+
+        self.selections
+            .borrow()
+            .iter()
+            .find(|owned| owned.selection == selection)
+            .map(|owned| owned.contents.clone())
+    }
+
+    fn set_selection_owner(
+        &self,
+        selection: xproto::Atom,
+        contents: SelectionContents,
+    ) -> Result<(), ClipboardError> {
+        let time = self.server_time();
+        let _ = self
+            .connection
+            .set_selection_owner(self.window, selection, time);
+        let owner = self
+            .connection
+            .get_selection_owner(selection)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .map(|reply| reply.owner);
+        if owner != Some(self.window) {
+            return Err(ClipboardError::Denied);
+        }
+
+        let mut selections = self.selections.borrow_mut();
+        selections.retain(|owned| owned.selection != selection);
+        selections.push(OwnedSelection {
+            selection,
+            time,
+            contents,
+        });
+        Ok(())
+    }
+
+    /// Returns the current server time from the PropertyNotify of an empty append to a
+    /// property, since ICCCM forbids acquiring selections at CurrentTime.
+    fn server_time(&self) -> xproto::Timestamp {
+        let property = self.atoms.GWL_TIMESTAMP;
+        let _ = self.connection.change_property8(
+            xproto::PropMode::APPEND,
+            self.window,
+            property,
+            xproto::AtomEnum::INTEGER,
+            &[],
+        );
+        self.flush();
+
+        match self.wait_selection_event(NONE, property) {
+            Ok(Event::PropertyNotify(notify)) => notify.time,
+            _ => CURRENT_TIME,
+        }
+    }
+
+    /// Asks the selection owner to convert the selection to the target and reads the result,
+    /// including INCR transfers. Other events are kept for the run loop.
+    fn convert_selection(
+        &self,
+        selection: xproto::Atom,
+        target: xproto::Atom,
+    ) -> Result<Vec<u8>, ClipboardError> {
+        let property = self.atoms.GWL_SELECTION;
+
+        let _ = self.connection.delete_property(self.window, property);
+        let _ = self.connection.convert_selection(
+            self.window,
+            selection,
+            target,
+            property,
+            CURRENT_TIME,
+        );
+        self.flush();
+
+        let notify = loop {
+            if let Event::SelectionNotify(notify) =
+                self.wait_selection_event(selection, property)?
+            {
+                break notify;
+            }
+        };
+        if notify.property == NONE {
+            return Err(ClipboardError::NoContent);
+        }
+
+        let reply = self.take_property(property)?;
+        if reply.type_ != self.atoms.INCR {
+            return Ok(reply.value);
+        }
+
+        // Deleting the INCR property asks for the first chunk, an empty chunk ends the transfer
+        let mut data = Vec::new();
+        loop {
+            if let Event::PropertyNotify(_) = self.wait_selection_event(selection, property)? {
+                let chunk = self.take_property(property)?.value;
+                if chunk.is_empty() {
+                    return Ok(data);
+                }
+                data.extend(chunk);
+            }
+        }
+    }
+
+    /// Waits for the SelectionNotify event of the selection or a new value of the property
+    /// on this window. Other events are deferred to the run loop.
+    fn wait_selection_event(
+        &self,
+        selection: xproto::Atom,
+        property: xproto::Atom,
+    ) -> Result<Event, ClipboardError> {
+        let deadline = Instant::now() + SELECTION_TIMEOUT;
+
+        loop {
+            while let Some(event) = self
+                .connection
+                .poll_for_event()
+                .map_err(|error| ClipboardError::Io(error.to_string()))?
+            {
+                match event {
+                    Event::SelectionNotify(notify)
+                        if notify.requestor == self.window && notify.selection == selection =>
+                    {
+                        return Ok(Event::SelectionNotify(notify));
+                    }
+                    Event::PropertyNotify(notify)
+                        if notify.window == self.window
+                            && notify.atom == property
+                            && notify.state == xproto::Property::NEW_VALUE =>
+                    {
+                        return Ok(Event::PropertyNotify(notify));
+                    }
+                    event => self.deferred_events.borrow_mut().push_back(event),
+                }
+            }
+
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                return Err(ClipboardError::Timeout);
+            }
+            let mut fd = libc::pollfd {
+                fd: self.connection.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            unsafe {
+                libc::poll(&mut fd, 1, timeout.as_millis() as i32 + 1);
+            }
+        }
+    }
+
+    /// Reads and deletes a property of this window.
+    fn take_property(
+        &self,
+        property: xproto::Atom,
+    ) -> Result<xproto::GetPropertyReply, ClipboardError> {
+        self.connection
+            .get_property(
+                true,
+                self.window,
+                property,
+                xproto::AtomEnum::ANY,
+                0,
+                u32::MAX,
+            )
+            .map_err(|error| ClipboardError::Io(error.to_string()))?
+            .reply()
+            .map_err(|error| ClipboardError::Io(error.to_string()))
+    }
+
+    /// Answers a request of another client for a selection owned by this window.
+    fn handle_selection_request(&self, request: &xproto::SelectionRequestEvent) {
+        // Obsolete clients leave the property empty and expect the target to be used
+        let property = match request.property {
+            NONE => request.target,
+            property => property,
+        };
+
+        let selections = self.selections.borrow();
+        let owned = selections
+            .iter()
+            .find(|owned| owned.selection == request.selection);
+
+        let converted = match owned {
+            // Pairs of targets and properties, failed conversions get None as property
+            Some(owned) if request.target == self.atoms.MULTIPLE && request.property != NONE => {
+                let mut pairs = self.window_atoms(request.requestor, property);
+                for pair in pairs.chunks_exact_mut(2) {
+                    if !self.convert_owned_selection(owned, request.requestor, pair[0], pair[1]) {
+                        pair[1] = NONE;
+                    }
+                }
+                let _ = self.connection.change_property32(
+                    xproto::PropMode::REPLACE,
+                    request.requestor,
+                    property,
+                    self.atoms.ATOM_PAIR,
+                    &pairs,
+                );
+                true
+            }
+            Some(owned) => {
+                self.convert_owned_selection(owned, request.requestor, request.target, property)
+            }
+            None => false,
+        };
+
+        let notify = xproto::SelectionNotifyEvent {
+            response_type: xproto::SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time: request.time,
+            requestor: request.requestor,
+            selection: request.selection,
+            target: request.target,
+            property: if converted { property } else { NONE },
+        };
+        let _ = self.connection.send_event(
+            false,
+            request.requestor,
+            xproto::EventMask::NO_EVENT,
+            notify,
+        );
+        self.flush();
+    }
+
+    /// Writes an owned selection converted to the target to a property of the requestor,
+    /// returns false when it has no contents for the target.
+    fn convert_owned_selection(
+        &self,
+        owned: &OwnedSelection,
+        requestor: xproto::Window,
+        target: xproto::Atom,
+        property: xproto::Atom,
+    ) -> bool {
+        if property == NONE {
+            return false;
+        }
+
+        if target == self.atoms.TARGETS {
+            let mut targets = vec![
+                self.atoms.TARGETS,
+                self.atoms.MULTIPLE,
+                self.atoms.TIMESTAMP,
+            ];
+            targets.extend(owned.contents.iter().map(|(target, _)| *target));
+            let _ = self.connection.change_property32(
+                xproto::PropMode::REPLACE,
+                requestor,
+                property,
+                xproto::AtomEnum::ATOM,
+                &targets,
+            );
+            return true;
+        }
+        if target == self.atoms.TIMESTAMP {
+            let _ = self.connection.change_property32(
+                xproto::PropMode::REPLACE,
+                requestor,
+                property,
+                xproto::AtomEnum::INTEGER,
+                &[owned.time],
+            );
+            return true;
+        }
+
+        match owned.contents.iter().find(|(t, _)| *t == target) {
+            Some((target, data)) => {
+                self.send_selection_data(requestor, property, *target, data.clone());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Writes selection contents to a property of the requestor, with INCR when they are large.
+    fn send_selection_data(
+        &self,
+        requestor: xproto::Window,
+        property: xproto::Atom,
+        target: xproto::Atom,
+        data: Vec<u8>,
+    ) {
+        if data.len() <= self.max_selection_chunk() {
+            let _ = self.connection.change_property8(
+                xproto::PropMode::REPLACE,
+                requestor,
+                property,
+                target,
+                &data,
+            );
+            return;
+        }
+
+        // The requestor deletes the property to ask for each chunk
+        let aux =
+            xproto::ChangeWindowAttributesAux::new().event_mask(xproto::EventMask::PROPERTY_CHANGE);
+        let _ = self.connection.change_window_attributes(requestor, &aux);
+        let _ = self.connection.change_property32(
+            xproto::PropMode::REPLACE,
+            requestor,
+            property,
+            self.atoms.INCR,
+            &[data.len() as u32],
+        );
+
+        self.drop_abandoned_transfers();
+        self.incr_transfers.borrow_mut().push(IncrTransfer {
+            requestor,
+            property,
+            target,
+            data,
+            offset: 0,
+            updated: Instant::now(),
+        });
+    }
+
+    /// Sends the next chunk of an INCR transfer after the requestor deleted the property.
+    fn continue_incr_transfer(&self, requestor: xproto::Window, property: xproto::Atom) {
+        self.drop_abandoned_transfers();
+
+        let chunk_size = self.max_selection_chunk();
+        let mut transfers = self.incr_transfers.borrow_mut();
+
+        let Some(index) = transfers
+            .iter()
+            .position(|t| t.requestor == requestor && t.property == property)
+        else {
+            return;
+        };
+
+        let transfer = &mut transfers[index];
+        let end = (transfer.offset + chunk_size).min(transfer.data.len());
+        let chunk = &transfer.data[transfer.offset..end];
+        let _ = self.connection.change_property8(
+            xproto::PropMode::REPLACE,
+            requestor,
+            property,
+            transfer.target,
+            chunk,
+        );
+
+        // The empty chunk after the last one ends the transfer
+        if chunk.is_empty() {
+            transfers.remove(index);
+            if !transfers.iter().any(|t| t.requestor == requestor) {
+                let aux = xproto::ChangeWindowAttributesAux::new()
+                    .event_mask(xproto::EventMask::NO_EVENT);
+                let _ = self.connection.change_window_attributes(requestor, &aux);
+            }
+        } else {
+            transfer.offset = end;
+            transfer.updated = Instant::now();
+        }
+        self.flush();
+    }
+
+    /// Forgets the INCR transfers whose requestor did not ask for a chunk within
+    /// SELECTION_TIMEOUT, e.g. because it was closed.
+    fn drop_abandoned_transfers(&self) {
+        let mut transfers = self.incr_transfers.borrow_mut();
+
+        let (abandoned, active): (Vec<_>, Vec<_>) = transfers
+            .drain(..)
+            .partition(|t| t.updated.elapsed() > SELECTION_TIMEOUT);
+        *transfers = active;
+
+        for transfer in abandoned {
+            if !transfers.iter().any(|t| t.requestor == transfer.requestor) {
+                let aux = xproto::ChangeWindowAttributesAux::new()
+                    .event_mask(xproto::EventMask::NO_EVENT);
+                let _ = self
+                    .connection
+                    .change_window_attributes(transfer.requestor, &aux);
+            }
+        }
+    }
+
+    /// Largest property the X server accepts in one request, up to MAX_SELECTION_CHUNK.
+    fn max_selection_chunk(&self) -> usize {
+        // Leaves room for the ChangeProperty request header
+        (self.connection.maximum_request_bytes() - 24).min(MAX_SELECTION_CHUNK)
+    }
+}
+
+/// Drag of another client over the window, announced by XdndEnter.
+struct XdndDrag {
+    source: xproto::Window,
+    /// Files read from the source, None until the uri-list requested on the first XdndPosition
+    /// arrived.
+    paths: Option<Vec<PathBuf>>,
+    /// Time of the XdndPosition the uri-list was requested with.
+    request_time: Option<xproto::Timestamp>,
+    /// Part of the uri-list received so far when the source sends it with INCR.
+    incr_data: Option<Vec<u8>>,
+    position: PhysicalPosition,
+    source_actions: Vec<DropAction>,
+    /// Action accepted by the callback, None while the drop is rejected.
+    action: Option<DropAction>,
+}
+
+impl RawWindow {
+    pub(crate) fn set_drag_action(&self, action: Option<DropAction>) {
+        if let Some(drag) = self.drag.borrow_mut().as_mut() {
+            drag.action = action;
+        }
+    }
+
+    pub(crate) fn drag_source_actions(&self) -> Vec<DropAction> {
+        self.drag
+            .borrow()
+            .as_ref()
+            .map(|drag| drag.source_actions.clone())
+            .unwrap_or_default()
+    }
+
+    /// Handles the XDND client messages of a drag source, delivers the events they correspond to
+    /// and answers the source with the action accepted by the callback.
+    fn handle_xdnd_message<F>(
+        &self,
+        message: &xproto::ClientMessageEvent,
+        callback: &mut F,
+        control_flow: &mut ControlFlow,
+    ) where
+        F: FnMut(WindowEvent, &mut ControlFlow),
+    {
+        let data = message.data.as_data32();
+        let source = data[0];
+
+        if message.type_ == self.atoms.XdndEnter {
+            // Version in the high byte, the first bit is set if XdndTypeList has more types
+            let version = data[1] >> 24;
+            let types = match data[1] & 1 {
+                0 => data[2..5].to_vec(),
+                _ => self.window_atoms(source, self.atoms.XdndTypeList),
+            };
+
+            // Versions before 3 use other messages, only files are taken
+            let files = version >= 3 && types.contains(&self.atoms.TEXT_URI_LIST);
+            *self.drag.borrow_mut() = files.then(|| XdndDrag {
+                source,
+                paths: None,
+                request_time: None,
+                incr_data: None,
+                position: PhysicalPosition::default(),
+                source_actions: Vec::new(),
+                action: None,
+            });
+        } else if message.type_ == self.atoms.XdndPosition {
+            if let Some(event) = self.update_xdnd_drag(&data) {
+                callback(event, control_flow);
+            }
+            self.send_xdnd_status(source);
+        } else if message.type_ == self.atoms.XdndLeave {
+            let drag = self.drag.borrow_mut().take();
+            if drag.is_some_and(|drag| drag.paths.is_some()) {
+                callback(WindowEvent::DragLeft, control_flow);
+            }
+        } else if message.type_ == self.atoms.XdndDrop {
+            let mut finished = [self.window, 0, 0, 0, 0];
+
+            let drag = self.drag.borrow_mut().take();
+            if let Some(XdndDrag {
+                paths: Some(paths),
+                position,
+                action: Some(action),
+                ..
+            }) = drag
+            {
+                callback(WindowEvent::DragDropped { paths, position }, control_flow);
+                // Whether the drop was accepted and the action taken, read since version 5
+                finished[1] = 1;
+                finished[2] = xdnd_action_atom(&self.atoms, action);
+            }
+            self.send_xdnd_message(source, self.atoms.XdndFinished, finished);
+        }
+    }
+
+    /// Moves the drag to the position of an XdndPosition message and returns the event for it.
+    /// The files are read on the first position, as the source only offers them during the drag.
+    fn update_xdnd_drag(&self, data: &[u32; 5]) -> Option<WindowEvent> {
+        let mut drag = self.drag.borrow_mut();
+        let current = drag.as_mut()?;
+
+        // Root coordinates packed as x << 16 | y, the proposed action is in the last item
+        let (x, y) = self.root_position();
+        let position =
+            PhysicalPosition::new((data[2] >> 16) as i32 - x, (data[2] & 0xffff) as i32 - y);
+        current.position = position;
+        current.source_actions = self.xdnd_source_actions(current.source, data[4]);
+
+        if current.paths.is_some() {
+            return Some(WindowEvent::DragMoved { position });
+        }
+
+        if current.request_time.is_none() {
+            let property = self.atoms.GWL_XDND;
+            let _ = self.connection.delete_property(self.window, property);
+            let _ = self.connection.convert_selection(
+                self.window,
+                self.atoms.XdndSelection,
+                self.atoms.TEXT_URI_LIST,
+                property,
+                data[3],
+            );
+            self.flush();
+            current.request_time = Some(data[3]);
+        }
+        None
+    }
+
+    /// Handles the SelectionNotify answering the request for the uri-list of the drag
+    /// and returns DragEntered if it holds the whole list.
+    fn receive_xdnd_selection(&self, notify: &xproto::SelectionNotifyEvent) -> Option<WindowEvent> {
+        let mut drag = self.drag.borrow_mut();
+        // Answers to the requests of earlier drags are dropped
+        let current = drag
+            .as_mut()
+            .filter(|drag| drag.paths.is_none() && drag.request_time == Some(notify.time))?;
+
+        let property = self.atoms.GWL_XDND;
+        let reply = match notify.property == property {
+            true => self.take_property(property).ok(),
+            false => None,
+        };
+        let Some(reply) = reply else {
+            // The source could not convert the selection, the drag is rejected until it leaves
+            *drag = None;
+            return None;
+        };
+
+        if reply.type_ == self.atoms.INCR {
+            // Deleting the INCR property asked for the first chunk
+            current.incr_data = Some(Vec::new());
+            return None;
+        }
+        finish_xdnd_transfer(&mut drag, &reply.value)
+    }
+
+    /// Appends a chunk of an INCR transfer of the uri-list, an empty chunk ends the transfer.
+    fn receive_xdnd_chunk(&self) -> Option<WindowEvent> {
+        let mut drag = self.drag.borrow_mut();
+        // New values written before the SelectionNotify are not part of the transfer
+        let incr_data = drag.as_mut()?.incr_data.as_mut()?;
+
+        let chunk = self.take_property(self.atoms.GWL_XDND).ok()?.value;
+        if !chunk.is_empty() {
+            incr_data.extend(chunk);
+            return None;
+        }
+        let uri_list = std::mem::take(incr_data);
+        finish_xdnd_transfer(&mut drag, &uri_list)
+    }
+
+    /// Delivers the DragEntered of a finished transfer and tells the source whether the callback
+    /// accepted the drop, since the source only asks again when the pointer moves.
+    fn deliver_xdnd_entered<F>(
+        &self,
+        event: Option<WindowEvent>,
+        callback: &mut F,
+        control_flow: &mut ControlFlow,
+    ) where
+        F: FnMut(WindowEvent, &mut ControlFlow),
+    {
+        if let Some(event) = event {
+            callback(event, control_flow);
+            let source = self.drag.borrow().as_ref().map(|drag| drag.source);
+            if let Some(source) = source {
+                self.send_xdnd_status(source);
+            }
+        }
+    }
+
+    /// Returns the action proposed by the source, or the actions it lists for XdndActionAsk.
+    fn xdnd_source_actions(
+        &self,
+        source: xproto::Window,
+        proposed: xproto::Atom,
+    ) -> Vec<DropAction> {
+        let mut actions = vec![proposed];
+        if proposed == self.atoms.XdndActionAsk {
+            actions.extend(self.window_atoms(source, self.atoms.XdndActionList));
+        }
+
+        [DropAction::Copy, DropAction::Move, DropAction::Link]
+            .into_iter()
+            .filter(|action| actions.contains(&xdnd_action_atom(&self.atoms, *action)))
+            .collect()
+    }
+
+    fn send_xdnd_status(&self, source: xproto::Window) {
+        let action = self.drag.borrow().as_ref().and_then(|drag| drag.action);
+
+        // Accepted in the first bit, the second bit asks for positions everywhere in the window
+        let status = match action {
+            Some(_) => 0b11,
+            None => 0b10,
+        };
+        let action = action.map_or(NONE, |action| xdnd_action_atom(&self.atoms, action));
+        self.send_xdnd_message(
+            source,
+            self.atoms.XdndStatus,
+            [self.window, status, 0, 0, action],
+        );
+    }
+
+    /// Sends an XDND client message from this window to the source of a drag.
+    fn send_xdnd_message(
+        &self,
+        source: xproto::Window,
+        message_type: xproto::Atom,
+        data: [u32; 5],
+    ) {
+        let event = xproto::ClientMessageEvent::new(32, source, message_type, data);
+        let _ = self
+            .connection
+            .send_event(false, source, xproto::EventMask::NO_EVENT, event);
+        self.flush();
+    }
+
+    /// Reads an atom list property of another window, e.g. of the source of a drag or the
+    /// ATOM_PAIR list of a MULTIPLE selection request.
+    fn window_atoms(&self, window: xproto::Window, property: xproto::Atom) -> Vec<xproto::Atom> {
+        let reply = self
+            .connection
+            .get_property(false, window, property, xproto::AtomEnum::ANY, 0, u32::MAX)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok());
+        reply
+            .and_then(|reply| Some(reply.value32()?.collect()))
+            .unwrap_or_default()
+    }
+}
+
+/// Takes the files of a received uri-list into the drag and returns DragEntered for them.
+fn finish_xdnd_transfer(drag: &mut Option<XdndDrag>, uri_list: &[u8]) -> Option<WindowEvent> {
+    let paths = parse_uri_list(uri_list);
+    if paths.is_empty() {
+        // Nothing that can be dropped, the drag is rejected until it leaves
+        *drag = None;
+        return None;
+    }
+
+    let current = drag.as_mut()?;
+    current.incr_data = None;
+    current.paths = Some(paths.clone());
+    Some(WindowEvent::DragEntered {
+        paths,
+        position: current.position,
+    })
+}
+
+fn xdnd_action_atom(atoms: &Atoms, action: DropAction) -> xproto::Atom {
+    match action {
+        DropAction::Copy => atoms.XdndActionCopy,
+        DropAction::Move => atoms.XdndActionMove,
+        DropAction::Link => atoms.XdndActionLink,
+    }
+}
+
+/// Highest XDND protocol version supported as a drop target.
+const XDND_VERSION: u32 = 5;
+
+// XEmbed protocol version, _XEMBED_INFO flags and messages
+const XEMBED_VERSION: u32 = 0;
+const XEMBED_MAPPED: u32 = 1 << 0;
+const XEMBED_EMBEDDED_NOTIFY: u32 = 0;
+const XEMBED_WINDOW_ACTIVATE: u32 = 1;
+const XEMBED_WINDOW_DEACTIVATE: u32 = 2;
+const XEMBED_FOCUS_IN: u32 = 4;
+const XEMBED_FOCUS_OUT: u32 = 5;
+
+fn event_mask() -> xproto::EventMask {
+    xproto::EventMask::EXPOSURE
+        | xproto::EventMask::STRUCTURE_NOTIFY
+        | xproto::EventMask::PROPERTY_CHANGE
+}
+
+fn intern_atoms(connection: &XCBConnection) -> Atoms {
+    Atoms::new(connection)
+        .map_err(ReplyError::from)
+        .and_then(|cookie| cookie.reply())
+        .expect("failed to intern the atoms")
+}
+
+fn intern_atom(connection: &XCBConnection, name: &str) -> Option<xproto::Atom> {
+    let reply = connection
+        .intern_atom(false, name.as_bytes())
+        .ok()?
+        .reply()
+        .ok()?;
+    Some(reply.atom)
+}
+
+fn atom_name(connection: &XCBConnection, atom: xproto::Atom) -> Option<String> {
+    let reply = connection.get_atom_name(atom).ok()?.reply().ok()?;
+    Some(String::from_utf8_lossy(&reply.name).into_owned())
+}
+
+/// Returns the index of the Xinerama screen with the geometry of the monitor.
+fn xinerama_index(connection: &XCBConnection, monitor: &MonitorHandle) -> Option<u32> {
+    connection
+        .extension_information(xinerama::X11_EXTENSION_NAME)
+        .ok()??;
+    if connection.xinerama_is_active().ok()?.reply().ok()?.state == 0 {
+        return None;
+    }
+    let screens = connection.xinerama_query_screens().ok()?.reply().ok()?;
+    screens
+        .screen_info
+        .iter()
+        .position(|screen| {
+            (screen.x_org as i32, screen.y_org as i32) == monitor.position
+                && (screen.width as u32, screen.height as u32) == monitor.size
+        })
+        .map(|index| index as u32)
+}
+
+/// Returns the index of the screen whose root window is an ancestor of the window.
+fn screen_of(connection: &XCBConnection, window: xproto::Window) -> usize {
+    let root = connection
+        .get_geometry(window)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .map(|geometry| geometry.root);
+    connection
+        .setup()
+        .roots
+        .iter()
+        .position(|screen| Some(screen.root) == root)
+        .unwrap_or(0)
+}
+
+fn window_visual_id(connection: &XCBConnection, window: xproto::Window) -> xproto::Visualid {
+    connection
+        .get_window_attributes(window)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .map_or(0, |attributes| attributes.visual)
+}
+
+/// Returns the scale factor configured for the whole desktop,
+/// read from Xft/DPI of the XSETTINGS manager or from the Xft.dpi resource.
+fn desktop_scale_factor(connection: &XCBConnection, screen: usize) -> Option<f64> {
+    let valid = |dpi: &f64| dpi.is_finite() && *dpi > 0.0;
+    xsettings_dpi(connection, screen)
+        .filter(valid)
+        .or_else(|| xft_dpi(connection, screen).filter(valid))
+        .map(|dpi| dpi / 96.0)
+}
+
+/// Reads Xft.dpi from RESOURCE_MANAGER, the property Xlib loads its resource database from.
+fn xft_dpi(connection: &XCBConnection, screen: usize) -> Option<f64> {
+    let root = connection.setup().roots[screen].root;
+    let resources = connection
+        .get_property(
+            false,
+            root,
+            xproto::AtomEnum::RESOURCE_MANAGER,
+            xproto::AtomEnum::STRING,
+            0,
+            u32::MAX,
+        )
+        .ok()?
+        .reply()
+        .ok()?;
+
+    String::from_utf8_lossy(&resources.value)
+        .lines()
+        .find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            match key.trim() {
+                "Xft.dpi" => value.trim().parse().ok(),
+                _ => None,
+            }
+        })
+}
+
+fn xsettings_dpi(connection: &XCBConnection, screen: usize) -> Option<f64> {
+    let selection = intern_atom(connection, &format!("_XSETTINGS_S{}", screen))?;
+    let owner = connection
+        .get_selection_owner(selection)
+        .ok()?
+        .reply()
+        .ok()?
+        .owner;
+    if owner == NONE {
+        return None;
+    }
+
+    let property = intern_atom(connection, "_XSETTINGS_SETTINGS")?;
+    let reply = connection
+        .get_property(false, owner, property, property, 0, u32::MAX)
+        .ok()?
+        .reply()
+        .ok()?;
+
+    match reply.format {
+        8 => parse_xsettings_dpi(&reply.value),
+        _ => None,
+    }
+}
+
+/// Collects the modes an output supports and its current mode from the screen resources.
+fn output_modes(
+    connection: &XCBConnection,
+    resources: &randr::GetScreenResourcesCurrentReply,
+    output: randr::Output,
+    bit_depth: u16,
+) -> (Vec<ModeInfo>, Option<ModeInfo>) {
+    let info = connection
+        .randr_get_output_info(output, resources.config_timestamp)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok());
+    let info = match info {
+        Some(info) => info,
+        None => return (Vec::new(), None),
+    };
+
+    let modes = info
+        .modes
+        .iter()
+        .filter_map(|id| resources.modes.iter().find(|mode| mode.id == *id))
+        .map(|mode| {
+            let refresh_rate_millihertz = match mode.htotal as u64 * mode.vtotal as u64 {
+                0 => 0,
+                dots => (mode.dot_clock as u64 * 1000 / dots) as u32,
+            };
+            ModeInfo {
+                id: mode.id as u64,
+                size: (mode.width as u32, mode.height as u32),
+                bit_depth,
+                refresh_rate_millihertz,
+            }
+        })
+        .collect::<Vec<ModeInfo>>();
+
+    let current_mode = match info.crtc {
+        NONE => None,
+        crtc => connection
+            .randr_get_crtc_info(crtc, resources.config_timestamp)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .and_then(|crtc_info| modes.iter().find(|mode| mode.id == crtc_info.mode as u64))
+            .copied(),
+    };
+
+    (modes, current_mode)
+}
+
+/// An EGL context rendering into the window, on the XCB platform of EGL.
+#[cfg(feature = "gl")]
+pub struct RawGlContext {
+    egl: EglContext,
+}
+
+#[cfg(feature = "gl")]
+impl IGlContext for RawGlContext {
+    fn new(window: &RawWindow, attributes: &GlAttributes) -> Result<Self, GlError> {
+        // eglCreateWindowSurface takes the window id itself, not a pointer to it
+        let native_window = window.window as usize as *mut c_void;
+
+        let egl = unsafe {
+            EglContext::new(
+                EGL_PLATFORM_XCB_EXT,
+                window.connection.get_raw_xcb_connection(),
+                attributes,
+                EGL_WINDOW_BIT,
+                |egl, display, config, surface_attributes| {
+                    (egl.create_window_surface)(
+                        display,
+                        config,
+                        native_window,
+                        surface_attributes.as_ptr(),
+                    )
+                },
+            )?
+        };
+
+        Ok(Self { egl })
+    }
+
+    fn make_current(&self) -> Result<(), GlError> {
+        self.egl.make_current()
+    }
+
+    fn swap_buffers(&self) {
+        self.egl.swap_buffers();
+    }
+
+    fn set_swap_interval(&self, interval: i32) {
+        self.egl.set_swap_interval(interval);
+    }
+
+    fn get_proc_address(&self, name: &str) -> *const c_void {
+        self.egl.get_proc_address(name)
+    }
+
+    // The surface of an X11 window follows the size of the window
+    fn resize(&self, _size: PhysicalSize) {}
+
+    fn size(&self) -> PhysicalSize {
+        self.egl.size()
+    }
+}
+
+#[cfg(feature = "vulkan")]
+const VK_STRUCTURE_TYPE_XCB_SURFACE_CREATE_INFO_KHR: i32 = 1000005000;
+
+#[cfg(feature = "vulkan")]
+#[repr(C)]
+struct VkXcbSurfaceCreateInfoKHR {
+    s_type: i32,
+    p_next: *const c_void,
+    flags: u32,
+    connection: *mut c_void,
+    window: xproto::Window,
+}
+
+#[cfg(feature = "vulkan")]
+type VkCreateXcbSurfaceKHR = unsafe extern "system" fn(
+    VkInstance,
+    *const VkXcbSurfaceCreateInfoKHR,
+    *const c_void,
+    *mut VkSurfaceKHR,
+) -> VkResult;
+
+#[cfg(feature = "vulkan")]
+impl RawWindow {
+    pub(crate) fn required_vulkan_extensions() -> &'static [&'static str] {
+        &["VK_KHR_surface", "VK_KHR_xcb_surface"]
+    }
+
+    pub(crate) unsafe fn create_vulkan_surface(
+        &self,
+        instance: VkInstance,
+    ) -> Result<VkSurfaceKHR, VulkanError> {
+        let create_surface: VkCreateXcbSurfaceKHR =
+            std::mem::transmute(instance_proc_address(instance, "vkCreateXcbSurfaceKHR")?);
+        let info = VkXcbSurfaceCreateInfoKHR {
+            s_type: VK_STRUCTURE_TYPE_XCB_SURFACE_CREATE_INFO_KHR,
+            p_next: null(),
+            flags: 0,
+            connection: self.connection.get_raw_xcb_connection(),
+            window: self.window,
+        };
+
+        let mut surface = 0;
+        match create_surface(instance, &info, null(), &mut surface) {
+            VK_SUCCESS => Ok(surface),
+            result => Err(VulkanError::SurfaceCreationFailed(result)),
+        }
+    }
+}
+
+unsafe impl HasRawWindowHandle for RawWindow {
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        let mut handle = raw_window_handle::XcbWindowHandle::empty();
+        handle.window = self.window;
+        handle.visual_id = self.visual_id;
+        RawWindowHandle::Xcb(handle)
+    }
+}
+
+unsafe impl HasRawDisplayHandle for RawWindow {
+    fn raw_display_handle(&self) -> RawDisplayHandle {
+        let mut handle = raw_window_handle::XcbDisplayHandle::empty();
+        handle.connection = self.connection.get_raw_xcb_connection();
+        handle.screen = self.screen as i32;
+        RawDisplayHandle::Xcb(handle)
+    }
+}
@@ -0,0 +1,130 @@
+//! XRandR, Xinerama and MIT-SHM functions of the Xlib backend, loaded from libXrandr,
+//! libXinerama and libXext at runtime so that none of them is needed to build or start an
+//! application. Without them no monitors are reported, fullscreen windows are not pinned to a
+//! monitor and software framebuffers are sent with XPutImage.
+
+use libloading::Library;
+use once_cell::sync::Lazy;
+use std::ffi::{c_char, c_int, c_uint};
+use x11::xinerama::XineramaScreenInfo;
+use x11::xlib::{Bool, Display, Drawable, Status, Time, Visual, Window, XImage, GC};
+use x11::xrandr::{
+    RRCrtc, RRMode, RROutput, Rotation, XRRCrtcInfo, XRRMonitorInfo, XRROutputInfo,
+    XRRScreenResources,
+};
+use x11::xshm::XShmSegmentInfo;
+
+pub(crate) struct XRandr {
+    pub query_extension: unsafe extern "C" fn(*mut Display, *mut c_int, *mut c_int) -> Bool,
+    pub query_version: unsafe extern "C" fn(*mut Display, *mut c_int, *mut c_int) -> Status,
+    pub get_monitors:
+        unsafe extern "C" fn(*mut Display, Window, Bool, *mut c_int) -> *mut XRRMonitorInfo,
+    pub free_monitors: unsafe extern "C" fn(*mut XRRMonitorInfo),
+    pub get_screen_resources_current:
+        unsafe extern "C" fn(*mut Display, Window) -> *mut XRRScreenResources,
+    pub free_screen_resources: unsafe extern "C" fn(*mut XRRScreenResources),
+    pub get_output_info:
+        unsafe extern "C" fn(*mut Display, *mut XRRScreenResources, RROutput) -> *mut XRROutputInfo,
+    pub free_output_info: unsafe extern "C" fn(*mut XRROutputInfo),
+    pub get_crtc_info:
+        unsafe extern "C" fn(*mut Display, *mut XRRScreenResources, RRCrtc) -> *mut XRRCrtcInfo,
+    pub free_crtc_info: unsafe extern "C" fn(*mut XRRCrtcInfo),
+    #[allow(clippy::type_complexity)]
+    pub set_crtc_config: unsafe extern "C" fn(
+        *mut Display,
+        *mut XRRScreenResources,
+        RRCrtc,
+        Time,
+        c_int,
+        c_int,
+        RRMode,
+        Rotation,
+        *mut RROutput,
+        c_int,
+    ) -> Status,
+    _library: Library,
+}
+
+pub(crate) struct Xinerama {
+    pub is_active: unsafe extern "C" fn(*mut Display) -> Bool,
+    pub query_screens: unsafe extern "C" fn(*mut Display, *mut c_int) -> *mut XineramaScreenInfo,
+    _library: Library,
+}
+
+pub(crate) struct XShm {
+    pub query_extension: unsafe extern "C" fn(*mut Display) -> Bool,
+    #[allow(clippy::type_complexity)]
+    pub create_image: unsafe extern "C" fn(
+        *mut Display,
+        *mut Visual,
+        c_uint,
+        c_int,
+        *mut c_char,
+        *mut XShmSegmentInfo,
+        c_uint,
+        c_uint,
+    ) -> *mut XImage,
+    pub attach: unsafe extern "C" fn(*mut Display, *mut XShmSegmentInfo) -> Bool,
+    pub detach: unsafe extern "C" fn(*mut Display, *mut XShmSegmentInfo) -> Bool,
+    #[allow(clippy::type_complexity)]
+    pub put_image: unsafe extern "C" fn(
+        *mut Display,
+        Drawable,
+        GC,
+        *mut XImage,
+        c_int,
+        c_int,
+        c_int,
+        c_int,
+        c_uint,
+        c_uint,
+        Bool,
+    ) -> Bool,
+    _library: Library,
+}
+
+/// libXrandr, None when it is not installed.
+pub(crate) static XRANDR: Lazy<Option<XRandr>> = Lazy::new(|| unsafe {
+    let library = open(&["libXrandr.so.2", "libXrandr.so"])?;
+    Some(XRandr {
+        query_extension: *library.get(b"XRRQueryExtension\0").ok()?,
+        query_version: *library.get(b"XRRQueryVersion\0").ok()?,
+        get_monitors: *library.get(b"XRRGetMonitors\0").ok()?,
+        free_monitors: *library.get(b"XRRFreeMonitors\0").ok()?,
+        get_screen_resources_current: *library.get(b"XRRGetScreenResourcesCurrent\0").ok()?,
+        free_screen_resources: *library.get(b"XRRFreeScreenResources\0").ok()?,
+        get_output_info: *library.get(b"XRRGetOutputInfo\0").ok()?,
+        free_output_info: *library.get(b"XRRFreeOutputInfo\0").ok()?,
+        get_crtc_info: *library.get(b"XRRGetCrtcInfo\0").ok()?,
+        free_crtc_info: *library.get(b"XRRFreeCrtcInfo\0").ok()?,
+        set_crtc_config: *library.get(b"XRRSetCrtcConfig\0").ok()?,
+        _library: library,
+    })
+});
+
+/// libXinerama, None when it is not installed.
+pub(crate) static XINERAMA: Lazy<Option<Xinerama>> = Lazy::new(|| unsafe {
+    let library = open(&["libXinerama.so.1", "libXinerama.so"])?;
+    Some(Xinerama {
+        is_active: *library.get(b"XineramaIsActive\0").ok()?,
+        query_screens: *library.get(b"XineramaQueryScreens\0").ok()?,
+        _library: library,
+    })
+});
+
+/// The MIT-SHM functions of libXext, None when it is not installed.
+pub(crate) static XSHM: Lazy<Option<XShm>> = Lazy::new(|| unsafe {
+    let library = open(&["libXext.so.6", "libXext.so"])?;
+    Some(XShm {
+        query_extension: *library.get(b"XShmQueryExtension\0").ok()?,
+        create_image: *library.get(b"XShmCreateImage\0").ok()?,
+        attach: *library.get(b"XShmAttach\0").ok()?,
+        detach: *library.get(b"XShmDetach\0").ok()?,
+        put_image: *library.get(b"XShmPutImage\0").ok()?,
+        _library: library,
+    })
+});
+
+unsafe fn open(names: &[&str]) -> Option<Library> {
+    names.iter().find_map(|name| Library::new(name).ok())
+}